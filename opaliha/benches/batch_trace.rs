@@ -0,0 +1,45 @@
+//! Scalar against batch tracing of full pupils of the apochromat, `cargo bench --bench batch_trace`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use opaliha::geometry::ray::Ray3;
+use opaliha::optical_system::config::load_sequential_system;
+use opaliha::optical_system::parameters::{FieldData, FieldRaw, FieldType};
+use opaliha::optical_system::pupil_sampling::PupilSampling;
+use opaliha::optical_system::ray_batch::RayBatch;
+use opaliha::optical_system::ray_generation::RaySet;
+
+const REPEATS: usize = 10;
+
+/// Fastest of `REPEATS` runs.
+fn fastest<T>(mut run: impl FnMut() -> T) -> Duration {
+    (0..REPEATS).map(|_| {
+        let start = Instant::now();
+        black_box(run());
+        start.elapsed()
+    }).min().unwrap_or_default()
+}
+
+fn main() {
+    let mut system = load_sequential_system(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/apochromat3.yaml")).unwrap();
+    let field = FieldRaw::new(0., 1.);
+    system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![field]);
+    let wavelength = system.primary_wavelength();
+    let last = system.image_surface().unwrap();
+
+    println!("{:>9} | {:>12} | {:>12} | Speedup", "Rays", "Scalar, ms", "Batch, ms");
+    for size in [32, 128, 512] {
+        let beam = system.generate_beam(&field, wavelength, &RaySet::FullPupil(PupilSampling::Square { size })).unwrap();
+        let rays: Vec<Ray3> = beam.rays.iter().map(|generated| generated.ray).collect();
+        let scalar = fastest(|| rays.iter().map(|&ray| system.trace_ray_to(ray, last)).collect::<Vec<Ray3>>());
+        let batch = fastest(|| {
+            let mut batch = RayBatch::from_rays(&rays).unwrap();
+            system.trace_batch(&mut batch, last);
+            batch
+        });
+        println!(
+            "{:>9} | {:>12.3} | {:>12.3} | {:.2}",
+            rays.len(), scalar.as_secs_f64() * 1e3, batch.as_secs_f64() * 1e3,
+            scalar.as_secs_f64() / batch.as_secs_f64(),
+        );
+    }
+}
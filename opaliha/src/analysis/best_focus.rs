@@ -0,0 +1,259 @@
+use std::fmt;
+use crate::analysis::mtf::MtfMethod;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::pupil_sampling::PupilSampling;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+const GOLDEN_SECTION_ITERATIONS: usize = 60;
+
+/// What the best image plane minimizes or maximizes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FocusCriterion {
+    /// RMS radius of the spot around its centroid, all wavelengths together.
+    RmsSpot(PupilSampling),
+    /// RMS wavefront error of every wavelength, `pupil_samples` across the wavefront map.
+    RmsWavefront { pupil_samples: usize },
+    /// Mean of the tangential and sagittal MTF at the frequency, maximized.
+    Mtf { frequency: f64, method: MtfMethod },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BestFocusSettings {
+    pub criterion: FocusCriterion,
+    /// Row of the field table to focus, all rows weighted by their weights when `None`.
+    pub field: Option<usize>,
+    /// Half-width of the search around the paraxial image of the primary wavelength, from the
+    /// longitudinal aberration and the depth of focus when `None`.
+    pub range: Option<f64>,
+    /// Samples of the coarse scan before the golden-section refinement.
+    pub steps: usize,
+}
+
+/// Image plane found by the search. `defocus` is the shift from the image surface the search
+/// started from, `thickness` the last thickness that puts the image surface there.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BestFocus {
+    pub criterion: FocusCriterion,
+    pub defocus: f64,
+    pub thickness: f64,
+    /// Criterion value in the best plane: mm for spots, waves for wavefronts, modulation for MTF.
+    pub value: f64,
+}
+
+
+impl Default for FocusCriterion {
+    fn default() -> Self {
+        FocusCriterion::RmsSpot(PupilSampling::default())
+    }
+}
+
+
+impl Default for BestFocusSettings {
+    fn default() -> Self {
+        BestFocusSettings { criterion: FocusCriterion::default(), field: None, range: None, steps: 21 }
+    }
+}
+
+
+impl fmt::Display for FocusCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FocusCriterion::RmsSpot(_) => write!(f, "RMS spot radius"),
+            FocusCriterion::RmsWavefront { .. } => write!(f, "RMS wavefront"),
+            FocusCriterion::Mtf { frequency, .. } => write!(f, "MTF at {} cycles/mm", frequency),
+        }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Searches the image plane shift optimizing the criterion: a scan over the range, then a
+    /// golden-section search around its best sample. `None` when no shift gives a value or,
+    /// without a range in the settings, the aperture definition gives no entrance pupil.
+    pub fn best_focus(&self, settings: &BestFocusSettings) -> Option<BestFocus> {
+        let image = self.image_surface()?;
+        let image_z = self.surfaces[image].position().z;
+        let center = self.paraxial_image_position(self.primary_wavelength()) - image_z;
+        let range = settings.range.or_else(|| self.focus_search_range())?;
+        let count = settings.steps.max(3);
+        let merit = |defocus: f64| self.focus_merit(settings, defocus).unwrap_or(f64::INFINITY);
+
+        let scan: Vec<(f64, f64)> = (0..count)
+            .map(|k| center - range + 2. * range * k as f64 / (count - 1) as f64)
+            .map(|defocus| (defocus, merit(defocus)))
+            .collect();
+        let best = (0..count).min_by(|&a, &b| scan[a].1.total_cmp(&scan[b].1))?;
+        if !scan[best].1.is_finite() { return None }
+
+        let (mut low, mut high) = (scan[best.saturating_sub(1)].0, scan[(best + 1).min(count - 1)].0);
+        let ratio = (5f64.sqrt() - 1.) / 2.;
+        let (mut a, mut b) = (high - ratio * (high - low), low + ratio * (high - low));
+        let (mut merit_a, mut merit_b) = (merit(a), merit(b));
+        for _ in 0..GOLDEN_SECTION_ITERATIONS {
+            if merit_a < merit_b {
+                high = b;
+                (b, merit_b) = (a, merit_a);
+                a = high - ratio * (high - low);
+                merit_a = merit(a);
+            } else {
+                low = a;
+                (a, merit_a) = (b, merit_b);
+                b = low + ratio * (high - low);
+                merit_b = merit(b);
+            }
+        }
+        let (defocus, value) = [(a, merit_a), (b, merit_b), scan[best]].into_iter()
+            .min_by(|x, y| x.1.total_cmp(&y.1))?;
+
+        Some(BestFocus {
+            criterion: settings.criterion,
+            defocus,
+            thickness: self.surfaces[image - 1].thickness()? + defocus,
+            value: match settings.criterion {
+                FocusCriterion::Mtf { .. } => -value,
+                _ => value,
+            },
+        })
+    }
+
+    /// Finds the best focus and moves the image surface there by setting the last thickness.
+    /// The thickness is set once, later changes of the system do not move the image again.
+    pub fn focus_image_surface(&mut self, settings: &BestFocusSettings) -> Option<BestFocus> {
+        let focus = self.best_focus(settings)?;
+        let image = self.image_surface()?;
+        self.surfaces[image - 1].set_thickness(focus.thickness);
+        self.update_positions();
+        Some(focus)
+    }
+
+    /// Criterion at the image plane shift, weighted over the selected fields, lower is better.
+    fn focus_merit(&self, settings: &BestFocusSettings, defocus: f64) -> Option<f64> {
+        let rows = &self.parameters.field_data.rows;
+        let fields: Vec<&FieldRaw> = match settings.field {
+            Some(index) => vec![rows.get(index)?],
+            None => rows.iter().collect(),
+        };
+        let (mut sum, mut total) = (0., 0.);
+        for field in fields {
+            let value = match settings.criterion {
+                FocusCriterion::RmsSpot(sampling) => self.defocused_rms_spot(field, sampling, defocus)?.powi(2),
+                FocusCriterion::RmsWavefront { pupil_samples } => {
+                    let (mut square_sum, mut weights) = (0., 0.);
+                    for entry in self.parameters.wavelengths.entries().iter() {
+                        let map = self.defocused_wavefront_map(field, entry.wavelength, pupil_samples, defocus)?;
+                        square_sum += entry.weight * map.rms().powi(2);
+                        weights += entry.weight;
+                    }
+                    square_sum / weights
+                }
+                FocusCriterion::Mtf { frequency, method } => {
+                    let curve = self.method_field_mtf(field, &[frequency], &method, defocus)?;
+                    -(curve.tangential[0] + curve.sagittal[0]) / 2.
+                }
+            };
+            sum += field.weight * value;
+            total += field.weight;
+        }
+        if total == 0. { return None }
+        Some(match settings.criterion {
+            FocusCriterion::Mtf { .. } => sum / total,
+            _ => (sum / total).sqrt(),
+        })
+    }
+
+    /// RMS radius around the weighted centroid of the rays of all wavelengths.
+    fn defocused_rms_spot(&self, field: &FieldRaw, sampling: PupilSampling, defocus: f64) -> Option<f64> {
+        let point = self.field_point(field)?;
+        let pupil = sampling.points();
+        let mut spots = Vec::new();
+        for entry in self.parameters.wavelengths.entries().iter() {
+            for &(px, py) in pupil.iter() {
+                if let Some(ray) = self.defocused_image_ray(field, point, px, py, entry.wavelength, defocus) {
+                    spots.push((ray.origin.x, ray.origin.y, entry.weight));
+                }
+            }
+        }
+        let total: f64 = spots.iter().map(|s| s.2).sum();
+        if total == 0. { return None }
+        let x = spots.iter().map(|s| s.0 * s.2).sum::<f64>() / total;
+        let y = spots.iter().map(|s| s.1 * s.2).sum::<f64>() / total;
+        Some((spots.iter().map(|s| s.2 * ((s.0 - x).powi(2) + (s.1 - y).powi(2))).sum::<f64>() / total).sqrt())
+    }
+
+    /// Spread of the axial foci of all wavelengths and pupil heights plus four depths of focus.
+    fn focus_search_range(&self) -> Option<f64> {
+        let primary = self.primary_wavelength();
+        let aperture = self.image_space_aperture(primary).ok()?;
+        let spread = self.longitudinal_aberration(11).curves.iter()
+            .flat_map(|curve| curve.focus.iter().flatten())
+            .fold(0., |max: f64, focus| max.max(focus.abs()));
+        Some(1.5 * spread + 4. * primary.mm() / (aperture * aperture))
+    }
+}
+
+
+impl fmt::Display for BestFocus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Best focus by {}", self.criterion)?;
+        writeln!(f, "Defocus         : {:.6}", self.defocus)?;
+        writeln!(f, "Last thickness  : {:.6}", self.thickness)?;
+        write!(f, "Criterion value : {:.6}", self.value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::analysis::psf::FftPsfSettings;
+    use crate::database::wavelengths::WavelengthTable;
+    use crate::optical_system::fixtures;
+
+    /// Monochromatic singlet with undercorrected spherical aberration, imaged on axis at 55 mm.
+    fn singlet() -> SequentialOpticalSystem {
+        let mut system = fixtures::with_pupil_and_field(fixtures::singlet(55.), 8., 3.);
+        system.parameters.wavelengths = WavelengthTable::single(system.primary_wavelength());
+        system
+    }
+
+    #[test]
+    fn test_third_order_spherical_foci() {
+        // with spherical aberration alone the RMS wavefront is smallest halfway to the marginal
+        // focus and the RMS spot two thirds of the way
+        let system = singlet();
+        let paraxial = system.paraxial_image_position(system.primary_wavelength()) - system.surfaces[3].position().z;
+        let marginal = system.longitudinal_aberration(11).marginal_spherical(0).unwrap();
+        assert!(marginal < 0.);
+        let focus = |criterion| system.best_focus(&BestFocusSettings { criterion, field: Some(0), ..Default::default() }).unwrap();
+        let wavefront = focus(FocusCriterion::RmsWavefront { pupil_samples: 64 });
+        assert_approx_eq!(wavefront.defocus - paraxial, marginal / 2., 0.05 * marginal.abs());
+        let spot = focus(FocusCriterion::RmsSpot(PupilSampling::Hexapolar { rings: 20 }));
+        assert_approx_eq!(spot.defocus - paraxial, 2. * marginal / 3., 0.05 * marginal.abs());
+        assert_approx_eq!(spot.thickness, 55. + spot.defocus, 1e-12);
+        assert_eq!(spot.to_string().lines().count(), 4);
+    }
+
+    #[test]
+    fn test_focus_image_surface() {
+        let mut system = singlet();
+        let settings = BestFocusSettings {
+            criterion: FocusCriterion::Mtf { frequency: 30., method: MtfMethod::Fft(FftPsfSettings { pupil_samples: 32, grid_size: 64, defocus: 0. }) },
+            steps: 11,
+            ..Default::default()
+        };
+        let paraxial = system.paraxial_image_position(system.primary_wavelength()) - system.surfaces[3].position().z;
+        let marginal = system.longitudinal_aberration(11).marginal_spherical(0).unwrap();
+        let focus = system.focus_image_surface(&settings).unwrap();
+        assert!(focus.value > 0. && focus.value <= 1.);
+        assert!(focus.defocus < paraxial && focus.defocus > paraxial + marginal);
+        assert_approx_eq!(system.surfaces[2].thickness().unwrap(), focus.thickness, 1e-12);
+        assert_approx_eq!(system.surfaces[3].position().z, 4. + focus.thickness, 1e-12);
+        // weighted over both fields the plane moves less than the spherical aberration, and a
+        // second search finds no shift
+        let spot = BestFocusSettings::default();
+        let weighted = system.focus_image_surface(&spot).unwrap();
+        assert!(weighted.defocus.abs() < marginal.abs());
+        assert_approx_eq!(system.best_focus(&spot).unwrap().defocus, 0., 1e-6);
+    }
+}
@@ -0,0 +1,448 @@
+use std::fmt;
+use crate::database::wavelengths::Wavelength;
+use crate::math::power_series::PowerSeries;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Passes of the intersection with the surface, each one fixes two more orders of the depth.
+const INTERSECTION_PASSES: usize = 3;
+
+/// Transverse ray aberration coefficients in the paraxial image plane for normalized pupil
+/// coordinates (ρ, θ), θ measured from the y axis, and normalized field H along y:
+///
+/// εy = σ1 ρ³ cos θ + σ2 ρ² H (2 + cos 2θ) + (3σ3 + σ4) ρ H² cos θ + σ5 H³
+///    + μ1 ρ⁵ cos θ + (μ2 + μ3 cos 2θ) ρ⁴ H + (μ4 + μ6 cos² θ) ρ³ H² cos θ
+///    + (μ7 + μ8 cos 2θ) ρ² H³ + μ10 ρ H⁴ cos θ + μ12 H⁵
+///
+/// εx = σ1 ρ³ sin θ + σ2 ρ² H sin 2θ + (σ3 + σ4) ρ H² sin θ
+///    + μ1 ρ⁵ sin θ + μ3 ρ⁴ H sin 2θ + (μ5 + μ6 cos² θ) ρ³ H² sin θ + μ9 ρ² H³ sin 2θ
+///    + μ11 ρ H⁴ sin θ
+///
+/// The third-order part equals the Seidel sums divided by 2 n'u' of the marginal ray.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct BuchdahlCoefficients {
+    /// σ1..σ5
+    pub third_order: [f64; 5],
+    /// μ1..μ12
+    pub fifth_order: [f64; 12],
+}
+
+/// Third- and fifth-order ray aberrations of an axially symmetric system after Buchdahl. The
+/// contribution of a surface is the change of the image-space aberration when it refracts the
+/// real rays instead of imaging them ideally. Its fifth-order part is intrinsic, found with the
+/// paraxial rays entering the surface, plus the part induced by the third-order aberrations
+/// the rays bring along, so the surface sums add up to the total.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuchdahlAberrations {
+    pub wavelength: Wavelength,
+    /// Entry `i` belongs to surface `i + 1`, up to the last refracting surface.
+    pub surfaces: Vec<BuchdahlCoefficients>,
+    /// Intrinsic part of `surfaces`, the rest of the fifth order is induced.
+    pub intrinsic: Vec<BuchdahlCoefficients>,
+    pub total: BuchdahlCoefficients,
+}
+
+/// Ray crossing the vertex plane of a surface: heights and direction tangents as series in
+/// the normalized pupil coordinates and field (px, py, H).
+#[derive(Debug, Clone, Copy)]
+struct SeriesRay {
+    x: PowerSeries,
+    y: PowerSeries,
+    tx: PowerSeries,
+    ty: PowerSeries,
+}
+
+
+impl BuchdahlCoefficients {
+    /// Aberration (εx, εy) given by the third- and fifth-order terms.
+    pub fn transverse_aberration(&self, px: f64, py: f64, h: f64) -> (f64, f64) {
+        let [s1, s2, s3, s4, s5] = self.third_order;
+        let [m1, m2, m3, m4, m5, m6, m7, m8, m9, m10, m11, m12] = self.fifth_order;
+        let r2 = px * px + py * py;
+        let cos2 = py * py - px * px;
+        let ey = s1 * r2 * py + s2 * (3. * py * py + px * px) * h + (3. * s3 + s4) * py * h * h + s5 * h.powi(3)
+            + m1 * r2 * r2 * py + (m2 * r2 + m3 * cos2) * r2 * h + (m4 * r2 + m6 * py * py) * py * h * h
+            + (m7 * r2 + m8 * cos2) * h.powi(3) + m10 * py * h.powi(4) + m12 * h.powi(5);
+        let ex = s1 * r2 * px + 2. * s2 * px * py * h + (s3 + s4) * px * h * h
+            + m1 * r2 * r2 * px + 2. * m3 * r2 * px * py * h + (m5 * r2 + m6 * py * py) * px * h * h
+            + 2. * m9 * px * py * h.powi(3) + m11 * px * h.powi(4);
+        (ex, ey)
+    }
+
+    /// Reads the coefficients off expansions of εx and εy, `cx` and `cy` give the coefficient
+    /// of px^i py^j H^k.
+    fn from_expansions(cx: impl Fn(usize, usize, usize) -> f64, cy: impl Fn(usize, usize, usize) -> f64) -> BuchdahlCoefficients {
+        let s1 = cy(0, 3, 0);
+        let s2 = cy(2, 0, 1);
+        let s3 = (cy(0, 1, 2) - cx(1, 0, 2)) / 2.;
+        let s4 = cx(1, 0, 2) - s3;
+        let s5 = cy(0, 0, 3);
+
+        let m1 = cy(0, 5, 0);
+        let m2 = (cy(0, 4, 1) + cy(4, 0, 1)) / 2.;
+        let m3 = (cy(0, 4, 1) - cy(4, 0, 1)) / 2.;
+        let m4 = cy(2, 1, 2);
+        let m5 = cx(3, 0, 2);
+        let m6 = cy(0, 3, 2) - m4;
+        let m7 = (cy(0, 2, 3) + cy(2, 0, 3)) / 2.;
+        let m8 = (cy(0, 2, 3) - cy(2, 0, 3)) / 2.;
+        let m9 = cx(1, 1, 3) / 2.;
+        let m10 = cy(0, 1, 4);
+        let m11 = cx(1, 0, 4);
+        let m12 = cy(0, 0, 5);
+        BuchdahlCoefficients {
+            third_order: [s1, s2, s3, s4, s5],
+            fifth_order: [m1, m2, m3, m4, m5, m6, m7, m8, m9, m10, m11, m12],
+        }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Surface sums of the third- and fifth-order coefficients at the primary wavelength with
+    /// paraxial ray aiming. The rays are traced as power series in (px, py, H) truncated above
+    /// the fifth degree, so the coefficients are exact Taylor coefficients of the real rays.
+    /// Conic and aspheric surfaces enter through their fourth- and sixth-order deformations.
+    /// Returns `None` without a refracting surface or a usable entrance pupil.
+    pub fn buchdahl_aberrations(&self) -> Option<BuchdahlAberrations> {
+        let wavelength = self.primary_wavelength();
+        let indices = self.indices_at(wavelength);
+        let curvatures = self.curvatures();
+        let marginal = self.paraxial_marginal_ray(wavelength).ok()?;
+        let last = self.last_refracting_surface();
+        if last == 0 { return None }
+        let image_slope = marginal[last].nu;
+        // ideal image of the ray from its invariant n (u ȳ − u_m y) with the paraxial marginal
+        // ray, it only differs from the paraxial image height by the aberration
+        let aberration = |ray: &SeriesRay, k: usize| {
+            let image = |height: PowerSeries, slope: PowerSeries| {
+                (height * marginal[k].nu - slope * (indices[k] * marginal[k].y)) * (1. / image_slope)
+            };
+            (image(ray.x, ray.tx), image(ray.y, ray.ty))
+        };
+        let coefficients = |(ex, ey): (PowerSeries, PowerSeries)| BuchdahlCoefficients::from_expansions(
+            |i, j, k| ex.coefficient(i, j, k),
+            |i, j, k| ey.coefficient(i, j, k),
+        );
+
+        let mut ray = self.object_space_ray()?;
+        let mut previous = (PowerSeries::constant(0.), PowerSeries::constant(0.));
+        let mut surfaces = Vec::with_capacity(last);
+        let mut intrinsic = Vec::with_capacity(last);
+        for k in 1..=last {
+            let surface = &self.surfaces[k];
+            let refract = |ray: &SeriesRay| ray.refracted(
+                curvatures[k],
+                surface.fourth_order_deformation(),
+                surface.sixth_order_deformation(),
+                indices[k - 1],
+                indices[k],
+            );
+            let refracted = refract(&ray);
+            let current = aberration(&refracted, k);
+            surfaces.push(coefficients((current.0 - previous.0, current.1 - previous.1)));
+            // the ideal image of the paraxial incoming ray is linear, it drops out
+            intrinsic.push(coefficients(aberration(&refract(&ray.paraxial()), k)));
+            previous = current;
+            let distance = self.surfaces.get(k + 1).map_or(0., |next| next.position().z - surface.position().z);
+            ray = refracted.transferred(distance);
+        }
+
+        let mut total = BuchdahlCoefficients::default();
+        for surface in surfaces.iter() {
+            total.third_order.iter_mut().zip(surface.third_order).for_each(|(t, s)| *t += s);
+            total.fifth_order.iter_mut().zip(surface.fifth_order).for_each(|(t, s)| *t += s);
+        }
+        Some(BuchdahlAberrations { wavelength, surfaces, intrinsic, total })
+    }
+
+    /// Object-space rays are straight, their crossing of the first vertex plane is linear in
+    /// (px, py, H) and follows from four launched rays.
+    fn object_space_ray(&self) -> Option<SeriesRay> {
+        let field = self.largest_field_coordinate();
+        let vertex = self.surfaces.get(1)?.position().z;
+        let crossing = |px: f64, py: f64, h: f64| -> Option<[f64; 4]> {
+            let ray = self.pupil_ray(self.field_point_at(0., h * field), px, py).ok()?;
+            let (tx, ty) = (ray.direction.x / ray.direction.z, ray.direction.y / ray.direction.z);
+            let t = vertex - ray.origin.z;
+            Some([ray.origin.x + t * tx, ray.origin.y + t * ty, tx, ty])
+        };
+        let center = crossing(0., 0., 0.)?;
+        let columns = [crossing(1., 0., 0.)?, crossing(0., 1., 0.)?, crossing(0., 0., 1.)?];
+        let series = |component: usize| (0..3).fold(PowerSeries::constant(center[component]), |sum, variable| {
+            sum + PowerSeries::variable(variable) * (columns[variable][component] - center[component])
+        });
+        Some(SeriesRay { x: series(0), y: series(1), tx: series(2), ty: series(3) })
+    }
+}
+
+
+impl SeriesRay {
+    fn paraxial(&self) -> SeriesRay {
+        SeriesRay { x: self.x.truncated(1), y: self.y.truncated(1), tx: self.tx.truncated(1), ty: self.ty.truncated(1) }
+    }
+
+    fn transferred(&self, distance: f64) -> SeriesRay {
+        SeriesRay { x: self.x + self.tx * distance, y: self.y + self.ty * distance, ..*self }
+    }
+
+    /// Refraction from index `n` to `n_next` at the surface with the vertex in this plane and
+    /// the sag c r²/2 + (c³/8 + a4) r⁴ + (c⁵/16 + a6) r⁶, the refracted ray is carried back
+    /// to the vertex plane.
+    fn refracted(&self, curvature: f64, a4: f64, a6: f64, n: f64, n_next: f64) -> SeriesRay {
+        let sag = [curvature / 2., curvature.powi(3) / 8. + a4, curvature.powi(5) / 16. + a6];
+        let point = |z: PowerSeries| (self.x + self.tx * z, self.y + self.ty * z);
+        let mut z = PowerSeries::constant(0.);
+        for _ in 0..INTERSECTION_PASSES {
+            let (x, y) = point(z);
+            let q = x * x + y * y;
+            z = q * ((q * sag[2] + sag[1]) * q + sag[0]);
+        }
+        let (x, y) = point(z);
+        let q = x * x + y * y;
+        // dz/dq, the normal is (−2x dz/dq, −2y dz/dq, 1) oriented along the rays
+        let slope = (q * (3. * sag[2]) + 2. * sag[1]) * q + sag[0];
+        let (gx, gy) = (x * slope * -2., y * slope * -2.);
+        let normal_scale = (gx * gx + gy * gy + 1.).sqrt().recip();
+        let normal = (gx * normal_scale, gy * normal_scale, normal_scale);
+
+        // wave vectors keep their tangential part, n' k' = n k + Γ N
+        let wave_scale = (self.tx * self.tx + self.ty * self.ty + 1.).sqrt().recip() * n;
+        let wave = (self.tx * wave_scale, self.ty * wave_scale, wave_scale);
+        let cosine = wave.0 * normal.0 + wave.1 * normal.1 + wave.2 * normal.2;
+        let gamma = (cosine * cosine + (n_next * n_next - n * n)).sqrt() - cosine;
+        let refracted = (wave.0 + normal.0 * gamma, wave.1 + normal.1 * gamma, wave.2 + normal.2 * gamma);
+        let (tx, ty) = (refracted.0 / refracted.2, refracted.1 / refracted.2);
+        SeriesRay { x: x - z * tx, y: y - z * ty, tx, ty }
+    }
+}
+
+
+impl fmt::Display for BuchdahlAberrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = || self.surfaces.iter().enumerate()
+            .map(|(index, coefficients)| (format!("{:>4}", index + 1), coefficients))
+            .chain([("TOT ".to_string(), &self.total)]);
+        let table = |f: &mut fmt::Formatter<'_>, prefix: &str, values: &dyn Fn(&BuchdahlCoefficients) -> Vec<f64>| {
+            write!(f, "Surf")?;
+            for index in 1..=values(&self.total).len() {
+                write!(f, " | {:>12}", format!("{}{}", prefix, index))?;
+            }
+            writeln!(f)?;
+            for (label, coefficients) in rows() {
+                write!(f, "{}", label)?;
+                for value in values(coefficients) {
+                    write!(f, " | {:12.6e}", value)?;
+                }
+                writeln!(f)?;
+            }
+            Ok(())
+        };
+        writeln!(f, "Buchdahl third-order coefficients at {}", self.wavelength)?;
+        table(f, "SIGMA", &|coefficients| coefficients.third_order.to_vec())?;
+        writeln!(f, "Buchdahl fifth-order coefficients at {}", self.wavelength)?;
+        table(f, "MU", &|coefficients| coefficients.fifth_order.to_vec())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::geometry::ray::RayValidity;
+    use crate::materials::catalog;
+    use crate::materials::material::Air;
+    use crate::math::least_squares::least_squares;
+    use crate::optical_system::parameters::{Aperture, ApertureType, FieldData, FieldRaw, FieldType};
+    use crate::optical_system::surfaces::EvenAsphereSurface;
+    use crate::optical_system::fixtures::surface;
+
+    /// Part of the full pupil and field the real rays of the oracle are sampled in.
+    const SAMPLE_SCALE: f64 = 0.4;
+    const PUPIL_SAMPLES: usize = 11;
+    const FIELD_SAMPLES: usize = 11;
+    /// Highest order of the fitted polynomial, the orders above five absorb the higher terms.
+    const FIT_DEGREE: i32 = 9;
+
+    /// F/2.5 singlet with the stop in front of it and a 10° field.
+    fn fast_singlet() -> SequentialOpticalSystem {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(surface(0., 5., Box::new(Air::default())));
+        system.add_surface(surface(30., 6., Box::new(catalog::glass("N-SK16").unwrap())));
+        system.add_surface(surface(-150., 40., Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system.parameters.stop_surface = 1;
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: 18. };
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., 10.)]);
+        system
+    }
+
+    /// `fast_singlet` with a conic front surface carrying an r⁶ term.
+    fn aspheric_singlet() -> SequentialOpticalSystem {
+        let mut system = fast_singlet();
+        system.surfaces[2] = Box::new(EvenAsphereSurface::new(
+            30., -0.6, vec![0., 2e-9], 6., Box::new(catalog::glass("N-SK16").unwrap()),
+        ));
+        system.update_positions();
+        system
+    }
+
+    /// Aberrations (εx, εy) in the paraxial image plane of the real ray traced up to surface k
+    /// and imaged ideally by the rest of the system, for k from 1 to the last refracting surface.
+    fn ideal_image_aberrations(system: &SequentialOpticalSystem, h: f64, px: f64, py: f64) -> Option<Vec<(f64, f64)>> {
+        let wavelength = system.primary_wavelength();
+        let indices = system.indices_at(wavelength);
+        let marginal = system.paraxial_marginal_ray(wavelength).unwrap();
+        let last = system.last_refracting_surface();
+        let image_slope = marginal[last].nu;
+        let field = h * system.largest_field_coordinate();
+        let ray = system.pupil_ray(system.field_point_at(0., field), px, py).ok()?;
+
+        let chief = system.paraxial_chief_ray(wavelength).unwrap();
+        let lagrange = field * (chief[1].nu * marginal[1].y - marginal[1].nu * chief[1].y);
+        let reference = -lagrange / image_slope;
+
+        (1..=last).map(|k| {
+            let traced = system.trace_ray_to(ray, k);
+            if traced.validity != RayValidity::VALID { return None }
+            let vertex = system.surfaces[k].position().z;
+            let d = traced.direction;
+            let (tx, ty) = (d.x / d.z, d.y / d.z);
+            let x = traced.origin.x + (vertex - traced.origin.z) * tx;
+            let y = traced.origin.y + (vertex - traced.origin.z) * ty;
+            let image = |height: f64, slope: f64| {
+                -(indices[k] * slope * marginal[k].y - marginal[k].nu * height) / image_slope
+            };
+            Some((image(x, tx), image(y, ty) - reference))
+        }).collect()
+    }
+
+    /// Exponents (i, j, k) of px^i py^j H^k in the odd orders up to `FIT_DEGREE`, with i of the
+    /// given parity: odd for εx and even for εy.
+    fn monomials(px_parity: i32) -> Vec<(i32, i32, i32)> {
+        let mut terms = Vec::new();
+        for degree in (1..=FIT_DEGREE).step_by(2) {
+            for i in (px_parity..=degree).step_by(2) {
+                for j in 0..=degree - i {
+                    terms.push((i, j, degree - i - j));
+                }
+            }
+        }
+        terms
+    }
+
+    /// Surface contributions fitted to real rays sampled over a reduced pupil and field.
+    fn fitted_surface_coefficients(system: &SequentialOpticalSystem) -> Vec<BuchdahlCoefficients> {
+        let last = system.last_refracting_surface();
+        let mut samples = Vec::new();
+        let mut contributions: Vec<Vec<(f64, f64)>> = vec![Vec::new(); last];
+        let pupil_step = 2. / (PUPIL_SAMPLES - 1) as f64;
+        let field_step = 2. / (FIELD_SAMPLES - 1) as f64;
+        for f in 0..FIELD_SAMPLES {
+            let h = -1. + f as f64 * field_step;
+            for i in 0..PUPIL_SAMPLES {
+                for j in 0..PUPIL_SAMPLES {
+                    let (px, py) = (-1. + i as f64 * pupil_step, -1. + j as f64 * pupil_step);
+                    if px.hypot(py) > 1. + 1e-9 { continue }
+                    let scaled = (px * SAMPLE_SCALE, py * SAMPLE_SCALE, h * SAMPLE_SCALE);
+                    let aberrations = ideal_image_aberrations(system, scaled.2, scaled.0, scaled.1).unwrap();
+                    let mut previous = (0., 0.);
+                    for (k, current) in aberrations.into_iter().enumerate() {
+                        contributions[k].push((current.0 - previous.0, current.1 - previous.1));
+                        previous = current;
+                    }
+                    samples.push((px, py, h));
+                }
+            }
+        }
+
+        let (x_terms, y_terms) = (monomials(1), monomials(0));
+        let design = |terms: &[(i32, i32, i32)]| -> Vec<Vec<f64>> {
+            samples.iter()
+                .map(|&(px, py, h)| terms.iter().map(|&(i, j, k)| px.powi(i) * py.powi(j) * h.powi(k)).collect())
+                .collect()
+        };
+        let (x_design, y_design) = (design(&x_terms), design(&y_terms));
+        let coefficient = |terms: &[(i32, i32, i32)], solution: &[f64], (i, j, k): (usize, usize, usize)| {
+            let exponents = (i as i32, j as i32, k as i32);
+            let index = terms.iter().position(|&term| term == exponents).unwrap();
+            solution[index] / SAMPLE_SCALE.powi(exponents.0 + exponents.1 + exponents.2)
+        };
+        contributions.iter().map(|values| {
+            let ex: Vec<f64> = values.iter().map(|v| v.0).collect();
+            let ey: Vec<f64> = values.iter().map(|v| v.1).collect();
+            let x_solution = least_squares(&x_design, &ex).unwrap();
+            let y_solution = least_squares(&y_design, &ey).unwrap();
+            BuchdahlCoefficients::from_expansions(
+                |i, j, k| coefficient(&x_terms, &x_solution, (i, j, k)),
+                |i, j, k| coefficient(&y_terms, &y_solution, (i, j, k)),
+            )
+        }).collect()
+    }
+
+    #[test]
+    fn test_third_order_part_matches_seidel() {
+        let system = fast_singlet();
+        let buchdahl = system.buchdahl_aberrations().unwrap();
+        let seidel = system.seidel_aberrations().unwrap();
+        let image_slope = system.paraxial_marginal_ray(seidel.wavelength).unwrap()[3].nu;
+        for (surface, expected) in buchdahl.surfaces.iter().zip(seidel.surfaces.iter()) {
+            let expected = expected.as_array();
+            for (sigma, s) in surface.third_order.iter().zip(expected) {
+                assert_approx_eq!(*sigma, s / (2. * image_slope), 1e-10);
+            }
+        }
+        let sum: f64 = buchdahl.surfaces.iter().map(|s| s.fifth_order[0]).sum();
+        assert_approx_eq!(buchdahl.total.fifth_order[0], sum);
+        assert!(buchdahl.total.fifth_order[0].abs() > 1e-6);
+        assert_eq!(buchdahl.to_string().lines().count(), 2 * (2 + 4));
+    }
+
+    #[test]
+    fn test_surface_sums_match_real_ray_fit() {
+        for system in [fast_singlet(), aspheric_singlet()] {
+            let buchdahl = system.buchdahl_aberrations().unwrap();
+            let fitted = fitted_surface_coefficients(&system);
+            let values = |c: &BuchdahlCoefficients| c.third_order.iter().chain(c.fifth_order.iter()).copied().collect::<Vec<_>>();
+            let scale = buchdahl.surfaces.iter().flat_map(values).fold(0f64, |max, v| max.max(v.abs()));
+            for (analytic, fitted) in buchdahl.surfaces.iter().zip(fitted.iter()) {
+                for (a, b) in values(analytic).iter().zip(values(fitted)) {
+                    assert_approx_eq!(a, b, 1e-5 * scale);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_rear_surface_has_induced_part() {
+        let buchdahl = aspheric_singlet().buchdahl_aberrations().unwrap();
+        let induced = |k: usize| -> Vec<f64> {
+            buchdahl.surfaces[k].third_order.iter().chain(buchdahl.surfaces[k].fifth_order.iter())
+                .zip(buchdahl.intrinsic[k].third_order.iter().chain(buchdahl.intrinsic[k].fifth_order.iter()))
+                .map(|(total, intrinsic)| total - intrinsic)
+                .collect()
+        };
+        // the plane stop does not aberrate, the front surface gets paraxial rays
+        assert!(induced(1).iter().all(|value| value.abs() < 1e-12));
+        // the rear surface sees the aberrations of the front one, in the fifth order only
+        let rear = induced(2);
+        assert!(rear[..5].iter().all(|value| value.abs() < 1e-12));
+        assert!(rear[5..].iter().any(|value| value.abs() > 1e-6));
+    }
+
+    #[test]
+    fn test_fifth_order_predicts_real_rays() {
+        let system = fast_singlet();
+        let total = system.buchdahl_aberrations().unwrap().total;
+        let third_only = BuchdahlCoefficients { fifth_order: [0.; 12], ..total };
+        for (px, py, h) in [(0., 0.3, 0.), (0., -0.25, 0.3), (0.2, 0.15, 0.35), (0.3, 0., 0.2)] {
+            let real = ideal_image_aberrations(&system, h, px, py).unwrap()[2];
+            let predicted = total.transverse_aberration(px, py, h);
+            let third = third_only.transverse_aberration(px, py, h);
+            let error = (real.0 - predicted.0).hypot(real.1 - predicted.1);
+            let third_error = (real.0 - third.0).hypot(real.1 - third.1);
+            assert!(error < 0.05 * third_error, "{} vs {}", error, third_error);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::analysis::spot_diagram::SpotDiagramSettings;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Number of points of the common radius grid in CSV exports and plots.
+const GRID_POINTS: usize = 101;
+
+/// Region the energy is collected in, all centred on the reference point.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum EnergyType {
+    /// Circle of the given radius.
+    #[default]
+    Encircled,
+    /// Square of the given half width.
+    Ensquared,
+    /// Slit along y of the given half width, |x| ≤ r.
+    LineSpreadX,
+    /// Slit along x of the given half width, |y| ≤ r.
+    LineSpreadY,
+}
+
+/// Cumulative energy of one field as a function of the radius (half width for squares and
+/// slits).
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnergyCurve {
+    pub field: FieldRaw,
+    /// Sorted distances of the samples from the reference.
+    pub distances: Vec<f64>,
+    /// Energy fraction within the matching distance.
+    pub fractions: Vec<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnergyAnalysis {
+    pub energy_type: EnergyType,
+    /// Row of the field table of every curve.
+    pub field_indices: Vec<usize>,
+    pub curves: Vec<EnergyCurve>,
+}
+
+
+impl EnergyType {
+    /// Distance of a point from the reference in the metric of the region.
+    pub fn distance(&self, x: f64, y: f64) -> f64 {
+        match self {
+            EnergyType::Encircled => x.hypot(y),
+            EnergyType::Ensquared => x.abs().max(y.abs()),
+            EnergyType::LineSpreadX => x.abs(),
+            EnergyType::LineSpreadY => y.abs(),
+        }
+    }
+}
+
+
+impl fmt::Display for EnergyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnergyType::Encircled => write!(f, "Encircled energy"),
+            EnergyType::Ensquared => write!(f, "Ensquared energy"),
+            EnergyType::LineSpreadX => write!(f, "Line spread energy, x"),
+            EnergyType::LineSpreadY => write!(f, "Line spread energy, y"),
+        }
+    }
+}
+
+
+impl EnergyCurve {
+    /// Curve from weighted samples (x, y, weight) relative to the reference point, the samples
+    /// are rays here and can be PSF pixels as well.
+    pub fn from_samples(field: FieldRaw, samples: &[(f64, f64, f64)], energy_type: EnergyType) -> EnergyCurve {
+        let mut weighted: Vec<(f64, f64)> = samples.iter()
+            .map(|&(x, y, weight)| (energy_type.distance(x, y), weight))
+            .collect();
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total: f64 = weighted.iter().map(|w| w.1).sum();
+        let mut sum = 0.;
+        let fractions = weighted.iter().map(|w| { sum += w.1; sum / total }).collect();
+        EnergyCurve { field, distances: weighted.iter().map(|w| w.0).collect(), fractions }
+    }
+
+    /// Energy fraction within the radius.
+    pub fn fraction_at(&self, radius: f64) -> f64 {
+        let inside = self.distances.partition_point(|&d| d <= radius);
+        if inside == 0 { 0. } else { self.fractions[inside - 1] }
+    }
+
+    /// Smallest radius holding the energy fraction, interpolated linearly between samples.
+    /// `None` for fractions outside (0, 1] or an empty curve.
+    pub fn radius_for(&self, fraction: f64) -> Option<f64> {
+        if fraction <= 0. || fraction > 1. { return None }
+        let index = self.fractions.partition_point(|&f| f < fraction - 1e-12);
+        let distance = *self.distances.get(index)?;
+        if index == 0 { return Some(distance) }
+        let (f0, f1) = (self.fractions[index - 1], self.fractions[index]);
+        let d0 = self.distances[index - 1];
+        Some(d0 + (distance - d0) * (fraction - f0) / (f1 - f0))
+    }
+
+    pub fn max_distance(&self) -> f64 {
+        self.distances.last().copied().unwrap_or(0.)
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Geometric energy curves of every field from the rays of the spot diagram, weighted by the
+    /// wavelength weights. `None` when the spot diagram settings are invalid.
+    pub fn geometric_energy(&self, energy_type: EnergyType, settings: &SpotDiagramSettings) -> Option<EnergyAnalysis> {
+        let weights: Vec<f64> = self.parameters.wavelengths.entries().iter().map(|e| e.weight).collect();
+        let spots = self.spot_diagram(settings)?;
+        let curves = spots.fields.iter().map(|spot| {
+            let samples: Vec<(f64, f64, f64)> = spot.points.iter()
+                .map(|p| (p.x, p.y, weights[p.wavelength]))
+                .collect();
+            EnergyCurve::from_samples(spot.field, &samples, energy_type)
+        }).collect();
+        let field_indices = spots.fields.iter().map(|spot| spot.field_index).collect();
+        Some(EnergyAnalysis { energy_type, field_indices, curves })
+    }
+}
+
+
+impl EnergyAnalysis {
+    /// Radii from zero to the largest sample distance of all fields.
+    pub fn radius_grid(&self) -> Vec<f64> {
+        let largest = self.curves.iter().map(|c| c.max_distance()).fold(0., f64::max);
+        (0..GRID_POINTS).map(|k| largest * k as f64 / (GRID_POINTS - 1) as f64).collect()
+    }
+
+    /// Radius in micrometres and the energy fraction of every field per row.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "radius_um")?;
+        for curve in self.curves.iter() {
+            write!(writer, ",field_{}_{}", curve.field.xfield, curve.field.yfield)?;
+        }
+        writeln!(writer)?;
+        for radius in self.radius_grid() {
+            write!(writer, "{}", radius * 1e3)?;
+            for curve in self.curves.iter() {
+                write!(writer, ",{}", curve.fraction_at(radius))?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (800, 600))
+    }
+}
+
+
+impl Plot for EnergyAnalysis {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let grid = self.radius_grid();
+        let largest = grid.last().copied().unwrap_or(0.).max(1e-9) * 1e3;
+        let mut chart = ChartBuilder::on(root)
+            .caption(self.energy_type.to_string(), ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0f64..largest, 0f64..1.05)?;
+        chart.configure_mesh()
+            .x_desc("Radius, um")
+            .y_desc("Fraction of energy")
+            .draw()?;
+        for (k, (index, curve)) in self.field_indices.iter().zip(self.curves.iter()).enumerate() {
+            let color = Palette99::pick(k).to_rgba();
+            chart.draw_series(LineSeries::new(grid.iter().map(|&r| (r * 1e3, curve.fraction_at(r))), color))?
+                .label(format!("Field {}: {}, {}", index + 1, curve.field.xfield, curve.field.yfield))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 15, y)], color));
+        }
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::LowerRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::parameters::{FieldData, FieldType};
+    use crate::optical_system::fixtures::{constant_glass, front_stop_lens};
+    use crate::optical_system::pupil_sampling::PupilSampling;
+
+    /// Energy on the stop of a system with EPD 10 mm, a uniformly filled disk.
+    fn pupil_energy(energy_type: EnergyType) -> EnergyAnalysis {
+        let mut system = front_stop_lens(constant_glass());
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.)]);
+        let settings = SpotDiagramSettings {
+            sampling: PupilSampling::Dithered { size: 101, seed: 3 },
+            surface: Some(1),
+            ..Default::default()
+        };
+        system.geometric_energy(energy_type, &settings).unwrap()
+    }
+
+    #[test]
+    fn test_uniform_disk() {
+        let encircled = &pupil_energy(EnergyType::Encircled).curves[0];
+        assert_approx_eq!(encircled.fraction_at(5. / 2f64.sqrt()), 0.5, 1e-2);
+        assert_approx_eq!(encircled.radius_for(0.8).unwrap(), 5. * 0.8f64.sqrt(), 2e-2);
+        assert_eq!(encircled.fraction_at(5.1), 1.);
+        assert!(encircled.radius_for(1.2).is_none());
+
+        // square of half width R/√2 inscribed in the disk holds 2/π of it
+        let ensquared = &pupil_energy(EnergyType::Ensquared).curves[0];
+        let corner = 5. / 2f64.sqrt();
+        assert_approx_eq!(ensquared.fraction_at(corner), 2. / std::f64::consts::PI, 1e-2);
+
+        // slit of half width R/2 across a disk of radius R
+        let slit = &pupil_energy(EnergyType::LineSpreadX).curves[0];
+        let expected = 2. / std::f64::consts::PI * (0.5f64.asin() + 0.5 * 0.75f64.sqrt());
+        assert_approx_eq!(slit.fraction_at(2.5), expected, 1e-2);
+    }
+
+    #[test]
+    fn test_csv_and_plot() {
+        let analysis = pupil_energy(EnergyType::Encircled);
+        let directory = std::env::temp_dir();
+        let csv = directory.join("opaliha_encircled.csv");
+        analysis.write_csv(&csv).unwrap();
+        let text = std::fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1 + GRID_POINTS);
+        assert_eq!(lines[0], "radius_um,field_0_0");
+        assert!(lines.last().unwrap().ends_with(",1"));
+        analysis.plot(directory.join("opaliha_encircled.png")).unwrap();
+    }
+}
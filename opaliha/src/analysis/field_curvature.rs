@@ -0,0 +1,299 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::ray::Ray3;
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Normalized pupil offset of the differential rays around the chief ray.
+const DIFFERENTIAL_PUPIL: f64 = 1e-3;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FieldCurvatureSettings {
+    /// Field points from the axis to the largest y field.
+    pub field_samples: usize,
+    pub distortion: DistortionType,
+}
+
+/// Ideal image height the real chief ray is compared with. `k` is the paraxial image height
+/// per unit of tan θ, θ the chief ray angle in object space.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum DistortionType {
+    /// k tan θ, the paraxial image height.
+    #[default]
+    FTanTheta,
+    /// k θ, the reference of scanning and fisheye lenses.
+    FTheta,
+    /// k' tan θ with k' fitted to the real heights by least squares.
+    CalibratedFTanTheta,
+    /// k' θ with k' fitted to the real heights by least squares.
+    CalibratedFTheta,
+}
+
+/// Focus of one wavelength along the field, measured along z from the image surface. `None`
+/// where the rays fail.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FocusCurve {
+    pub wavelength: Wavelength,
+    pub tangential: Vec<Option<f64>>,
+    pub sagittal: Vec<Option<f64>>,
+}
+
+/// Chief ray height of the primary wavelength on the image surface and its ideal value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DistortionPoint {
+    pub real_height: f64,
+    pub reference_height: f64,
+}
+
+/// Field curvature and distortion along the y field, entries follow `fields`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldCurvature {
+    pub distortion_type: DistortionType,
+    /// Y field values in the units of the field table.
+    pub fields: Vec<f64>,
+    pub focus: Vec<FocusCurve>,
+    pub distortion: Vec<Option<DistortionPoint>>,
+}
+
+
+impl Default for FieldCurvatureSettings {
+    fn default() -> Self {
+        FieldCurvatureSettings { field_samples: 21, distortion: DistortionType::default() }
+    }
+}
+
+
+impl fmt::Display for DistortionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistortionType::FTanTheta => write!(f, "F-Tan(Theta)"),
+            DistortionType::FTheta => write!(f, "F-Theta"),
+            DistortionType::CalibratedFTanTheta => write!(f, "Cal. F-Tan(Theta)"),
+            DistortionType::CalibratedFTheta => write!(f, "Cal. F-Theta"),
+        }
+    }
+}
+
+
+impl DistortionPoint {
+    /// Relative height error in percent, zero on the axis.
+    pub fn percent(&self) -> f64 {
+        if self.reference_height == 0. { return 0. }
+        100. * (self.real_height - self.reference_height) / self.reference_height
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Tangential and sagittal focus of every wavelength and the distortion of the primary
+    /// wavelength from the axis to the largest field. Foci are where differential rays next to
+    /// the real chief ray cross in image space.
+    pub fn field_curvature(&self, settings: &FieldCurvatureSettings) -> FieldCurvature {
+        let count = settings.field_samples.max(2);
+        let max_field = self.parameters.field_data.max_field();
+        let fields: Vec<f64> = (0..count).map(|k| max_field * k as f64 / (count - 1) as f64).collect();
+        let points: Vec<Option<FieldPoint>> = fields.iter().map(|&y| self.field_point(&FieldRaw::new(0., y))).collect();
+
+        let focus = self.parameters.wavelengths.wavelengths().into_iter().map(|wavelength| {
+            let crossing = |tangential: bool| points.iter()
+                .map(|point| self.differential_focus((*point)?, wavelength, tangential))
+                .collect();
+            FocusCurve { wavelength, tangential: crossing(true), sagittal: crossing(false) }
+        }).collect();
+
+        // real heights against the object space angle θ of the same aimed chief ray, with k from
+        // the paraxial image height
+        let primary = self.primary_wavelength();
+        let heights: Vec<Option<(f64, f64)>> = points.iter().map(|point| {
+            let point = (*point)?;
+            let ray = self.field_ray_at_surface(&FieldRaw::default(), point, 0., 0., primary, self.image_surface()?)?;
+            let direction = self.aimed_ray(point, 0., 0.).ok()?.direction;
+            Some((direction.y.atan2(direction.z), ray.origin.y))
+        }).collect();
+        let k = self.paraxial_image_height_per_object_unit() * if self.object_is_infinite() {
+            1.
+        } else {
+            self.pupil_reference().map_or(f64::NAN, |reference| self.surfaces[0].position().z - reference.z)
+        };
+        let angle = |theta: f64| match settings.distortion {
+            DistortionType::FTanTheta | DistortionType::CalibratedFTanTheta => theta.tan(),
+            DistortionType::FTheta | DistortionType::CalibratedFTheta => theta,
+        };
+        let scale = match settings.distortion {
+            DistortionType::FTanTheta | DistortionType::FTheta => k,
+            DistortionType::CalibratedFTanTheta | DistortionType::CalibratedFTheta => {
+                let (products, squares) = heights.iter().flatten()
+                    .fold((0., 0.), |(p, s), &(theta, y)| (p + angle(theta) * y, s + angle(theta).powi(2)));
+                if squares == 0. { k } else { products / squares }
+            }
+        };
+        let distortion = heights.iter()
+            .map(|height| height.map(|(theta, y)| DistortionPoint { real_height: y, reference_height: scale * angle(theta) }))
+            .collect();
+
+        FieldCurvature { distortion_type: settings.distortion, fields, focus, distortion }
+    }
+
+    /// Distance along z from the image surface to where the rays at ± the differential pupil
+    /// offset cross, in the meridional section when `tangential`.
+    fn differential_focus(&self, point: FieldPoint, wavelength: Wavelength, tangential: bool) -> Option<f64> {
+        let last = self.image_surface()?;
+        let (px, py) = if tangential { (0., DIFFERENTIAL_PUPIL) } else { (DIFFERENTIAL_PUPIL, 0.) };
+        let trace = |sign: f64| self.field_ray_at_surface(&FieldRaw::default(), point, sign * px, sign * py, wavelength, last);
+        let (upper, lower) = (trace(1.)?, trace(-1.)?);
+        let image_z = self.surfaces[last].position().z;
+        // transverse coordinate at the image plane and its change per unit z
+        let section = |ray: &Ray3| {
+            let (position, slope) = if tangential { (ray.origin.y, ray.direction.y) } else { (ray.origin.x, ray.direction.x) };
+            let at_image = position + slope / ray.direction.z * (image_z - ray.origin.z);
+            (at_image, slope / ray.direction.z)
+        };
+        let ((p1, s1), (p2, s2)) = (section(&upper), section(&lower));
+        if s1 == s2 { return None }
+        Some((p2 - p1) / (s1 - s2))
+    }
+}
+
+
+impl FieldCurvature {
+    /// Largest absolute distortion in percent.
+    pub fn max_distortion(&self) -> f64 {
+        self.distortion.iter().flatten().fold(0., |max, point| f64::max(max, point.percent().abs()))
+    }
+
+    /// Field curvature on the left, distortion on the right, both against the y field.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (1000, 500))
+    }
+}
+
+
+impl Plot for FieldCurvature {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let top = self.fields.last().copied().unwrap_or(0.).max(1e-9);
+        let panels = root.split_evenly((1, 2));
+
+        let extent = self.focus.iter()
+            .flat_map(|curve| curve.tangential.iter().chain(curve.sagittal.iter()).flatten())
+            .fold(0., |max: f64, value| max.max(value.abs()))
+            .max(1e-6) * 1.1;
+        let mut chart = ChartBuilder::on(&panels[0])
+            .caption("Field curvature, T solid, S dashed", ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(-extent..extent, 0f64..top)?;
+        chart.configure_mesh()
+            .x_desc("Focus, mm")
+            .y_desc("Y field")
+            .draw()?;
+        for (index, curve) in self.focus.iter().enumerate() {
+            let color = Palette99::pick(index).to_rgba();
+            let series = |values: &Vec<Option<f64>>| -> Vec<(f64, f64)> {
+                values.iter().zip(self.fields.iter()).filter_map(|(value, &field)| Some(((*value)?, field))).collect()
+            };
+            chart.draw_series(LineSeries::new(series(&curve.tangential), color))?
+                .label(format!("{}", curve.wavelength))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            chart.draw_series(DashedLineSeries::new(series(&curve.sagittal), 6, 4, color.into()))?;
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+
+        let extent = self.max_distortion().max(1e-6) * 1.1;
+        let mut chart = ChartBuilder::on(&panels[1])
+            .caption(format!("Distortion, {}", self.distortion_type), ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(-extent..extent, 0f64..top)?;
+        chart.configure_mesh()
+            .x_desc("Percent")
+            .y_desc("Y field")
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            self.distortion.iter().zip(self.fields.iter()).filter_map(|(point, &field)| Some((point.as_ref()?.percent(), field))),
+            &BLUE,
+        ))?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for FieldCurvature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: Option<f64>| value.map_or(format!("{:>13}", "-"), |v| format!("{:13.6}", v));
+        writeln!(f, "Field curvature, mm")?;
+        write!(f, "  Y field ")?;
+        for curve in self.focus.iter() {
+            write!(f, "| {:>13} | {:>13} ", format!("T {}", curve.wavelength), format!("S {}", curve.wavelength))?;
+        }
+        writeln!(f)?;
+        for (k, field) in self.fields.iter().enumerate() {
+            write!(f, "{:9.4} ", field)?;
+            for curve in self.focus.iter() {
+                write!(f, "| {} | {} ", value(curve.tangential[k]), value(curve.sagittal[k]))?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "Distortion, {}", self.distortion_type)?;
+        writeln!(f, "  Y field |  Real height | Ref. height | Distortion, %")?;
+        for (field, point) in self.fields.iter().zip(self.distortion.iter()) {
+            match point {
+                Some(point) => writeln!(f, "{:9.4} | {:12.6} | {:11.6} | {:10.6}", field, point.real_height, point.reference_height, point.percent())?,
+                None => writeln!(f, "{:9.4} | {:>12} | {:>11} | {:>10}", field, "-", "-", "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::fixtures::{focused_singlet, with_pupil_and_field};
+
+    #[test]
+    fn test_field_curvature_against_seidel() {
+        let system = with_pupil_and_field(focused_singlet(), 8., 1.);
+        let curvature = system.field_curvature(&FieldCurvatureSettings { field_samples: 5, ..Default::default() });
+        let primary = &curvature.focus[system.parameters.wavelengths.primary()];
+        assert_approx_eq!(primary.tangential[0].unwrap(), primary.sagittal[0].unwrap(), 1e-9);
+        assert!(primary.tangential[0].unwrap().abs() < 1e-4);
+        // third order: tangential and sagittal foci go as 3 S_III + S_IV and S_III + S_IV
+        let seidel = system.seidel_aberrations().unwrap().total;
+        let (tangential, sagittal) = (primary.tangential[4].unwrap(), primary.sagittal[4].unwrap());
+        let expected = (3. * seidel.astigmatism + seidel.petzval) / (seidel.astigmatism + seidel.petzval);
+        assert_approx_eq!(tangential / sagittal, expected, 2e-2 * expected.abs());
+        assert_eq!(curvature.to_string().lines().count(), 2 + 5 + 2 + 5);
+        curvature.plot(std::env::temp_dir().join("opaliha_field_curvature.png")).unwrap();
+    }
+
+    #[test]
+    fn test_distortion_references() {
+        let system = with_pupil_and_field(focused_singlet(), 8., 20.);
+        let distortion = |distortion| system.field_curvature(&FieldCurvatureSettings { field_samples: 5, distortion });
+        let tan = distortion(DistortionType::FTanTheta);
+        let theta = distortion(DistortionType::FTheta);
+        assert_eq!(tan.distortion[0].unwrap().percent(), 0.);
+        for (k, field) in tan.fields.iter().enumerate().skip(1) {
+            let angle = field.to_radians();
+            let (d_tan, d_theta) = (tan.distortion[k].unwrap().percent(), theta.distortion[k].unwrap().percent());
+            assert_approx_eq!(1. + d_theta / 100., (1. + d_tan / 100.) * angle.tan() / angle, 1e-9);
+        }
+        let calibrated = distortion(DistortionType::CalibratedFTheta);
+        assert!(calibrated.max_distortion() < theta.max_distortion());
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GridDistortionSettings {
+    /// Grid points along each side.
+    pub size: usize,
+    /// Width over height of the grid, the height spans the largest field.
+    pub aspect_ratio: f64,
+}
+
+/// Image position of one grid point: `ideal` from the paraxial magnification, `real` from the
+/// chief ray of the primary wavelength, `None` when it fails.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GridPoint {
+    pub ideal: (f64, f64),
+    pub real: Option<(f64, f64)>,
+}
+
+/// Real against ideal image of a grid that is rectangular in object coordinates: tangents of
+/// the field angles for an object at infinity, heights otherwise. Points are row-major from
+/// the bottom left corner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GridDistortion {
+    pub size: usize,
+    pub points: Vec<GridPoint>,
+}
+
+
+impl Default for GridDistortionSettings {
+    fn default() -> Self {
+        GridDistortionSettings { size: 11, aspect_ratio: 1. }
+    }
+}
+
+
+impl GridPoint {
+    /// Distance between the real and the ideal position in percent of the ideal radius.
+    pub fn distortion(&self) -> Option<f64> {
+        let (x, y) = self.real?;
+        let radius = self.ideal.0.hypot(self.ideal.1);
+        if radius == 0. { return Some(0.) }
+        Some(100. * (x - self.ideal.0).hypot(y - self.ideal.1) / radius)
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// `None` when the largest field has no object point.
+    pub fn grid_distortion(&self, settings: &GridDistortionSettings) -> Option<GridDistortion> {
+        let size = settings.size.max(2);
+        let top = match self.field_point(&FieldRaw::new(0., self.parameters.field_data.max_field()))? {
+            FieldPoint::Direction(direction) => direction.y / direction.z,
+            FieldPoint::Point(point) => point.y,
+        };
+        let scale = self.paraxial_image_height_per_object_unit();
+        let primary = self.primary_wavelength();
+        let image = self.image_surface();
+        let coordinate = |index: usize| -1. + 2. * index as f64 / (size - 1) as f64;
+
+        let points = (0..size * size).map(|k| {
+            let (cx, cy) = (coordinate(k % size) * top * settings.aspect_ratio, coordinate(k / size) * top);
+            let real = image
+                .and_then(|image| self.field_ray_at_surface(&FieldRaw::default(), self.field_point_at(cx, cy), 0., 0., primary, image))
+                .map(|ray| (ray.origin.x, ray.origin.y));
+            GridPoint { ideal: (scale * cx, scale * cy), real }
+        }).collect();
+        Some(GridDistortion { size, points })
+    }
+}
+
+
+impl GridDistortion {
+    pub fn point(&self, row: usize, column: usize) -> &GridPoint {
+        &self.points[row * self.size + column]
+    }
+
+    /// Largest distortion of the grid points in percent.
+    pub fn max_distortion(&self) -> f64 {
+        self.points.iter().filter_map(|point| point.distortion()).fold(0., f64::max)
+    }
+
+    /// Ideal grid in grey with the real grid drawn over it.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (700, 700))
+    }
+}
+
+
+impl Plot for GridDistortion {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let extent = self.points.iter()
+            .flat_map(|point| [Some(point.ideal), point.real])
+            .flatten()
+            .fold(0., |max: f64, (x, y)| max.max(x.abs()).max(y.abs()))
+            .max(1e-9) * 1.1;
+        let mut chart = ChartBuilder::on(root)
+            .caption(format!("Grid distortion, max {:.4}%", self.max_distortion()), ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(-extent..extent, -extent..extent)?;
+        chart.configure_mesh()
+            .x_desc("X, mm")
+            .y_desc("Y, mm")
+            .draw()?;
+
+        let lines = |real: bool| {
+            let position = |point: &GridPoint| if real { point.real } else { Some(point.ideal) };
+            let mut lines: Vec<Vec<(f64, f64)>> = Vec::new();
+            for k in 0..self.size {
+                lines.push((0..self.size).filter_map(|j| position(self.point(k, j))).collect());
+                lines.push((0..self.size).filter_map(|i| position(self.point(i, k))).collect());
+            }
+            lines
+        };
+        for line in lines(false) {
+            chart.draw_series(LineSeries::new(line, &RGBColor(170, 170, 170)))?;
+        }
+        for line in lines(true) {
+            chart.draw_series(LineSeries::new(line, &BLUE))?;
+        }
+        chart.draw_series(self.points.iter().filter_map(|point| point.real).map(|p| Cross::new(p, 3, BLUE)))?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for GridDistortion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Grid distortion {} x {}, max {:.6}%", self.size, self.size, self.max_distortion())?;
+        writeln!(f, "  i   j |   Ideal X    |   Ideal Y    |    Real X    |    Real Y    | Distortion, %")?;
+        for (k, point) in self.points.iter().enumerate() {
+            write!(f, "{:3} {:3} | {:12.6} | {:12.6} ", k / self.size, k % self.size, point.ideal.0, point.ideal.1)?;
+            match (point.real, point.distortion()) {
+                (Some((x, y)), Some(distortion)) => writeln!(f, "| {:12.6} | {:12.6} | {:10.6}", x, y, distortion)?,
+                _ => writeln!(f, "| {:>12} | {:>12} | {:>10}", "-", "-", "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::analysis::field_curvature::FieldCurvatureSettings;
+    use crate::optical_system::parameters::{FieldData, FieldType};
+    use crate::optical_system::fixtures::{singlet, with_pupil_and_field};
+
+    #[test]
+    fn test_grid_against_field_distortion() {
+        let mut system = with_pupil_and_field(singlet(57.), 8., 15.);
+
+        let grid = system.grid_distortion(&GridDistortionSettings { size: 5, aspect_ratio: 1.5 }).unwrap();
+        assert_eq!(grid.points.len(), 25);
+        assert_eq!(grid.point(2, 2).distortion(), Some(0.));
+        // mirror symmetric about both axes
+        let (corner, opposite) = (grid.point(0, 0).real.unwrap(), grid.point(4, 4).real.unwrap());
+        assert_approx_eq!(corner.0, -opposite.0, 1e-9);
+        assert_approx_eq!(corner.1, -opposite.1, 1e-9);
+        assert_approx_eq!(grid.point(4, 4).ideal.0, 1.5 * grid.point(4, 2).ideal.1, 1e-9);
+        // the top of the center column is the largest field of the field curvature analysis
+        let curvature = system.field_curvature(&FieldCurvatureSettings { field_samples: 3, ..Default::default() });
+        let edge = curvature.distortion[2].unwrap();
+        assert_approx_eq!(grid.point(4, 2).real.unwrap().1, edge.real_height, 1e-9);
+        assert_approx_eq!(grid.point(4, 2).distortion().unwrap(), edge.percent().abs(), 1e-9);
+        assert!(grid.max_distortion() >= grid.point(4, 2).distortion().unwrap());
+        assert_eq!(grid.to_string().lines().count(), 2 + 25);
+        grid.plot(std::env::temp_dir().join("opaliha_grid_distortion.png")).unwrap();
+
+        // object heights have no object point for an object at infinity
+        system.parameters.field_data = FieldData::new(FieldType::ObjectHeight, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., 5.)]);
+        assert!(system.grid_distortion(&GridDistortionSettings::default()).is_none());
+    }
+}
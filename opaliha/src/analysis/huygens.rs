@@ -0,0 +1,273 @@
+use std::f64::consts::PI;
+use std::path::Path;
+use std::thread;
+use ndarray::{Array2, Axis};
+use num::complex::Complex64;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::analysis::mtf::{otf_cut, MtfCurve};
+use crate::analysis::psf::{centered_otf, FftPsf};
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::geometry::point::{distance, Point3};
+use crate::geometry::vector::Vector3;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Square grid of image points, row `i` and column `j` at
+/// `center + x_step (j - size / 2) + y_step (i - size / 2)`. The steps may leave the image
+/// surface, for tilted detectors for instance.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ImageGrid {
+    pub center: Point3,
+    pub x_step: Vector3,
+    pub y_step: Vector3,
+    pub size: usize,
+}
+
+/// PSF from the sum of spherical wavelets leaving the last refracting surface, weighted over
+/// the wavelength table. Intensity is relative to the in-phase sum of the wavelets at the chief
+/// ray image point, per wavelength.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HuygensPsf {
+    pub field: FieldRaw,
+    pub grid: ImageGrid,
+    /// Rows along `y_step`.
+    pub intensity: Array2<f64>,
+}
+
+/// Wavelets of one wavelength: points on the last refracting surface with their optical path.
+struct Wavelets {
+    weight: f64,
+    wavenumber: f64,
+    index: f64,
+    sources: Vec<(Point3, f64)>,
+    /// In-phase amplitude at the chief ray image point.
+    reference: f64,
+}
+
+
+impl ImageGrid {
+    /// Grid in the plane z = const through the center.
+    pub fn new(center: Point3, spacing: f64, size: usize) -> ImageGrid {
+        ImageGrid { center, x_step: Vector3::unit_x() * spacing, y_step: Vector3::unit_y() * spacing, size }
+    }
+
+    /// Same pixels as the central `size` × `size` part of an FFT PSF.
+    pub fn matching(psf: &FftPsf, size: usize) -> ImageGrid {
+        ImageGrid::new(psf.center, psf.spacing, size)
+    }
+
+    /// Grid rotated about its x direction by the angle in radians, y moving towards +z.
+    pub fn tilted_about_x(self, angle: f64) -> ImageGrid {
+        let length = self.y_step.norm();
+        let (sin, cos) = angle.sin_cos();
+        ImageGrid { y_step: Vector3 { x: 0., y: cos * length, z: sin * length }, ..self }
+    }
+
+    pub fn point(&self, row: usize, column: usize) -> Point3 {
+        let half = (self.size / 2) as f64;
+        self.center + self.x_step * (column as f64 - half) + self.y_step * (row as f64 - half)
+    }
+}
+
+
+impl Wavelets {
+    fn amplitude(&self, point: Point3) -> Complex64 {
+        self.sources.iter().map(|&(source, path)| {
+            let r = distance(source, point);
+            Complex64::from_polar(1. / r, self.wavenumber * (path + self.index * r))
+        }).sum()
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Grid of 64 × 64 points at λ / (4 NA) of the primary wavelength centred on the chief ray
+    /// on the image surface, two samples per cycle of the cutoff frequency.
+    pub fn default_image_grid(&self, field: &FieldRaw) -> Option<ImageGrid> {
+        let primary = self.primary_wavelength();
+        let point = self.field_point(field)?;
+        let chief = self.field_ray_at_surface(field, point, 0., 0., primary, self.image_surface()?)?;
+        let spacing = 0.5 / self.diffraction_cutoff(primary)?;
+        Some(ImageGrid::new(chief.origin, spacing, 64))
+    }
+
+    /// Huygens PSF of the field on the grid from `pupil_samples` rays across the pupil,
+    /// evaluated on all available threads. `None` when the chief ray fails.
+    pub fn huygens_psf(&self, field: &FieldRaw, grid: &ImageGrid, pupil_samples: usize) -> Option<HuygensPsf> {
+        let point = self.field_point(field)?;
+        let last = self.last_refracting_surface();
+        let image = self.image_surface()?;
+        let samples = pupil_samples.max(2);
+        let coordinate = |k: usize| -1. + 2. * k as f64 / (samples - 1) as f64;
+        let pupil: Vec<(f64, f64)> = (0..samples * samples)
+            .map(|k| (coordinate(k % samples), coordinate(k / samples)))
+            .filter(|p| p.0.hypot(p.1) <= 1. + 1e-12)
+            .collect();
+
+        let mut wavelets = Vec::new();
+        for entry in self.parameters.wavelengths.entries().iter() {
+            let wavelength = entry.wavelength;
+            let Some(chief) = self.field_ray_at_surface(field, point, 0., 0., wavelength, image) else { continue };
+            let sources: Vec<(Point3, f64)> = pupil.iter()
+                .filter_map(|&(px, py)| self.field_ray_at_surface(field, point, px, py, wavelength, last))
+                .map(|ray| (ray.origin, ray.optical_path))
+                .collect();
+            let reference = sources.iter().map(|&(source, _)| 1. / distance(source, chief.origin)).sum();
+            wavelets.push(Wavelets {
+                weight: entry.weight,
+                wavenumber: 2. * PI / wavelength.mm(),
+                index: self.indices_at(wavelength)[last],
+                sources,
+                reference,
+            });
+        }
+        if wavelets.is_empty() { return None }
+        let total: f64 = wavelets.iter().map(|w| w.weight).sum();
+
+        let mut intensity = Array2::zeros((grid.size, grid.size));
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let rows = grid.size.div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            for (chunk, mut block) in intensity.axis_chunks_iter_mut(Axis(0), rows).enumerate() {
+                let wavelets = &wavelets;
+                scope.spawn(move || {
+                    for ((i, j), value) in block.indexed_iter_mut() {
+                        let point = grid.point(chunk * rows + i, j);
+                        *value = wavelets.iter()
+                            .map(|w| w.weight * w.amplitude(point).norm_sqr() / (w.reference * w.reference))
+                            .sum::<f64>() / total;
+                    }
+                });
+            }
+        });
+
+        Some(HuygensPsf { field: *field, grid: *grid, intensity })
+    }
+}
+
+
+impl HuygensPsf {
+    pub fn peak(&self) -> f64 {
+        self.intensity.iter().fold(0., |max, &value| f64::max(max, value))
+    }
+
+    /// Distance of a row or column from the center along its step.
+    pub fn position(&self, index: usize, step: Vector3) -> f64 {
+        (index as f64 - (self.grid.size / 2) as f64) * step.norm()
+    }
+
+    /// Intensity along the x step through the center.
+    pub fn cross_section_x(&self) -> Vec<(f64, f64)> {
+        let row = self.grid.size / 2;
+        (0..self.grid.size).map(|j| (self.position(j, self.grid.x_step), self.intensity[(row, j)])).collect()
+    }
+
+    /// Intensity along the y step through the center.
+    pub fn cross_section_y(&self) -> Vec<(f64, f64)> {
+        let column = self.grid.size / 2;
+        (0..self.grid.size).map(|i| (self.position(i, self.grid.y_step), self.intensity[(i, column)])).collect()
+    }
+
+    /// Largest difference to an FFT PSF sampled on the same pixels, see `ImageGrid::matching`.
+    /// `None` when the grid is larger than the FFT grid.
+    pub fn max_difference(&self, fft: &FftPsf) -> Option<f64> {
+        let offset = (fft.size() / 2).checked_sub(self.grid.size / 2)?;
+        if offset + self.grid.size > fft.size() { return None }
+        Some(self.intensity.indexed_iter()
+            .map(|((i, j), value)| (value - fft.intensity[(offset + i, offset + j)]).abs())
+            .fold(0., f64::max))
+    }
+
+    /// MTF from the FFT of the grid, tangential along the y step and sagittal along the x step.
+    /// The grid is zero-padded to a power of two, frequencies beyond its Nyquist limit give zero.
+    pub fn mtf(&self, frequencies: &[f64]) -> MtfCurve {
+        let otf = centered_otf(&self.intensity);
+        let size = otf.nrows() as f64;
+        let cut = |step: Vector3, tangential: bool| frequencies.iter()
+            .map(|&frequency| otf_cut(&otf, 1. / (size * step.norm()), frequency, tangential).norm())
+            .collect();
+        MtfCurve {
+            field: self.field,
+            tangential: cut(self.grid.y_step, true),
+            sagittal: cut(self.grid.x_step, false),
+        }
+    }
+
+    /// Cross-sections along both grid directions in micrometres.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (1000, 450))
+    }
+}
+
+
+impl Plot for HuygensPsf {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let top = self.peak().max(1e-12) * 1.05;
+        let panels = root.split_evenly((1, 2));
+        for (panel, (name, section)) in panels.iter().zip([("X", self.cross_section_x()), ("Y", self.cross_section_y())]) {
+            let left = section.first().map_or(-1., |p| p.0) * 1e3;
+            let right = section.last().map_or(1., |p| p.0) * 1e3;
+            let caption = format!("Huygens PSF {}, field {}, {}", name, self.field.xfield, self.field.yfield);
+            let mut chart = ChartBuilder::on(panel)
+                .caption(caption, ("sans-serif", 18))
+                .margin(10)
+                .x_label_area_size(40)
+                .y_label_area_size(50)
+                .build_cartesian_2d(left..right.max(left + 1e-9), 0f64..top)?;
+            chart.configure_mesh()
+                .x_desc(format!("{}, um", name))
+                .y_desc("Relative intensity")
+                .draw()?;
+            chart.draw_series(LineSeries::new(section.iter().map(|&(position, value)| (position * 1e3, value)), &BLUE))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::analysis::psf::FftPsfSettings;
+    use crate::optical_system::fixtures::{focused_singlet, with_pupil_and_field};
+
+    #[test]
+    fn test_matches_fft() {
+        let system = with_pupil_and_field(focused_singlet(), 3., 5.);
+        let field = FieldRaw::new(0., 5.);
+        let fft = system.fft_psf(&field, system.primary_wavelength(), &FftPsfSettings { pupil_samples: 32, grid_size: 128, defocus: 0. }).unwrap();
+        let grid = ImageGrid::matching(&fft, 32);
+        let huygens = system.huygens_psf(&field, &grid, 32).unwrap();
+        assert_approx_eq!(huygens.peak(), fft.peak(), 2e-2);
+        assert!(huygens.max_difference(&fft).unwrap() < 3e-2);
+
+        let frequencies = [0., 10., 20.];
+        let mtf = huygens.mtf(&frequencies);
+        let otf = fft.otf();
+        for (k, &frequency) in frequencies.iter().enumerate() {
+            assert_approx_eq!(mtf.sagittal[k], otf_cut(&otf, fft.frequency_spacing(), frequency, false).norm(), 5e-2);
+        }
+        huygens.plot(std::env::temp_dir().join("opaliha_huygens_psf.png")).unwrap();
+    }
+
+    #[test]
+    fn test_tilted_detector() {
+        let system = with_pupil_and_field(focused_singlet(), 3., 5.);
+        let field = FieldRaw::new(0., 0.);
+        let mut grid = system.default_image_grid(&field).unwrap();
+        grid.size = 16;
+        let flat = system.huygens_psf(&field, &grid, 24).unwrap();
+        let tilted = system.huygens_psf(&field, &grid.tilted_about_x(0.5), 24).unwrap();
+        assert_approx_eq!(tilted.intensity[(8, 8)], flat.intensity[(8, 8)], 1e-12);
+        for (a, b) in flat.cross_section_x().iter().zip(tilted.cross_section_x().iter()) {
+            assert_approx_eq!(a.1, b.1, 1e-12);
+        }
+        // the defocused rows of the tilted detector spread the light
+        assert!(tilted.intensity[(4, 8)] > flat.intensity[(4, 8)]);
+    }
+}
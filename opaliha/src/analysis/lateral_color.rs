@@ -0,0 +1,176 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Chief ray height of one wavelength minus that of the primary wavelength on the image
+/// surface, `None` where either fails.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LateralColorCurve {
+    pub wavelength: Wavelength,
+    pub errors: Vec<Option<f64>>,
+}
+
+/// Lateral colour along the y field, entries follow `fields`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LateralColor {
+    /// Y field values in the units of the field table.
+    pub fields: Vec<f64>,
+    pub curves: Vec<LateralColorCurve>,
+    /// Airy radius of the primary wavelength, the scale lateral colour is judged against.
+    /// `None` when the aperture definition gives no entrance pupil.
+    pub airy_radius: Option<f64>,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Lateral colour at `field_samples` points from the axis to the largest y field.
+    pub fn lateral_color(&self, field_samples: usize) -> LateralColor {
+        let count = field_samples.max(2);
+        let max_field = self.parameters.field_data.max_field();
+        let fields: Vec<f64> = (0..count).map(|k| max_field * k as f64 / (count - 1) as f64).collect();
+        let height = |y: f64, wavelength: Wavelength| {
+            let point = self.field_point(&FieldRaw::new(0., y))?;
+            Some(self.field_ray_at_surface(&FieldRaw::default(), point, 0., 0., wavelength, self.image_surface()?)?.origin.y)
+        };
+        let primary = self.primary_wavelength();
+        let references: Vec<Option<f64>> = fields.iter().map(|&y| height(y, primary)).collect();
+
+        let curves = self.parameters.wavelengths.wavelengths().into_iter().map(|wavelength| LateralColorCurve {
+            wavelength,
+            errors: fields.iter().zip(references.iter())
+                .map(|(&y, reference)| Some(height(y, wavelength)? - (*reference)?))
+                .collect(),
+        }).collect();
+
+        let airy_radius = self.image_space_aperture(primary).ok().map(|aperture| 0.61 * primary.mm() / aperture);
+        LateralColor { fields, curves, airy_radius }
+    }
+}
+
+
+impl LateralColor {
+    /// Spread of the chief rays of all wavelengths at every field.
+    pub fn spread(&self) -> Vec<Option<f64>> {
+        (0..self.fields.len()).map(|k| {
+            let errors: Option<Vec<f64>> = self.curves.iter().map(|curve| curve.errors[k]).collect();
+            let errors = errors?;
+            let low = errors.iter().copied().fold(f64::INFINITY, f64::min);
+            let high = errors.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            Some(high - low)
+        }).collect()
+    }
+
+    /// Largest spread over the field.
+    pub fn max_lateral_color(&self) -> f64 {
+        self.spread().into_iter().flatten().fold(0., f64::max)
+    }
+
+    /// Errors in micrometres against the y field with the Airy radius drawn dashed.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (600, 600))
+    }
+}
+
+
+impl Plot for LateralColor {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let top = self.fields.last().copied().unwrap_or(0.).max(1e-9);
+        let extent = self.curves.iter()
+            .flat_map(|curve| curve.errors.iter().flatten())
+            .fold(self.airy_radius.unwrap_or(0.), |max: f64, value| max.max(value.abs())).max(1e-9) * 1e3 * 1.1;
+        let mut chart = ChartBuilder::on(root)
+            .caption("Lateral color", ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(-extent..extent, 0f64..top)?;
+        chart.configure_mesh()
+            .x_desc("Error, um")
+            .y_desc("Y field")
+            .draw()?;
+        for (index, curve) in self.curves.iter().enumerate() {
+            let color = Palette99::pick(index).to_rgba();
+            chart.draw_series(LineSeries::new(
+                curve.errors.iter().zip(self.fields.iter()).filter_map(|(error, &y)| Some(((*error)? * 1e3, y))),
+                color,
+            ))?
+                .label(format!("{}", curve.wavelength))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        if let Some(radius) = self.airy_radius {
+            for side in [-radius * 1e3, radius * 1e3] {
+                chart.draw_series(DashedLineSeries::new(vec![(side, 0.), (side, top)], 6, 4, BLACK.into()))?;
+            }
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for LateralColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.airy_radius {
+            Some(radius) => writeln!(f, "Lateral color, um, Airy radius {:.4}", radius * 1e3)?,
+            None => writeln!(f, "Lateral color, um")?,
+        }
+        write!(f, "  Y field ")?;
+        for curve in self.curves.iter() {
+            write!(f, "| {:>11} ", format!("{}", curve.wavelength))?;
+        }
+        writeln!(f, "| Spread")?;
+        for ((k, y), spread) in self.fields.iter().enumerate().zip(self.spread()) {
+            write!(f, "{:9.4} ", y)?;
+            for curve in self.curves.iter() {
+                match curve.errors[k] {
+                    Some(error) => write!(f, "| {:11.4} ", error * 1e3)?,
+                    None => write!(f, "| {:>11} ", "-")?,
+                }
+            }
+            match spread {
+                Some(spread) => writeln!(f, "| {:.4}", spread * 1e3)?,
+                None => writeln!(f, "| -")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::config::load_sequential_system;
+    use crate::optical_system::parameters::{FieldData, FieldType};
+
+    #[test]
+    fn test_apochromat_lateral_color() {
+        let mut system = load_sequential_system(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/apochromat3.yaml")).unwrap();
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., 1.)]);
+        let color = system.lateral_color(5);
+        let primary = system.parameters.wavelengths.primary();
+        assert!(color.curves[primary].errors.iter().all(|error| *error == Some(0.)));
+        assert!(color.curves.iter().all(|curve| curve.errors[0] == Some(0.)));
+        // blue and red chief rays land on opposite sides of the primary one
+        let (blue, red) = (color.curves[0].errors[4].unwrap(), color.curves[5].errors[4].unwrap());
+        assert!(blue * red < 0.);
+        assert_eq!(color.max_lateral_color(), color.spread()[4].unwrap());
+        // first order lateral colour grows linearly with the field and stays inside the Airy disk
+        assert_approx_eq!(color.spread()[4].unwrap(), 4. * color.spread()[1].unwrap(), 1e-2 * color.max_lateral_color());
+        assert!(color.max_lateral_color() < color.airy_radius.unwrap());
+        assert_eq!(color.to_string().lines().count(), 2 + 5);
+        color.plot(std::env::temp_dir().join("opaliha_lateral_color.png")).unwrap();
+    }
+}
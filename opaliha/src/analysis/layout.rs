@@ -0,0 +1,184 @@
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::geometry::point::Point3;
+use crate::geometry::ray::Ray3;
+use crate::geometry::vector::Vector3;
+use crate::optical_system::ray_generation::RaySet;
+use crate::optical_system::ray_path::RayPath;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Points of the profile drawn for each surface.
+const PROFILE_POINTS: usize = 41;
+
+/// Section of a surface in the y-z plane.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SurfaceProfile {
+    pub surface: usize,
+    /// (z, y) from the bottom to the top of the clear aperture.
+    pub points: Vec<(f64, f64)>,
+    /// Glass follows the surface, the profile is joined to the next one at the edges.
+    pub solid_after: bool,
+}
+
+/// Meridional section of the system with ray paths of the primary wavelength.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Layout {
+    pub profiles: Vec<SurfaceProfile>,
+    /// Row of the field table and the path of each ray.
+    pub rays: Vec<(usize, RayPath)>,
+    /// Rays from an object at infinity are drawn from `lead_in` in front of the first surface
+    /// they hit.
+    pub infinite_object: bool,
+    pub lead_in: f64,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Meridional fans of `rays_per_field` rays for every field traced to the image surface,
+    /// with surfaces drawn over their clear apertures or, when not set, over the ray heights.
+    /// `None` for a system without an image surface.
+    pub fn layout(&self, rays_per_field: usize) -> Option<Layout> {
+        let last = self.image_surface()?;
+        let set = RaySet::MeridionalFan { rays: rays_per_field };
+        let primary = self.primary_wavelength();
+        let rays: Vec<(usize, RayPath)> = self.parameters.field_data.rows.iter().enumerate()
+            .filter_map(|(index, field)| Some((index, self.generate_beam(field, primary, &set)?)))
+            .flat_map(|(index, beam)| beam.rays.into_iter().map(move |generated| (index, generated.ray)))
+            .map(|(index, ray)| (index, self.trace_path(ray, last)))
+            .collect();
+
+        let indices = self.indices_at(primary);
+        let profiles = (1..self.surfaces.len()).map(|k| {
+            let surface = &self.surfaces[k];
+            let height = surface.semi_diameter().unwrap_or_else(|| {
+                rays.iter()
+                    .filter_map(|(_, path)| path.record(k))
+                    .fold(0., |max: f64, record| max.max(record.point.x.hypot(record.point.y)))
+                    * 1.05
+            });
+            let vertex = surface.position();
+            let points = (0..PROFILE_POINTS).filter_map(|j| {
+                let y = height * (-1. + 2. * j as f64 / (PROFILE_POINTS - 1) as f64);
+                let probe = Ray3::new(Point3 { x: vertex.x, y: vertex.y + y, z: vertex.z - 2. * height - 1. }, Vector3::unit_z());
+                surface.intersect(&probe).map(|hit| (hit.point.z, hit.point.y))
+            }).collect();
+            // gases differ from 1 by a few 1e-4
+            let solid_after = k < last && (indices[k] - 1.).abs() > 1e-3;
+            SurfaceProfile { surface: k, points, solid_after }
+        }).collect();
+
+        let length = self.surfaces[last].position().z - self.surfaces.get(1).map_or(0., |s| s.position().z);
+        Some(Layout { profiles, rays, infinite_object: self.object_is_infinite(), lead_in: 0.2 * length.abs().max(1.) })
+    }
+}
+
+
+impl Layout {
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (1200, 600))
+    }
+
+    /// Polyline of a ray path in (z, y), starting at the object point or, for an object at
+    /// infinity, `lead_in` in front of the first surface.
+    fn polyline(&self, path: &RayPath) -> Vec<(f64, f64)> {
+        let mut points = Vec::with_capacity(path.records.len() + 1);
+        match (self.infinite_object, path.records.first()) {
+            (true, Some(first)) => {
+                let start = first.point + first.incoming_direction * (-self.lead_in / first.incoming_direction.z.max(1e-9));
+                points.push((start.z, start.y));
+            }
+            _ => points.push((path.start.origin.z, path.start.origin.y)),
+        }
+        points.extend(path.records.iter().map(|record| (record.point.z, record.point.y)));
+        points
+    }
+}
+
+
+impl Plot for Layout {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let lines: Vec<(usize, Vec<(f64, f64)>)> = self.rays.iter().map(|(field, path)| (*field, self.polyline(path))).collect();
+        let all = lines.iter().flat_map(|(_, line)| line.iter())
+            .chain(self.profiles.iter().flat_map(|profile| profile.points.iter()));
+        let (mut left, mut right, mut height) = (f64::INFINITY, f64::NEG_INFINITY, 0f64);
+        for &(z, y) in all {
+            left = left.min(z);
+            right = right.max(z);
+            height = height.max(y.abs());
+        }
+        if !left.is_finite() { (left, right) = (0., 1.) }
+        let margin = 0.05 * (right - left).max(1e-9);
+        let height = height.max(1e-9) * 1.1;
+
+        let mut chart = ChartBuilder::on(root)
+            .caption("Layout", ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(left - margin..right + margin, -height..height)?;
+        chart.configure_mesh()
+            .disable_mesh()
+            .x_desc("Z, mm")
+            .y_desc("Y, mm")
+            .draw()?;
+        for (field, line) in lines {
+            chart.draw_series(LineSeries::new(line, Palette99::pick(field).to_rgba().stroke_width(1)))?;
+        }
+        for (index, profile) in self.profiles.iter().enumerate() {
+            chart.draw_series(LineSeries::new(profile.points.clone(), BLACK.stroke_width(2)))?;
+            let next = self.profiles.get(index + 1).filter(|_| profile.solid_after);
+            if let (Some(next), Some(&bottom), Some(&top)) = (next, profile.points.first(), profile.points.last()) {
+                if let (Some(&next_bottom), Some(&next_top)) = (next.points.first(), next.points.last()) {
+                    chart.draw_series(LineSeries::new(vec![bottom, next_bottom], BLACK.stroke_width(2)))?;
+                    chart.draw_series(LineSeries::new(vec![top, next_top], BLACK.stroke_width(2)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::dispersion::Dispersion;
+    use crate::materials::glass::Glass;
+    use crate::optical_system::fixtures;
+
+    #[test]
+    fn test_singlet_layout() {
+        let system = fixtures::visible(fixtures::singlet(57.), 10., 5.);
+
+        let layout = system.layout(5).unwrap();
+        assert_eq!(layout.rays.len(), 2 * 5);
+        assert!(layout.infinite_object);
+        assert_eq!(layout.profiles.iter().map(|profile| profile.solid_after).collect::<Vec<_>>(), vec![true, false, false]);
+        let front = &layout.profiles[0];
+        assert_eq!(front.points.len(), PROFILE_POINTS);
+        // the vertex sits in the middle, the convex edges behind it
+        let middle = front.points[PROFILE_POINTS / 2];
+        assert_approx_eq!(middle.0, 0., 1e-9);
+        assert!(front.points[0].0 > 0.);
+        assert_approx_eq!(front.points[0].1, -front.points[PROFILE_POINTS - 1].1, 1e-9);
+        // without clear apertures the surfaces cover the rays
+        assert!(front.points[PROFILE_POINTS - 1].1 > 5.);
+        layout.plot(std::env::temp_dir().join("opaliha_layout.png")).unwrap();
+    }
+
+    #[test]
+    fn test_solid_from_index() {
+        let solid = |material| {
+            let layout = fixtures::visible(fixtures::lens(material, 57.), 10., 5.).layout(3).unwrap();
+            layout.profiles[0].solid_after
+        };
+        assert!(solid(Box::new(Glass::new("air", Dispersion::Constant(1.5)))));
+        assert!(!solid(Box::new(Glass::new("N-BK7", Dispersion::Constant(1.)))));
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Axial focus of one wavelength against the pupil height, `None` where the ray fails.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LongitudinalCurve {
+    pub wavelength: Wavelength,
+    pub focus: Vec<Option<f64>>,
+}
+
+/// Where on-axis rays of the y pupil axis cross the axis, along z from the paraxial image of
+/// the primary wavelength. The zero pupil height holds the paraxial focus of the wavelength.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LongitudinalAberration {
+    pub pupil: Vec<f64>,
+    pub curves: Vec<LongitudinalCurve>,
+}
+
+/// Paraxial image position against wavelength, along z from the paraxial image of the
+/// reference wavelength.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChromaticFocalShift {
+    pub reference: Wavelength,
+    pub wavelengths: Vec<Wavelength>,
+    pub shifts: Vec<f64>,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Longitudinal aberration of every wavelength at `pupil_samples` heights from the axis to
+    /// the edge of the pupil.
+    pub fn longitudinal_aberration(&self, pupil_samples: usize) -> LongitudinalAberration {
+        let count = pupil_samples.max(2);
+        let pupil: Vec<f64> = (0..count).map(|k| k as f64 / (count - 1) as f64).collect();
+        let reference = self.paraxial_image_position(self.primary_wavelength());
+        let last = self.last_refracting_surface();
+        let field = FieldRaw::default();
+        let point = self.field_point(&field);
+
+        let curves = self.parameters.wavelengths.wavelengths().into_iter().map(|wavelength| {
+            let focus = pupil.iter().map(|&py| {
+                if py == 0. { return Some(self.paraxial_image_position(wavelength) - reference) }
+                let ray = self.field_ray_at_surface(&field, point?, 0., py, wavelength, last)?;
+                if ray.direction.y == 0. { return None }
+                Some(ray.origin.z - ray.origin.y * ray.direction.z / ray.direction.y - reference)
+            }).collect();
+            LongitudinalCurve { wavelength, focus }
+        }).collect();
+        LongitudinalAberration { pupil, curves }
+    }
+
+    /// Chromatic focal shift at `samples` wavelengths across the band of the wavelength table,
+    /// referred to the primary wavelength.
+    pub fn chromatic_focal_shift(&self, samples: usize) -> ChromaticFocalShift {
+        let (short, long) = self.parameters.wavelengths.band();
+        let count = samples.max(2);
+        let wavelengths: Vec<Wavelength> = (0..count)
+            .map(|k| Wavelength::from_um(short.um() + (long.um() - short.um()) * k as f64 / (count - 1) as f64))
+            .collect();
+        let reference = self.primary_wavelength();
+        let focus = self.paraxial_image_position(reference);
+        let shifts = wavelengths.iter().map(|&wavelength| self.paraxial_image_position(wavelength) - focus).collect();
+        ChromaticFocalShift { reference, wavelengths, shifts }
+    }
+}
+
+
+impl LongitudinalAberration {
+    /// Longitudinal spherical aberration at the edge of the pupil, real focus minus paraxial
+    /// focus of the wavelength. `None` for a wavelength index outside the table.
+    pub fn marginal_spherical(&self, wavelength: usize) -> Option<f64> {
+        let focus = &self.curves.get(wavelength)?.focus;
+        Some((*focus.last()?)? - focus[0]?)
+    }
+
+    /// Pupil height on the vertical axis against the focus in micrometres, one curve per
+    /// wavelength.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (600, 600))
+    }
+}
+
+
+impl ChromaticFocalShift {
+    /// Spread of the focus across the band, the largest minus the smallest shift. `None`
+    /// without samples.
+    pub fn secondary_spectrum(&self) -> Option<f64> {
+        let largest = self.shifts.iter().copied().reduce(f64::max)?;
+        let smallest = self.shifts.iter().copied().reduce(f64::min)?;
+        Some(largest - smallest)
+    }
+
+    /// Wavelengths next to which the focal shift curve crosses the reference focus.
+    pub fn zero_crossings(&self) -> Vec<Wavelength> {
+        self.shifts.windows(2).zip(self.wavelengths.windows(2))
+            .filter(|(s, _)| s[0] == 0. || s[0] * s[1] < 0.)
+            .map(|(s, w)| {
+                let fraction = s[0] / (s[0] - s[1]);
+                Wavelength::from_um(w[0].um() + (w[1].um() - w[0].um()) * fraction)
+            })
+            .collect()
+    }
+
+    /// Focal shift in micrometres against wavelength.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (700, 500))
+    }
+}
+
+
+impl Plot for LongitudinalAberration {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let extent = self.curves.iter()
+            .flat_map(|curve| curve.focus.iter().flatten())
+            .fold(0., |max: f64, value| max.max(value.abs()))
+            .max(1e-9) * 1e3 * 1.1;
+        let mut chart = ChartBuilder::on(root)
+            .caption("Longitudinal aberration", ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(-extent..extent, 0f64..1f64)?;
+        chart.configure_mesh()
+            .x_desc("Focus, um")
+            .y_desc("Pupil height")
+            .draw()?;
+        for (index, curve) in self.curves.iter().enumerate() {
+            let color = Palette99::pick(index).to_rgba();
+            chart.draw_series(LineSeries::new(
+                curve.focus.iter().zip(self.pupil.iter()).filter_map(|(focus, &py)| Some(((*focus)? * 1e3, py))),
+                color,
+            ))?
+                .label(format!("{}", curve.wavelength))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+
+impl Plot for ChromaticFocalShift {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let (left, right) = match (self.wavelengths.first(), self.wavelengths.last()) {
+            (Some(first), Some(last)) => (first.um(), last.um().max(first.um() + 1e-9)),
+            _ => (0., 1.),
+        };
+        let extent = self.shifts.iter().fold(0., |max: f64, s| max.max(s.abs())).max(1e-9) * 1e3 * 1.1;
+        let mut chart = ChartBuilder::on(root)
+            .caption(format!("Chromatic focal shift, secondary spectrum {:.3} um", self.secondary_spectrum().unwrap_or(0.) * 1e3), ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(left..right, -extent..extent)?;
+        chart.configure_mesh()
+            .x_desc("Wavelength, um")
+            .y_desc("Focal shift, um")
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            self.wavelengths.iter().zip(self.shifts.iter()).map(|(w, s)| (w.um(), s * 1e3)),
+            &BLUE,
+        ))?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for LongitudinalAberration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Longitudinal aberration, mm")?;
+        write!(f, "  Pupil ")?;
+        for curve in self.curves.iter() {
+            write!(f, "| {:>12} ", format!("{}", curve.wavelength))?;
+        }
+        writeln!(f)?;
+        for (k, py) in self.pupil.iter().enumerate() {
+            write!(f, "{:7.4} ", py)?;
+            for curve in self.curves.iter() {
+                match curve.focus[k] {
+                    Some(focus) => write!(f, "| {:12.6} ", focus)?,
+                    None => write!(f, "| {:>12} ", "-")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for ChromaticFocalShift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chromatic focal shift from {}, um", self.reference)?;
+        for (wavelength, shift) in self.wavelengths.iter().zip(self.shifts.iter()) {
+            writeln!(f, "{} | {:12.4}", wavelength, shift * 1e3)?;
+        }
+        match self.secondary_spectrum() {
+            Some(spectrum) => writeln!(f, "Secondary spectrum, um : {:.4}", spectrum * 1e3),
+            None => writeln!(f, "Secondary spectrum, um : -"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::config::load_sequential_system;
+
+    fn apochromat() -> SequentialOpticalSystem {
+        load_sequential_system(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/apochromat3.yaml")).unwrap()
+    }
+
+    #[test]
+    fn test_focal_shift_matches_paraxial_focus() {
+        let system = apochromat();
+        let table = system.parameters.wavelengths.wavelengths();
+        let shift = system.chromatic_focal_shift(41);
+        assert_eq!(shift.wavelengths.len(), 41);
+        assert_approx_eq!(shift.wavelengths[0].um(), table[0].um(), 1e-12);
+        let crossings = shift.zero_crossings();
+        assert!(crossings.iter().any(|w| (w.um() - system.primary_wavelength().um()).abs() < 1e-4));
+        let spectrum = shift.secondary_spectrum().unwrap();
+        // the blue end of the band turns back towards the reference focus
+        let smallest = shift.shifts.iter().copied().fold(f64::INFINITY, f64::min);
+        assert!(smallest < shift.shifts[0]);
+        assert_approx_eq!(spectrum, shift.shifts[40] - smallest, 1e-12);
+        let empty = ChromaticFocalShift { reference: shift.reference, wavelengths: Vec::new(), shifts: Vec::new() };
+        assert_eq!(empty.secondary_spectrum(), None);
+        assert!(empty.to_string().ends_with("-\n"));
+        assert_eq!(shift.to_string().lines().count(), 43);
+        shift.plot(std::env::temp_dir().join("opaliha_focal_shift.png")).unwrap();
+
+        let longitudinal = system.longitudinal_aberration(11);
+        assert_approx_eq!(longitudinal.curves[0].focus[0].unwrap(), shift.shifts[0], 1e-9);
+        assert_approx_eq!(longitudinal.curves[5].focus[0].unwrap(), shift.shifts[40], 1e-9);
+        assert_eq!(longitudinal.curves[4].focus[0], Some(0.));
+    }
+
+    #[test]
+    fn test_spherical_grows_with_pupil_squared() {
+        let system = apochromat();
+        let longitudinal = system.longitudinal_aberration(11);
+        let primary = system.parameters.wavelengths.primary();
+        let focus = &longitudinal.curves[primary].focus;
+        let marginal = longitudinal.marginal_spherical(primary).unwrap();
+        assert!(marginal.abs() > 1e-4);
+        assert_approx_eq!(focus[1].unwrap(), marginal / 100., 1e-2 * marginal.abs() / 100.);
+        assert_eq!(longitudinal.marginal_spherical(longitudinal.curves.len()), None);
+        assert_eq!(longitudinal.to_string().lines().count(), 13);
+        longitudinal.plot(std::env::temp_dir().join("opaliha_longitudinal.png")).unwrap();
+    }
+}
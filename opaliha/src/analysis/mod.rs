@@ -0,0 +1,19 @@
+pub mod best_focus;
+pub mod buchdahl;
+pub mod encircled_energy;
+pub mod field_curvature;
+pub mod grid_distortion;
+pub mod huygens;
+pub mod layout;
+pub mod lateral_color;
+pub mod longitudinal_aberration;
+pub mod mtf;
+pub mod psf;
+pub mod ray_fan;
+pub mod seidel;
+pub mod spot_diagram;
+pub mod vignetting;
+pub mod wavefront;
+pub mod zernike_fit;
+
+pub use crate::optical_system::pupil_sampling;
@@ -0,0 +1,521 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use ndarray::Array2;
+use num::complex::Complex64;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::analysis::psf::FftPsfSettings;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::pupil_sampling::PupilSampling;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MtfSettings {
+    pub psf: FftPsfSettings,
+    /// Highest frequency in cycles/mm, the diffraction cutoff at the primary wavelength when
+    /// `None`.
+    pub max_frequency: Option<f64>,
+    /// Frequencies from zero to the highest one.
+    pub frequency_samples: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GeometricMtfSettings {
+    pub sampling: PupilSampling,
+    /// Highest frequency in cycles/mm, the diffraction cutoff at the primary wavelength when
+    /// `None`.
+    pub max_frequency: Option<f64>,
+    pub frequency_samples: usize,
+    /// Multiplies the curves by the diffraction limit, which keeps them below it near focus.
+    pub scale_by_diffraction: bool,
+}
+
+/// How a single MTF value is computed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MtfMethod {
+    Fft(FftPsfSettings),
+    /// Ray density of the spot, unscaled.
+    Geometric(PupilSampling),
+}
+
+/// Modulation of one field, tangential along y and sagittal along x.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MtfCurve {
+    pub field: FieldRaw,
+    pub tangential: Vec<f64>,
+    pub sagittal: Vec<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MtfAnalysis {
+    /// Cycles per mm.
+    pub frequencies: Vec<f64>,
+    /// Weighted over the wavelengths like the curves.
+    pub diffraction_limit: Vec<f64>,
+    /// Row of the field table of every curve, fields failing are left out.
+    pub field_indices: Vec<usize>,
+    pub curves: Vec<MtfCurve>,
+}
+
+/// Modulation at one frequency against the shift of the image plane, the curves run over
+/// `defocus` instead of frequency.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ThroughFocusMtf {
+    pub frequency: f64,
+    pub defocus: Vec<f64>,
+    /// Row of the field table of every curve.
+    pub field_indices: Vec<usize>,
+    pub curves: Vec<MtfCurve>,
+}
+
+/// Modulation at fixed frequencies along the y field.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MtfVsField {
+    pub frequencies: Vec<f64>,
+    pub fields: Vec<f64>,
+    /// Indexed by frequency, then field.
+    pub tangential: Vec<Vec<f64>>,
+    pub sagittal: Vec<Vec<f64>>,
+}
+
+
+impl Default for MtfSettings {
+    fn default() -> Self {
+        MtfSettings { psf: FftPsfSettings::default(), max_frequency: None, frequency_samples: 51 }
+    }
+}
+
+
+impl Default for GeometricMtfSettings {
+    fn default() -> Self {
+        GeometricMtfSettings {
+            sampling: PupilSampling::Square { size: 64 },
+            max_frequency: None,
+            frequency_samples: 51,
+            scale_by_diffraction: false,
+        }
+    }
+}
+
+
+impl Default for MtfMethod {
+    fn default() -> Self {
+        MtfMethod::Fft(FftPsfSettings::default())
+    }
+}
+
+
+/// MTF of an aberration-free circular pupil, zero beyond the cutoff.
+pub fn diffraction_limited_mtf(frequency: f64, cutoff: f64) -> f64 {
+    let ratio = (frequency / cutoff).abs();
+    if ratio >= 1. { return 0. }
+    let phi = ratio.acos();
+    2. / PI * (phi - phi.cos() * phi.sin())
+}
+
+
+/// OTF along the y (`tangential`) or x axis at the frequency, interpolated linearly between
+/// the grid frequencies and zero beyond the Nyquist limit of the grid.
+pub(crate) fn otf_cut(otf: &Array2<Complex64>, spacing: f64, frequency: f64, tangential: bool) -> Complex64 {
+    let position = frequency / spacing;
+    let index = position.floor() as usize;
+    if index + 1 >= otf.nrows() / 2 { return Complex64::new(0., 0.) }
+    let at = |k: usize| if tangential { otf[(k, 0)] } else { otf[(0, k)] };
+    at(index) * (1. - position.fract()) + at(index + 1) * position.fract()
+}
+
+
+impl SequentialOpticalSystem {
+    /// Incoherent cutoff 2 NA / λ of the paraxial image-space aperture in cycles/mm, `None`
+    /// when the aperture definition gives no entrance pupil.
+    pub fn diffraction_cutoff(&self, wavelength: Wavelength) -> Option<f64> {
+        Some(2. * self.image_space_aperture(wavelength).ok()? / wavelength.mm())
+    }
+
+    /// Polychromatic FFT MTF of a field at the frequencies. The OTF of every wavelength is moved
+    /// to the primary chief ray so lateral colour lowers the modulation.
+    pub fn fft_field_mtf(&self, field: &FieldRaw, frequencies: &[f64], settings: &FftPsfSettings) -> Option<MtfCurve> {
+        let primary = self.fft_psf(field, self.primary_wavelength(), settings)?.center;
+        let mut tangential = vec![Complex64::new(0., 0.); frequencies.len()];
+        let mut sagittal = tangential.clone();
+        let mut total = 0.;
+        for entry in self.parameters.wavelengths.entries().iter() {
+            let Some(psf) = self.fft_psf(field, entry.wavelength, settings) else { continue };
+            let otf = psf.otf();
+            let spacing = psf.frequency_spacing();
+            let shift = psf.center - primary;
+            for (k, &frequency) in frequencies.iter().enumerate() {
+                let phase = |offset: f64| Complex64::from_polar(entry.weight, -2. * PI * frequency * offset);
+                tangential[k] += otf_cut(&otf, spacing, frequency, true) * phase(shift.y);
+                sagittal[k] += otf_cut(&otf, spacing, frequency, false) * phase(shift.x);
+            }
+            total += entry.weight;
+        }
+        Some(MtfCurve {
+            field: *field,
+            tangential: tangential.iter().map(|value| value.norm() / total).collect(),
+            sagittal: sagittal.iter().map(|value| value.norm() / total).collect(),
+        })
+    }
+
+    /// FFT MTF of every field from zero to the highest frequency of the settings. `None` when
+    /// the aperture definition gives no entrance pupil.
+    pub fn fft_mtf(&self, settings: &MtfSettings) -> Option<MtfAnalysis> {
+        let frequencies = self.mtf_frequencies(settings.max_frequency, settings.frequency_samples)?;
+        let (field_indices, curves) = self.parameters.field_data.rows.iter().enumerate()
+            .filter_map(|(index, field)| Some((index, self.fft_field_mtf(field, &frequencies, &settings.psf)?)))
+            .unzip();
+        Some(MtfAnalysis { diffraction_limit: self.diffraction_limit(&frequencies)?, frequencies, field_indices, curves })
+    }
+
+    /// Geometric MTF of a field from the Fourier transform of its spot at the image plane shifted
+    /// by `defocus`: rays of all wavelengths with their weights, positions relative to the primary
+    /// wavelength chief ray.
+    pub fn geometric_field_mtf(&self, field: &FieldRaw, frequencies: &[f64], sampling: PupilSampling, defocus: f64) -> Option<MtfCurve> {
+        let point = self.field_point(field)?;
+        let primary = self.defocused_image_ray(field, point, 0., 0., self.primary_wavelength(), defocus)?.origin;
+        let pupil = sampling.points();
+        let mut spots = Vec::new();
+        for entry in self.parameters.wavelengths.entries().iter() {
+            for &(px, py) in pupil.iter() {
+                if let Some(ray) = self.defocused_image_ray(field, point, px, py, entry.wavelength, defocus) {
+                    spots.push((ray.origin.x - primary.x, ray.origin.y - primary.y, entry.weight));
+                }
+            }
+        }
+        let total: f64 = spots.iter().map(|s| s.2).sum();
+        if total == 0. { return None }
+        let modulation = |frequency: f64, tangential: bool| spots.iter()
+            .map(|&(x, y, weight)| Complex64::from_polar(weight, -2. * PI * frequency * if tangential { y } else { x }))
+            .sum::<Complex64>()
+            .norm() / total;
+        Some(MtfCurve {
+            field: *field,
+            tangential: frequencies.iter().map(|&f| modulation(f, true)).collect(),
+            sagittal: frequencies.iter().map(|&f| modulation(f, false)).collect(),
+        })
+    }
+
+    /// Geometric MTF of every field, for systems far from the diffraction limit. `None` when
+    /// the aperture definition gives no entrance pupil.
+    pub fn geometric_mtf(&self, settings: &GeometricMtfSettings) -> Option<MtfAnalysis> {
+        let frequencies = self.mtf_frequencies(settings.max_frequency, settings.frequency_samples)?;
+        let diffraction_limit = self.diffraction_limit(&frequencies)?;
+        let (field_indices, curves) = self.parameters.field_data.rows.iter().enumerate()
+            .filter_map(|(index, field)| Some((index, self.geometric_field_mtf(field, &frequencies, settings.sampling, 0.)?)))
+            .map(|(index, mut curve)| {
+                if settings.scale_by_diffraction {
+                    for values in [&mut curve.tangential, &mut curve.sagittal] {
+                        values.iter_mut().zip(diffraction_limit.iter()).for_each(|(value, limit)| *value *= limit);
+                    }
+                }
+                (index, curve)
+            })
+            .unzip();
+        Some(MtfAnalysis { frequencies, diffraction_limit, field_indices, curves })
+    }
+
+    /// MTF of every field at the frequency for `steps` image plane shifts from `-range` to
+    /// `range`. Fields failing at some shift are left out.
+    pub fn through_focus_mtf(&self, frequency: f64, range: f64, steps: usize, method: &MtfMethod) -> ThroughFocusMtf {
+        let count = steps.max(2);
+        let defocus: Vec<f64> = (0..count).map(|k| -range + 2. * range * k as f64 / (count - 1) as f64).collect();
+        let (field_indices, curves) = self.parameters.field_data.rows.iter().enumerate().filter_map(|(index, field)| {
+            let mut curve = MtfCurve { field: *field, tangential: Vec::new(), sagittal: Vec::new() };
+            for &shift in defocus.iter() {
+                let value = self.method_field_mtf(field, &[frequency], method, shift)?;
+                curve.tangential.push(value.tangential[0]);
+                curve.sagittal.push(value.sagittal[0]);
+            }
+            Some((index, curve))
+        }).unzip();
+        ThroughFocusMtf { frequency, defocus, field_indices, curves }
+    }
+
+    /// MTF of a field by either method with the image plane shifted by `defocus`.
+    pub(crate) fn method_field_mtf(&self, field: &FieldRaw, frequencies: &[f64], method: &MtfMethod, defocus: f64) -> Option<MtfCurve> {
+        match method {
+            MtfMethod::Fft(settings) => self.fft_field_mtf(field, frequencies, &FftPsfSettings { defocus, ..*settings }),
+            MtfMethod::Geometric(sampling) => self.geometric_field_mtf(field, frequencies, *sampling, defocus),
+        }
+    }
+
+    /// Evenly spaced frequencies from zero, up to the primary cutoff by default.
+    fn mtf_frequencies(&self, max_frequency: Option<f64>, samples: usize) -> Option<Vec<f64>> {
+        let highest = max_frequency.or_else(|| self.diffraction_cutoff(self.primary_wavelength()))?;
+        let count = samples.max(2);
+        Some((0..count).map(|k| highest * k as f64 / (count - 1) as f64).collect())
+    }
+
+    /// FFT MTF at the frequencies for `field_samples` y fields from zero to the largest field.
+    /// Fields whose rays fail are left out.
+    pub fn fft_mtf_vs_field(&self, frequencies: &[f64], field_samples: usize, settings: &FftPsfSettings) -> MtfVsField {
+        let largest = self.parameters.field_data.max_field();
+        let count = field_samples.max(2);
+        let mut result = MtfVsField {
+            frequencies: frequencies.to_vec(),
+            fields: Vec::new(),
+            tangential: vec![Vec::new(); frequencies.len()],
+            sagittal: vec![Vec::new(); frequencies.len()],
+        };
+        for k in 0..count {
+            let y = largest * k as f64 / (count - 1) as f64;
+            let Some(curve) = self.fft_field_mtf(&FieldRaw::new(0., y), frequencies, settings) else { continue };
+            result.fields.push(y);
+            for (index, (t, s)) in curve.tangential.iter().zip(curve.sagittal.iter()).enumerate() {
+                result.tangential[index].push(*t);
+                result.sagittal[index].push(*s);
+            }
+        }
+        result
+    }
+
+    /// Diffraction-limited MTF weighted over the wavelength table, `None` when the aperture
+    /// definition gives no entrance pupil.
+    pub fn diffraction_limit(&self, frequencies: &[f64]) -> Option<Vec<f64>> {
+        let entries = &self.parameters.wavelengths.entries();
+        let total: f64 = entries.iter().map(|e| e.weight).sum();
+        let cutoffs: Vec<f64> = entries.iter().map(|e| self.diffraction_cutoff(e.wavelength)).collect::<Option<_>>()?;
+        Some(frequencies.iter().map(|&frequency| {
+            entries.iter().zip(cutoffs.iter())
+                .map(|(entry, &cutoff)| entry.weight * diffraction_limited_mtf(frequency, cutoff))
+                .sum::<f64>() / total
+        }).collect())
+    }
+}
+
+
+impl MtfAnalysis {
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (800, 600))
+    }
+}
+
+
+impl ThroughFocusMtf {
+    /// Defocus of the highest tangential plus sagittal modulation of a field curve.
+    pub fn best_defocus(&self, curve: &MtfCurve) -> Option<f64> {
+        (0..self.defocus.len())
+            .max_by(|&a, &b| (curve.tangential[a] + curve.sagittal[a]).total_cmp(&(curve.tangential[b] + curve.sagittal[b])))
+            .map(|index| self.defocus[index])
+    }
+
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (800, 600))
+    }
+}
+
+
+impl MtfVsField {
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (800, 600))
+    }
+}
+
+
+/// Chart with modulation over `x`, tangential curves solid and sagittal ones dashed.
+fn draw_modulation<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    caption: &str,
+    x_desc: &str,
+    x: &[f64],
+    curves: &[(String, &Vec<f64>, &Vec<f64>)],
+    limit: Option<&Vec<f64>>,
+) -> PlotResult
+where
+    DB::ErrorType: 'static,
+{
+    let left = x.first().copied().unwrap_or(0.);
+    let right = x.last().copied().unwrap_or(0.).max(left + 1e-9);
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(50)
+        .build_cartesian_2d(left..right, 0f64..1.05)?;
+    chart.configure_mesh()
+        .x_desc(x_desc)
+        .y_desc("Modulation")
+        .draw()?;
+    if let Some(limit) = limit {
+        chart.draw_series(LineSeries::new(x.iter().copied().zip(limit.iter().copied()), &BLACK))?
+            .label("Diffraction limit")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 15, y)], BLACK));
+    }
+    for (index, (label, tangential, sagittal)) in curves.iter().enumerate() {
+        let color = Palette99::pick(index).to_rgba();
+        chart.draw_series(LineSeries::new(x.iter().copied().zip(tangential.iter().copied()), color))?
+            .label(label.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 15, y)], color));
+        chart.draw_series(DashedLineSeries::new(x.iter().copied().zip(sagittal.iter().copied()), 6, 4, color.into()))?;
+    }
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
+
+
+impl Plot for MtfAnalysis {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let curves: Vec<(String, &Vec<f64>, &Vec<f64>)> = self.field_indices.iter().zip(self.curves.iter())
+            .map(|(index, c)| (format!("Field {}: {}, {}", index + 1, c.field.xfield, c.field.yfield), &c.tangential, &c.sagittal))
+            .collect();
+        draw_modulation(root, "FFT MTF, T solid, S dashed", "Spatial frequency, cycles/mm", &self.frequencies, &curves, Some(&self.diffraction_limit))
+    }
+}
+
+
+impl Plot for MtfVsField {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let curves: Vec<(String, &Vec<f64>, &Vec<f64>)> = self.frequencies.iter().enumerate()
+            .map(|(index, f)| (format!("{} cycles/mm", f), &self.tangential[index], &self.sagittal[index]))
+            .collect();
+        draw_modulation(root, "FFT MTF vs field, T solid, S dashed", "Y field", &self.fields, &curves, None)
+    }
+}
+
+
+impl Plot for ThroughFocusMtf {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let curves: Vec<(String, &Vec<f64>, &Vec<f64>)> = self.field_indices.iter().zip(self.curves.iter())
+            .map(|(index, c)| (format!("Field {}: {}, {}", index + 1, c.field.xfield, c.field.yfield), &c.tangential, &c.sagittal))
+            .collect();
+        let defocus: Vec<f64> = self.defocus.iter().map(|d| d * 1e3).collect();
+        let caption = format!("Through focus MTF at {} cycles/mm, T solid, S dashed", self.frequency);
+        draw_modulation(root, &caption, "Defocus, um", &defocus, &curves, None)
+    }
+}
+
+
+impl fmt::Display for ThroughFocusMtf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Through focus MTF at {} cycles/mm", self.frequency)?;
+        write!(f, "Defocus, um ")?;
+        for index in self.field_indices.iter() {
+            write!(f, "| F{:<2} T  | F{:<2} S  ", index + 1, index + 1)?;
+        }
+        writeln!(f)?;
+        for (k, defocus) in self.defocus.iter().enumerate() {
+            write!(f, "{:11.3} ", defocus * 1e3)?;
+            for curve in self.curves.iter() {
+                write!(f, "| {:6.4} | {:6.4} ", curve.tangential[k], curve.sagittal[k])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for MtfAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Frequency |  Limit ")?;
+        for index in self.field_indices.iter() {
+            write!(f, "| F{:<2} T  | F{:<2} S  ", index + 1, index + 1)?;
+        }
+        writeln!(f)?;
+        for (k, frequency) in self.frequencies.iter().enumerate() {
+            write!(f, "{:9.3} | {:6.4} ", frequency, self.diffraction_limit[k])?;
+            for curve in self.curves.iter() {
+                write!(f, "| {:6.4} | {:6.4} ", curve.tangential[k], curve.sagittal[k])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::WavelengthTable;
+    use crate::optical_system::fixtures::{focused_singlet, with_pupil_and_field};
+
+    #[test]
+    fn test_near_diffraction_limit() {
+        let settings = MtfSettings {
+            psf: FftPsfSettings { pupil_samples: 32, grid_size: 128, defocus: 0. },
+            max_frequency: None,
+            frequency_samples: 11,
+        };
+        let analysis = with_pupil_and_field(focused_singlet(), 1., 5.).fft_mtf(&settings).unwrap();
+        assert_approx_eq!(analysis.diffraction_limit[5], diffraction_limited_mtf(0.5, 1.), 1e-12);
+        let on_axis = &analysis.curves[0];
+        assert_approx_eq!(on_axis.tangential[0], 1., 1e-12);
+        for k in 0..11 {
+            assert_approx_eq!(on_axis.tangential[k], analysis.diffraction_limit[k], 3e-2);
+            assert_approx_eq!(on_axis.tangential[k], on_axis.sagittal[k], 1e-9);
+        }
+        assert_eq!(analysis.to_string().lines().count(), 12);
+        analysis.plot(std::env::temp_dir().join("opaliha_fft_mtf.png")).unwrap();
+    }
+
+    #[test]
+    fn test_lateral_colour_and_field() {
+        let mut system = with_pupil_and_field(focused_singlet(), 4., 5.);
+        let settings = FftPsfSettings { pupil_samples: 32, grid_size: 128, defocus: 0. };
+        let frequency = [20.];
+        let field = FieldRaw::new(0., 5.);
+        let monochromatic = system.fft_field_mtf(&field, &frequency, &settings).unwrap();
+        system.parameters.wavelengths = WavelengthTable::visible();
+        let polychromatic = system.fft_field_mtf(&field, &frequency, &settings).unwrap();
+        assert!(polychromatic.tangential[0] < monochromatic.tangential[0]);
+
+        let along_field = system.fft_mtf_vs_field(&[10., 40.], 3, &settings);
+        assert_eq!(along_field.fields, vec![0., 2.5, 5.]);
+        assert!(along_field.tangential[1][2] < along_field.tangential[0][2]);
+        along_field.plot(std::env::temp_dir().join("opaliha_fft_mtf_field.png")).unwrap();
+    }
+
+    #[test]
+    fn test_geometric_blur_disk() {
+        // far out of focus the spot is a uniform disk of radius b with MTF 2 J1(2π f b) / (2π f b)
+        let system = with_pupil_and_field(focused_singlet(), 2., 5.);
+        let defocus = 1.;
+        let blur = defocus * system.image_space_aperture(system.primary_wavelength()).unwrap();
+        let zero = 3.8317 / (2. * PI * blur);
+        let curve = system.geometric_field_mtf(&FieldRaw::new(0., 0.), &[0., zero / 2., zero], PupilSampling::Square { size: 101 }, defocus).unwrap();
+        assert_approx_eq!(curve.tangential[0], 1., 1e-12);
+        assert_approx_eq!(curve.tangential[1], 2. * 0.5813 / 1.9159, 2e-2);
+        assert!(curve.sagittal[2] < 3e-2);
+
+        let settings = GeometricMtfSettings { scale_by_diffraction: true, frequency_samples: 5, ..Default::default() };
+        let analysis = system.geometric_mtf(&settings).unwrap();
+        assert_eq!(analysis.curves[0].tangential[4], 0.);
+        assert_eq!(analysis.field_indices, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_through_focus() {
+        let system = with_pupil_and_field(focused_singlet(), 2., 5.);
+        let method = MtfMethod::Fft(FftPsfSettings { pupil_samples: 32, grid_size: 64, defocus: 0. });
+        let through_focus = system.through_focus_mtf(20., 2., 9, &method);
+        assert_eq!(through_focus.defocus.len(), 9);
+        let on_axis = &through_focus.curves[0];
+        assert_approx_eq!(through_focus.best_defocus(on_axis).unwrap(), 0., 1e-12);
+        assert_approx_eq!(on_axis.tangential[2], on_axis.tangential[6], 2e-2);
+        assert!(on_axis.tangential[0] < 0.5 * on_axis.tangential[4]);
+        assert_eq!(through_focus.to_string().lines().count(), 11);
+        through_focus.plot(std::env::temp_dir().join("opaliha_through_focus.png")).unwrap();
+
+        let geometric = system.through_focus_mtf(20., 2., 9, &MtfMethod::Geometric(PupilSampling::default()));
+        assert!(geometric.curves[0].tangential[0] < geometric.curves[0].tangential[4]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::f64::consts::PI;
+use std::path::Path;
+use ndarray::Array2;
+use num::complex::Complex64;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::point::Point3;
+use crate::math::fft::{fft_2d, fft_shift};
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FftPsfSettings {
+    /// Pupil samples across the diameter.
+    pub pupil_samples: usize,
+    /// Size of the zero-padded FFT grid, raised to a power of two of at least twice the pupil
+    /// samples so the OTF does not alias.
+    pub grid_size: usize,
+    /// Shift of the image plane along z, for through-focus analyses.
+    pub defocus: f64,
+}
+
+/// Diffraction PSF from the FFT of the pupil function. The pupil amplitude is one where rays
+/// reach the image and zero where they fail, the phase comes from the OPD. Like in the spot
+/// diagram clear semi-diameters do not clip the rays.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FftPsf {
+    pub field: FieldRaw,
+    pub wavelength: Wavelength,
+    /// Chief ray point on the image surface the PSF is centred on.
+    pub center: Point3,
+    /// Pixel pitch on the image surface.
+    pub spacing: f64,
+    /// Intensity relative to the peak of the unaberrated pupil, rows along y with the center in
+    /// the middle of the grid.
+    pub intensity: Array2<f64>,
+}
+
+
+impl Default for FftPsfSettings {
+    fn default() -> Self {
+        FftPsfSettings { pupil_samples: 64, grid_size: 256, defocus: 0. }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    pub fn fft_psf(&self, field: &FieldRaw, wavelength: Wavelength, settings: &FftPsfSettings) -> Option<FftPsf> {
+        let samples = settings.pupil_samples.max(2);
+        let size = settings.grid_size.max(2 * samples).next_power_of_two();
+        let map = self.defocused_wavefront_map(field, wavelength, samples, settings.defocus)?;
+        let sphere = self.defocused_reference_sphere(field, self.field_point(field)?, wavelength, settings.defocus)?;
+        let aperture = self.image_space_aperture(wavelength).ok()?;
+
+        let mut grid = Array2::from_elem((size, size), Complex64::new(0., 0.));
+        let mut open = 0.;
+        for (k, value) in map.values.iter().enumerate() {
+            if let Some(opd) = value {
+                grid[(k / samples, k % samples)] = Complex64::from_polar(1., -2. * PI * opd);
+                open += 1.;
+            }
+        }
+        if open == 0. { return None }
+        fft_2d(&mut grid, false);
+        let intensity = fft_shift(&grid.mapv(|amplitude| amplitude.norm_sqr() / (open * open)));
+
+        Some(FftPsf {
+            field: *field,
+            wavelength,
+            center: sphere.center,
+            // pupil step 2 NA / (samples - 1) in direction cosines
+            spacing: wavelength.mm() * (samples - 1) as f64 / (2. * aperture * size as f64),
+            intensity,
+        })
+    }
+}
+
+
+impl FftPsf {
+    pub fn size(&self) -> usize {
+        self.intensity.nrows()
+    }
+
+    /// Position of a row or column relative to the center.
+    pub fn position(&self, index: usize) -> f64 {
+        (index as f64 - (self.size() / 2) as f64) * self.spacing
+    }
+
+    /// Highest intensity, an estimate of the Strehl ratio.
+    pub fn peak(&self) -> f64 {
+        self.intensity.iter().fold(0., |max, &value| f64::max(max, value))
+    }
+
+    /// Intensity along x through the center.
+    pub fn cross_section_x(&self) -> Vec<(f64, f64)> {
+        let row = self.size() / 2;
+        (0..self.size()).map(|j| (self.position(j), self.intensity[(row, j)])).collect()
+    }
+
+    /// Intensity along y through the center.
+    pub fn cross_section_y(&self) -> Vec<(f64, f64)> {
+        let column = self.size() / 2;
+        (0..self.size()).map(|i| (self.position(i), self.intensity[(i, column)])).collect()
+    }
+
+    /// Intensity weighted mean position relative to the center.
+    pub fn centroid(&self) -> (f64, f64) {
+        let (mut x, mut y, mut total) = (0., 0., 0.);
+        for ((i, j), &value) in self.intensity.indexed_iter() {
+            x += self.position(j) * value;
+            y += self.position(i) * value;
+            total += value;
+        }
+        (x / total, y / total)
+    }
+
+    /// Optical transfer function normalized to one at zero frequency, zero frequency at index
+    /// 0 and the frequency step `frequency_spacing` along both axes.
+    pub fn otf(&self) -> Array2<Complex64> {
+        centered_otf(&self.intensity)
+    }
+
+    /// Frequency step of the OTF in cycles per unit length.
+    pub fn frequency_spacing(&self) -> f64 {
+        1. / (self.size() as f64 * self.spacing)
+    }
+
+    /// Cross-sections along x and y over the central quarter of the grid, in micrometres.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (1000, 450))
+    }
+}
+
+
+/// OTF of an intensity grid centred at `size / 2`, zero-padded to a power of two at least as
+/// large, normalized to one at zero frequency which lands at index 0.
+pub(crate) fn centered_otf(intensity: &Array2<f64>) -> Array2<Complex64> {
+    let (rows, columns) = intensity.dim();
+    let size = rows.max(columns).next_power_of_two();
+    let mut otf = Array2::from_elem((size, size), Complex64::new(0., 0.));
+    for ((i, j), &value) in intensity.indexed_iter() {
+        let (row, column) = ((i + size - rows / 2) % size, (j + size - columns / 2) % size);
+        otf[(row, column)] = Complex64::new(value, 0.);
+    }
+    fft_2d(&mut otf, false);
+    let zero = otf[(0, 0)];
+    otf.mapv(|value| value / zero)
+}
+
+
+impl Plot for FftPsf {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let extent = self.position(self.size() / 2 + self.size() / 8) * 1e3;
+        let top = self.peak().max(1e-12) * 1.05;
+        let panels = root.split_evenly((1, 2));
+        for (panel, (name, section)) in panels.iter().zip([("X", self.cross_section_x()), ("Y", self.cross_section_y())]) {
+            let caption = format!("PSF {}, field {}, {}  {}", name, self.field.xfield, self.field.yfield, self.wavelength);
+            let mut chart = ChartBuilder::on(panel)
+                .caption(caption, ("sans-serif", 18))
+                .margin(10)
+                .x_label_area_size(40)
+                .y_label_area_size(50)
+                .build_cartesian_2d(-extent..extent, 0f64..top)?;
+            chart.configure_mesh()
+                .x_desc(format!("{}, um", name))
+                .y_desc("Relative intensity")
+                .draw()?;
+            chart.draw_series(LineSeries::new(
+                section.iter().map(|&(position, value)| (position * 1e3, value)).filter(|p| p.0.abs() <= extent),
+                &BLUE,
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::analysis::spot_diagram::SpotDiagramSettings;
+    use crate::optical_system::fixtures::{focused_singlet, with_pupil_and_field};
+    use crate::optical_system::pupil_sampling::PupilSampling;
+
+    #[test]
+    fn test_airy_pattern() {
+        let system = with_pupil_and_field(focused_singlet(), 2., 5.);
+        let primary = system.primary_wavelength();
+        let settings = FftPsfSettings { pupil_samples: 32, grid_size: 128, defocus: 0. };
+        let psf = system.fft_psf(&FieldRaw::new(0., 0.), primary, &settings).unwrap();
+        let map = system.wavefront_map(&FieldRaw::new(0., 0.), primary, 32).unwrap();
+        assert_approx_eq!(psf.peak(), map.strehl_ratio(), 1e-2);
+        assert_eq!(psf.intensity[(64, 64)], psf.peak());
+        // first dark ring at 0.61 λ / NA
+        let aperture = system.image_space_aperture(primary).unwrap();
+        let dark = 0.61 * primary.mm() / aperture / psf.spacing;
+        let (below, fraction) = (dark.floor() as usize, dark.fract());
+        let section = psf.cross_section_x();
+        let ring = section[64 + below].1 * (1. - fraction) + section[64 + below + 1].1 * fraction;
+        assert!(ring < 0.02);
+        assert_approx_eq!(psf.otf()[(0, 0)].re, 1., 1e-12);
+    }
+
+    #[test]
+    fn test_centroid_follows_rays() {
+        let mut system = with_pupil_and_field(focused_singlet(), 4., 5.);
+        system.parameters.wavelengths = crate::database::wavelengths::WavelengthTable::single(system.primary_wavelength());
+        let field = FieldRaw::new(0., 5.);
+        let psf = system.fft_psf(&field, system.primary_wavelength(), &FftPsfSettings { pupil_samples: 128, grid_size: 512, defocus: 0. }).unwrap();
+        let spots = system.spot_diagram(&SpotDiagramSettings { sampling: PupilSampling::Square { size: 128 }, ..Default::default() }).unwrap();
+        let points = &spots.fields[1].points;
+        let mean_y = points.iter().map(|p| p.y).sum::<f64>() / points.len() as f64;
+        let (x, y) = psf.centroid();
+        assert_approx_eq!(x, 0., 1e-9);
+        assert!(mean_y.abs() > 1e-4);
+        assert_approx_eq!(y, mean_y, 0.05 * mean_y.abs());
+        let path = std::env::temp_dir().join("opaliha_fft_psf.png");
+        psf.plot(&path).unwrap();
+    }
+}
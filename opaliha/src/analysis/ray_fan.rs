@@ -0,0 +1,295 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::analysis::wavefront::ReferenceSphere;
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RayFanSettings {
+    /// Rays across the pupil diameter in each fan.
+    pub samples: usize,
+    /// Surface the errors are measured on, the image surface when `None`.
+    pub surface: Option<usize>,
+}
+
+/// What a fan shows along the pupil axes.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FanQuantity {
+    /// Transverse ray error from the primary wavelength chief ray.
+    #[default]
+    RayError,
+    /// Optical path difference from the reference sphere of the wavelength, in waves.
+    OpticalPathDifference,
+}
+
+/// Ray errors of one wavelength along a pupil axis, vignetted rays are left out.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FanCurve {
+    pub wavelength: Wavelength,
+    /// Normalized pupil coordinate and the ray error there.
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Tangential fan: εy or OPD against py with px = 0. Sagittal fan: εx or OPD against px with
+/// py = 0.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldFan {
+    /// Row of the field table.
+    pub field_index: usize,
+    pub field: FieldRaw,
+    pub tangential: Vec<FanCurve>,
+    pub sagittal: Vec<FanCurve>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RayFan {
+    pub quantity: FanQuantity,
+    pub surface: usize,
+    pub fields: Vec<FieldFan>,
+}
+
+
+impl FanQuantity {
+    /// Factor from stored values to the displayed unit.
+    fn scale(&self) -> f64 {
+        match self {
+            FanQuantity::RayError => 1e3,
+            FanQuantity::OpticalPathDifference => 1.,
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            FanQuantity::RayError => "um",
+            FanQuantity::OpticalPathDifference => "waves",
+        }
+    }
+
+    /// Names of the tangential and the sagittal values.
+    fn labels(&self) -> (&'static str, &'static str) {
+        match self {
+            FanQuantity::RayError => ("EY", "EX"),
+            FanQuantity::OpticalPathDifference => ("WY", "WX"),
+        }
+    }
+}
+
+
+impl fmt::Display for FanQuantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FanQuantity::RayError => write!(f, "Ray fans"),
+            FanQuantity::OpticalPathDifference => write!(f, "OPD fans"),
+        }
+    }
+}
+
+
+impl Default for RayFanSettings {
+    fn default() -> Self {
+        RayFanSettings { samples: 21, surface: None }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Transverse ray aberration fans of every field and wavelength. Fields whose chief ray
+    /// fails are skipped. `None` when `settings.surface` is not a surface after the object.
+    pub fn ray_fan(&self, settings: &RayFanSettings) -> Option<RayFan> {
+        Some(self.fan(FanQuantity::RayError, settings.samples, self.checked_surface(settings.surface)?))
+    }
+
+    /// Optical path difference fans of every field and wavelength, always on the image surface.
+    /// `None` for a system without an image surface.
+    pub fn opd_fan(&self, settings: &RayFanSettings) -> Option<RayFan> {
+        Some(self.fan(FanQuantity::OpticalPathDifference, settings.samples, self.image_surface()?))
+    }
+
+    fn fan(&self, quantity: FanQuantity, samples: usize, surface: usize) -> RayFan {
+        let primary = self.primary_wavelength();
+        let wavelengths = self.parameters.wavelengths.wavelengths();
+        let pupil: Vec<f64> = (0..samples.max(2))
+            .map(|k| -1. + 2. * k as f64 / (samples.max(2) - 1) as f64)
+            .collect();
+
+        let fields = self.parameters.field_data.rows.iter().enumerate().filter_map(|(field_index, field)| {
+            let point = self.field_point(field)?;
+            let chief = self.field_ray_at_surface(field, point, 0., 0., primary, surface)?;
+            let spheres: Vec<Option<ReferenceSphere>> = match quantity {
+                FanQuantity::RayError => vec![None; wavelengths.len()],
+                FanQuantity::OpticalPathDifference => wavelengths.iter()
+                    .map(|&w| self.reference_sphere(field, point, w))
+                    .collect(),
+            };
+            let fan = |index: usize, tangential: bool| FanCurve {
+                wavelength: wavelengths[index],
+                points: pupil.iter().filter_map(|&p| {
+                    let (px, py) = if tangential { (0., p) } else { (p, 0.) };
+                    let ray = self.field_ray_at_surface(field, point, px, py, wavelengths[index], surface)?;
+                    let value = match quantity {
+                        FanQuantity::RayError if tangential => ray.origin.y - chief.origin.y,
+                        FanQuantity::RayError => ray.origin.x - chief.origin.x,
+                        FanQuantity::OpticalPathDifference => spheres[index]?.opd(&ray)?,
+                    };
+                    Some((p, value))
+                }).collect(),
+            };
+            Some(FieldFan {
+                field_index,
+                field: *field,
+                tangential: (0..wavelengths.len()).map(|index| fan(index, true)).collect(),
+                sagittal: (0..wavelengths.len()).map(|index| fan(index, false)).collect(),
+            })
+        }).collect();
+
+        RayFan { quantity, surface, fields }
+    }
+}
+
+
+impl RayFan {
+    /// Largest absolute value of all fans, the common scale of the plots.
+    pub fn max_error(&self) -> f64 {
+        self.fields.iter()
+            .flat_map(|fan| fan.tangential.iter().chain(fan.sagittal.iter()))
+            .flat_map(|curve| curve.points.iter())
+            .fold(0., |max, point| f64::max(max, point.1.abs()))
+    }
+
+    /// One row per field with the tangential fan on the left and the sagittal one on the right.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (900, 300 * self.fields.len().max(1) as u32))
+    }
+}
+
+
+impl Plot for RayFan {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let scale = self.quantity.scale();
+        let extent = (self.max_error() * scale).max(1e-6) * 1.1;
+        let (tangential, sagittal) = self.quantity.labels();
+        let panels = root.split_evenly((self.fields.len().max(1), 2));
+        for (index, fan) in self.fields.iter().enumerate() {
+            let sections = [(tangential, &fan.tangential, "PY"), (sagittal, &fan.sagittal, "PX")];
+            for (column, (error, curves, pupil)) in sections.into_iter().enumerate() {
+                let caption = format!("Field {}: {}, {}  {}", fan.field_index + 1, fan.field.xfield, fan.field.yfield, error);
+                let mut chart = ChartBuilder::on(&panels[2 * index + column])
+                    .caption(caption, ("sans-serif", 16))
+                    .margin(8)
+                    .x_label_area_size(30)
+                    .y_label_area_size(60)
+                    .build_cartesian_2d(-1f64..1f64, -extent..extent)?;
+                chart.configure_mesh()
+                    .x_desc(pupil)
+                    .y_desc(self.quantity.unit())
+                    .draw()?;
+                for (wavelength, curve) in curves.iter().enumerate() {
+                    let color = Palette99::pick(wavelength).to_rgba();
+                    chart.draw_series(LineSeries::new(curve.points.iter().map(|&(p, e)| (p, e * scale)), color))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for RayFan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} on surface {}, values in {}", self.quantity, self.surface, self.quantity.unit())?;
+        let (tangential, sagittal) = self.quantity.labels();
+        for fan in self.fields.iter() {
+            for (name, label, curves) in [("Tangential", tangential, &fan.tangential), ("Sagittal", sagittal, &fan.sagittal)] {
+                writeln!(f, "Field {}: {}, {}  {}, {}", fan.field_index + 1, fan.field.xfield, fan.field.yfield, name, label)?;
+                for curve in curves.iter() {
+                    write!(f, "  {} |", curve.wavelength)?;
+                    for (_, error) in curve.points.iter() {
+                        write!(f, " {:9.4}", error * self.quantity.scale())?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::fixtures;
+
+    #[test]
+    fn test_on_axis_fans() {
+        let fans = fixtures::visible(fixtures::singlet(57.), 12., 5.).ray_fan(&RayFanSettings::default()).unwrap();
+        assert_eq!(fans.fields.len(), 2);
+        let on_axis = &fans.fields[0];
+        assert_eq!(on_axis.tangential.len(), 3);
+        for (tangential, sagittal) in on_axis.tangential.iter().zip(on_axis.sagittal.iter()) {
+            let points = &tangential.points;
+            assert_eq!(points.len(), 21);
+            // odd in the pupil coordinate and the same along both axes on axis
+            assert_approx_eq!(points[10].1, 0., 1e-12);
+            for k in 0..21 {
+                assert_approx_eq!(points[k].1, -points[20 - k].1, 1e-12);
+                assert_approx_eq!(points[k].1, sagittal.points[k].1, 1e-12);
+            }
+        }
+        // undercorrected spherical aberration, the marginal rays focus short and cross the axis
+        let primary = &on_axis.tangential[1];
+        assert!(primary.points[20].1 < 0.);
+    }
+
+    #[test]
+    fn test_off_axis_fans_and_output() {
+        let fans = fixtures::visible(fixtures::singlet(57.), 12., 5.).ray_fan(&RayFanSettings { samples: 11, surface: None }).unwrap();
+        let off_axis = &fans.fields[1];
+        // the chief ray of the primary wavelength is the reference
+        assert_approx_eq!(off_axis.tangential[1].points[5].1, 0., 1e-12);
+        // lateral colour moves the chief rays of the other wavelengths apart
+        assert!(off_axis.tangential[0].points[5].1.abs() > 1e-6);
+        assert!(fans.max_error() > 0.);
+        assert_eq!(fans.to_string().lines().count(), 1 + 2 * 2 * 4);
+        let path = std::env::temp_dir().join("opaliha_ray_fan.png");
+        fans.plot(&path).unwrap();
+        assert!(path.metadata().unwrap().len() > 0);
+    }
+
+    #[test]
+    fn test_opd_fan() {
+        let system = fixtures::visible(fixtures::singlet(57.), 12., 5.);
+        let fans = system.opd_fan(&RayFanSettings { samples: 11, surface: None }).unwrap();
+        assert_eq!(fans.quantity, FanQuantity::OpticalPathDifference);
+        let primary = system.primary_wavelength();
+        let map = system.wavefront_map(&FieldRaw::new(0., 5.), primary, 11).unwrap();
+        for (k, &(py, opd)) in fans.fields[1].tangential[1].points.iter().enumerate() {
+            assert_approx_eq!(py, map.pupil_coordinate(k), 1e-12);
+            assert_approx_eq!(opd, map.values[k * 11 + 5].unwrap(), 1e-9);
+        }
+        // every wavelength is referred to its own chief ray and the on-axis fans are even
+        for curve in fans.fields[0].tangential.iter() {
+            assert_approx_eq!(curve.points[5].1, 0., 1e-9);
+            assert_approx_eq!(curve.points[0].1, curve.points[10].1, 1e-9);
+        }
+        assert!(fans.to_string().starts_with("OPD fans"));
+    }
+
+    #[test]
+    fn test_invalid_surface() {
+        let system = fixtures::visible(fixtures::singlet(57.), 12., 5.);
+        let image = system.surfaces.len() - 1;
+        assert!(system.ray_fan(&RayFanSettings { samples: 5, surface: Some(0) }).is_none());
+        assert!(system.ray_fan(&RayFanSettings { samples: 5, surface: Some(image + 1) }).is_none());
+        assert_eq!(system.ray_fan(&RayFanSettings { samples: 5, surface: Some(image) }).unwrap().surface, image);
+        assert!(SequentialOpticalSystem::default().opd_fan(&RayFanSettings::default()).is_none());
+    }
+}
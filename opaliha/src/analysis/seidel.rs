@@ -0,0 +1,301 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::pupils::ApertureError;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Seidel sums S_I..S_V and the chromatic sums C_I, C_II in Welford's notation.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SeidelCoefficients {
+    pub spherical: f64,
+    pub coma: f64,
+    pub astigmatism: f64,
+    pub petzval: f64,
+    pub distortion: f64,
+    pub axial_color: f64,
+    pub lateral_color: f64,
+}
+
+/// Third-order aberrations of an axially symmetric system, the paraxial marginal ray passes the
+/// edge of the entrance pupil and the chief ray goes to the largest field.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SeidelAberrations {
+    pub wavelength: Wavelength,
+    /// Entry `i` belongs to surface `i + 1`, up to the last refracting surface.
+    pub surfaces: Vec<SeidelCoefficients>,
+    pub total: SeidelCoefficients,
+}
+
+
+impl SeidelCoefficients {
+    pub const NAMES: [&'static str; 7] = ["SPHA", "COMA", "ASTI", "FCUR", "DIST", "CLA", "CTR"];
+
+    pub fn as_array(&self) -> [f64; 7] {
+        [
+            self.spherical, self.coma, self.astigmatism, self.petzval, self.distortion,
+            self.axial_color, self.lateral_color,
+        ]
+    }
+}
+
+
+impl Add for SeidelCoefficients {
+    type Output = SeidelCoefficients;
+
+    fn add(self, other: SeidelCoefficients) -> SeidelCoefficients {
+        SeidelCoefficients {
+            spherical: self.spherical + other.spherical,
+            coma: self.coma + other.coma,
+            astigmatism: self.astigmatism + other.astigmatism,
+            petzval: self.petzval + other.petzval,
+            distortion: self.distortion + other.distortion,
+            axial_color: self.axial_color + other.axial_color,
+            lateral_color: self.lateral_color + other.lateral_color,
+        }
+    }
+}
+
+
+impl Sum for SeidelCoefficients {
+    fn sum<I: Iterator<Item = SeidelCoefficients>>(iter: I) -> SeidelCoefficients {
+        iter.fold(SeidelCoefficients::default(), |sum, item| sum + item)
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Surface contributions from the refraction invariants A = n i and Ā = n ī of the paraxial
+    /// marginal and chief rays at the primary wavelength. The fourth-order deformation of conic
+    /// and aspheric surfaces adds 8 G Δn y⁴ to S_I and the stop-shifted terms to S_II, S_III
+    /// and S_V. Colour uses the dispersion between the ends of the wavelength band.
+    pub fn seidel_aberrations(&self) -> Result<SeidelAberrations, ApertureError> {
+        let wavelength = self.primary_wavelength();
+        let indices = self.indices_at(wavelength);
+        let (short, long) = self.parameters.wavelengths.band();
+        let dispersions: Vec<f64> = self.indices_at(short).iter()
+            .zip(self.indices_at(long))
+            .map(|(n_short, n_long)| n_short - n_long)
+            .collect();
+        let curvatures = self.curvatures();
+        let marginal = self.paraxial_marginal_ray(wavelength)?;
+        let field = self.largest_field_coordinate();
+        let chief: Vec<_> = self.paraxial_chief_ray(wavelength)?.iter()
+            .map(|ray| (ray.y * field, ray.nu * field))
+            .collect();
+        let lagrange = chief[1].1 * marginal[1].y - marginal[1].nu * chief[1].0;
+
+        let surfaces: Vec<SeidelCoefficients> = (1..=self.last_refracting_surface()).map(|k| {
+            let (n, n_next, c) = (indices[k - 1], indices[k], curvatures[k]);
+            let (y, y_bar) = (marginal[k].y, chief[k].0);
+            let a = marginal[k - 1].nu + n * y * c;
+            let a_bar = chief[k - 1].1 + n * y_bar * c;
+            let delta_u = marginal[k].nu / (n_next * n_next) - marginal[k - 1].nu / (n * n);
+            let delta_inverse = 1. / n_next - 1. / n;
+            let delta_inverse_square = 1. / (n_next * n_next) - 1. / (n * n);
+            let delta_dispersion = dispersions[k] / n_next - dispersions[k - 1] / n;
+            let aspheric = 8. * self.surfaces[k].fourth_order_deformation() * (n_next - n) * y;
+
+            SeidelCoefficients {
+                spherical: -a * a * y * delta_u + aspheric * y.powi(3),
+                coma: -a * a_bar * y * delta_u + aspheric * y * y * y_bar,
+                astigmatism: -a_bar * a_bar * y * delta_u + aspheric * y * y_bar * y_bar,
+                petzval: -lagrange * lagrange * c * delta_inverse,
+                // (Ā/A)(S_III + S_IV) written without the division, so that A = 0 is fine
+                distortion: -a_bar * (a_bar * a_bar * y * delta_inverse_square
+                    + c * y_bar * delta_inverse * (a * y_bar - 2. * a_bar * y))
+                    + aspheric * y_bar.powi(3),
+                axial_color: a * y * delta_dispersion,
+                lateral_color: a_bar * y * delta_dispersion,
+            }
+        }).collect();
+
+        let total = surfaces.iter().copied().sum();
+        Ok(SeidelAberrations { wavelength, surfaces, total })
+    }
+}
+
+
+impl SeidelAberrations {
+    /// Bar chart of the surface contributions with the totals as the last group.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (1024, 600))
+    }
+}
+
+
+impl Plot for SeidelAberrations {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let groups: Vec<[f64; 7]> = self.surfaces.iter().chain([&self.total])
+            .map(|coefficients| coefficients.as_array())
+            .collect();
+        let extent = groups.iter().flatten().fold(0f64, |m, v| m.max(v.abs())).max(1e-12) * 1.1;
+        let count = groups.len();
+
+        let mut chart = ChartBuilder::on(root)
+            .caption(format!("Seidel diagram, {}", self.wavelength), ("sans-serif", 24))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(-0.5..count as f64 - 0.5, -extent..extent)?;
+        chart.configure_mesh()
+            .disable_x_mesh()
+            .x_labels(count)
+            .x_label_formatter(&|x| {
+                let group = x.round() as usize;
+                if group + 1 == count { "Sum".to_string() } else { format!("{}", group + 1) }
+            })
+            .x_desc("Surface")
+            .draw()?;
+
+        let width = 0.8 / SeidelCoefficients::NAMES.len() as f64;
+        for (series, name) in SeidelCoefficients::NAMES.iter().enumerate() {
+            let color = Palette99::pick(series).to_rgba();
+            chart.draw_series(groups.iter().enumerate().map(|(group, values)| {
+                let left = group as f64 - 0.4 + series as f64 * width;
+                Rectangle::new([(left, 0.), (left + width, values[series])], color.filled())
+            }))?
+                .label(*name)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for SeidelAberrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Seidel aberration coefficients at {}", self.wavelength)?;
+        write!(f, "Surf")?;
+        for name in SeidelCoefficients::NAMES {
+            write!(f, " | {:>12}", name)?;
+        }
+        writeln!(f)?;
+        let rows = self.surfaces.iter().enumerate()
+            .map(|(index, coefficients)| (format!("{:>4}", index + 1), coefficients))
+            .chain([("TOT ".to_string(), &self.total)]);
+        for (label, coefficients) in rows {
+            write!(f, "{}", label)?;
+            for value in coefficients.as_array() {
+                write!(f, " | {:12.6}", value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::WavelengthTable;
+    use crate::geometry::point::Point3;
+    use crate::geometry::ray::{Ray3, RayValidity};
+    use crate::geometry::vector::Vector3;
+    use crate::materials::material::Air;
+    use crate::optical_system::parameters::{Aperture, ApertureType, FieldData, FieldRaw, FieldType};
+    use crate::optical_system::surfaces::EvenAsphereSurface;
+    use crate::optical_system::fixtures::{self, constant_glass as glass, surface};
+
+    /// Height of a real ray from the top of the entrance pupil in the paraxial image plane.
+    fn marginal_ray_height(system: &SequentialOpticalSystem) -> f64 {
+        let radius = system.entrance_pupil_radius(system.primary_wavelength()).unwrap();
+        let ray = Ray3::new(Point3 { x: 0., y: radius, z: -1. }, Vector3::unit_z());
+        let last = system.last_refracting_surface();
+        let ray = system.trace_ray_to(ray, last);
+        assert_eq!(ray.validity, RayValidity::VALID);
+        let image = system.first_order_properties().unwrap().paraxial_image_position;
+        ray.origin.y + (image - ray.origin.z) * ray.direction.y / ray.direction.z
+    }
+
+    #[test]
+    fn test_spherical_matches_real_ray() {
+        let system = fixtures::with_pupil_and_field(fixtures::lens(glass(), 55.), 3., 5.);
+        let seidel = system.seidel_aberrations().unwrap();
+        assert!(seidel.total.spherical > 0.);
+        let marginal = system.paraxial_marginal_ray(seidel.wavelength).unwrap();
+        let slope = marginal[2].nu;
+        // transverse aberration of the marginal ray is S_I / (2 n'u') up to fifth order
+        assert_approx_eq!(marginal_ray_height(&system), seidel.total.spherical / (2. * slope), 1e-5);
+    }
+
+    #[test]
+    fn test_petzval_and_stop_at_center_of_curvature() {
+        let system = fixtures::with_pupil_and_field(fixtures::lens(glass(), 55.), 3., 5.);
+        let seidel = system.seidel_aberrations().unwrap();
+        let lagrange = system.first_order_properties().unwrap().lagrange_invariant;
+        // the Petzval sum does not depend on the thickness, H² (n − 1)(c₁ − c₂) / n
+        assert_approx_eq!(seidel.total.petzval, lagrange * lagrange * 0.5 * (2. / 60.) / 1.5);
+        assert_eq!(seidel.surfaces.len(), 2);
+
+        // a surface concentric with the stop has Ā = 0
+        let mut concentric = SequentialOpticalSystem::default();
+        concentric.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        concentric.add_surface(surface(0., 30., Box::new(Air::default())));
+        concentric.add_surface(surface(-30., 40., glass()));
+        concentric.add_surface(surface(0., 0., glass()));
+        concentric.parameters.stop_surface = 1;
+        concentric.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 10.)]);
+        let surface = concentric.seidel_aberrations().unwrap().surfaces[1];
+        assert!(surface.spherical.abs() > 1e-6);
+        assert_approx_eq!(surface.coma, 0.);
+        assert_approx_eq!(surface.astigmatism, 0.);
+        assert_approx_eq!(surface.distortion, 0.);
+    }
+
+    #[test]
+    fn test_ellipsoid_has_no_spherical_aberration() {
+        // refraction from air into glass by an ellipsoid with k = −1/n² is free of spherical
+        // aberration for an object at infinity
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(Box::new(EvenAsphereSurface::new(20., -1. / 2.25, vec![], 60., glass())));
+        system.add_surface(surface(0., 0., glass()));
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: 16. };
+        let seidel = system.seidel_aberrations().unwrap();
+        assert_approx_eq!(seidel.total.spherical, 0.);
+        assert!(marginal_ray_height(&system).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_axial_color_matches_focal_shift() {
+        let mut system = fixtures::with_pupil_and_field(fixtures::singlet(55.), 3., 5.);
+        system.parameters.wavelengths = WavelengthTable::visible();
+        let seidel = system.seidel_aberrations().unwrap();
+        let (short, long) = system.parameters.wavelengths.band();
+        let focus = |wavelength| {
+            let marginal = system.paraxial_marginal_ray(wavelength).unwrap();
+            -marginal[2].y / marginal[2].nu
+        };
+        let slope = system.paraxial_marginal_ray(seidel.wavelength).unwrap()[2].nu;
+        // longitudinal colour −C_I / (n'u'²), linear in the dispersion
+        assert_approx_eq!(focus(short) - focus(long), -seidel.total.axial_color / (slope * slope), 1e-2);
+        assert!(seidel.total.lateral_color.abs() < seidel.total.axial_color.abs());
+    }
+
+    #[test]
+    fn test_plot_and_report() {
+        let seidel = fixtures::with_pupil_and_field(fixtures::lens(glass(), 55.), 3., 5.).seidel_aberrations().unwrap();
+        let directory = std::env::temp_dir();
+        for name in ["opaliha_seidel.png", "opaliha_seidel.svg"] {
+            let path = directory.join(name);
+            seidel.plot(&path).unwrap();
+            assert!(path.metadata().unwrap().len() > 0);
+        }
+        assert!(seidel.plot(directory.join("opaliha_seidel.txt")).is_err());
+        assert_eq!(seidel.to_string().lines().count(), 5);
+    }
+}
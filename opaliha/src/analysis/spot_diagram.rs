@@ -0,0 +1,366 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::pupil_sampling::PupilSampling;
+use crate::optical_system::ray_aiming::RayAimingError;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Point spot coordinates are measured from.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum SpotReference {
+    /// Real chief ray at the primary wavelength.
+    #[default]
+    ChiefRay,
+    /// Centroid of all rays weighted by the wavelength weights.
+    Centroid,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct SpotDiagramSettings {
+    pub sampling: PupilSampling,
+    pub reference: SpotReference,
+    /// Surface the spots are evaluated on, from 1 to the image surface, the image surface when
+    /// `None`.
+    pub surface: Option<usize>,
+}
+
+/// Ray position relative to the reference point, `wavelength` indexes the wavelength table.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SpotPoint {
+    pub x: f64,
+    pub y: f64,
+    pub wavelength: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldSpot {
+    /// Row of the field table.
+    pub field_index: usize,
+    pub field: FieldRaw,
+    /// Reference point (x, y) on the surface.
+    pub reference: (f64, f64),
+    /// The chief ray reference was asked for but the chief ray failed, `reference` is the
+    /// centroid instead.
+    pub chief_ray_failed: bool,
+    pub points: Vec<SpotPoint>,
+    /// Rays ray aiming could not find a start for.
+    pub aiming_failures: usize,
+    /// Rays that missed a surface or were reflected totally on the way.
+    pub failed_rays: usize,
+    /// Weighted RMS distance of the rays from the reference.
+    pub rms_radius: f64,
+    /// Distance of the farthest ray from the reference.
+    pub geometric_radius: f64,
+}
+
+/// Polychromatic spots of every field of the system. Clear semi-diameters do not clip the rays,
+/// rays are left out only when they can not be aimed or fail to reach the surface, each field
+/// counts them. Fields without a single ray on the surface are left out.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpotDiagram {
+    pub surface: usize,
+    pub wavelengths: Vec<Wavelength>,
+    /// 1.22 λ F/# of the paraxial marginal ray in image space, primary wavelength. Only for spots
+    /// on the image surface of a system with an entrance pupil.
+    pub airy_radius: Option<f64>,
+    pub fields: Vec<FieldSpot>,
+}
+
+
+impl SequentialOpticalSystem {
+    /// `None` when `settings.surface` is not a surface after the object.
+    pub fn spot_diagram(&self, settings: &SpotDiagramSettings) -> Option<SpotDiagram> {
+        let image = self.image_surface()?;
+        let surface = self.checked_surface(settings.surface)?;
+        let primary = self.primary_wavelength();
+        let airy_radius = (surface == image)
+            .then(|| self.image_space_aperture(primary).ok())
+            .flatten()
+            .map(|aperture| 0.61 * primary.mm() / aperture);
+        let table = &self.parameters.wavelengths;
+        let pupil = settings.sampling.points();
+
+        let fields = self.parameters.field_data.rows.iter().enumerate().filter_map(|(field_index, field)| {
+            let point = self.field_point(field)?;
+            let mut hits = Vec::new();
+            let (mut aiming_failures, mut failed_rays) = (0, 0);
+            for (index, entry) in table.entries().iter().enumerate() {
+                for &(px, py) in pupil.iter() {
+                    match self.traced_field_ray(field, point, px, py, entry.wavelength, surface) {
+                        Ok(ray) if ray.validity == RayValidity::VALID => {
+                            hits.push((ray.origin.x, ray.origin.y, index, entry.weight));
+                        }
+                        Ok(_) => failed_rays += 1,
+                        Err(_) => aiming_failures += 1,
+                    }
+                }
+            }
+            if hits.is_empty() { return None }
+
+            let total_weight: f64 = hits.iter().map(|hit| hit.3).sum();
+            let centroid = (
+                hits.iter().map(|hit| hit.0 * hit.3).sum::<f64>() / total_weight,
+                hits.iter().map(|hit| hit.1 * hit.3).sum::<f64>() / total_weight,
+            );
+            let chief = match settings.reference {
+                SpotReference::ChiefRay => Some(self.field_ray_at_surface(field, point, 0., 0., primary, surface)),
+                SpotReference::Centroid => None,
+            };
+            let chief_ray_failed = chief.is_some_and(|ray| ray.is_none());
+            let reference = chief.flatten().map_or(centroid, |ray| (ray.origin.x, ray.origin.y));
+            let points: Vec<SpotPoint> = hits.iter()
+                .map(|&(x, y, wavelength, _)| SpotPoint { x: x - reference.0, y: y - reference.1, wavelength })
+                .collect();
+            let square_sum: f64 = points.iter().zip(hits.iter())
+                .map(|(p, hit)| (p.x * p.x + p.y * p.y) * hit.3)
+                .sum();
+            Some(FieldSpot {
+                field_index,
+                field: *field,
+                reference,
+                chief_ray_failed,
+                aiming_failures,
+                failed_rays,
+                rms_radius: (square_sum / total_weight).sqrt(),
+                geometric_radius: points.iter().map(|p| p.x.hypot(p.y)).fold(0., f64::max),
+                points,
+            })
+        }).collect();
+
+        Some(SpotDiagram { surface, wavelengths: table.wavelengths(), airy_radius, fields })
+    }
+
+    /// Ray of the field through normalized pupil coordinates of the unvignetted pupil, traced
+    /// to `surface` at the given wavelength. `None` when the ray can not be aimed or fails on
+    /// the way, see `traced_field_ray` to tell the two apart.
+    pub(crate) fn field_ray_at_surface(
+        &self,
+        field: &FieldRaw,
+        point: FieldPoint,
+        px: f64,
+        py: f64,
+        wavelength: Wavelength,
+        surface: usize,
+    ) -> Option<Ray3> {
+        self.traced_field_ray(field, point, px, py, wavelength, surface).ok()
+            .filter(|ray| ray.validity == RayValidity::VALID)
+    }
+
+    /// `field_ray_at_surface` keeping the aiming error and the validity of the traced ray.
+    pub(crate) fn traced_field_ray(
+        &self,
+        field: &FieldRaw,
+        point: FieldPoint,
+        px: f64,
+        py: f64,
+        wavelength: Wavelength,
+        surface: usize,
+    ) -> Result<Ray3, RayAimingError> {
+        let (px, py) = field.vignetted_pupil(px, py);
+        let ray = self.aimed_ray(point, px, py)?.with_wavelength(wavelength);
+        Ok(self.trace_ray_to(ray, surface))
+    }
+
+    /// Ray of the field on the image surface carried on along its direction to the plane
+    /// `defocus` behind the image vertex plane, with the optical path of that stretch added.
+    pub(crate) fn defocused_image_ray(
+        &self,
+        field: &FieldRaw,
+        point: FieldPoint,
+        px: f64,
+        py: f64,
+        wavelength: Wavelength,
+        defocus: f64,
+    ) -> Option<Ray3> {
+        let image = self.image_surface()?;
+        let mut ray = self.field_ray_at_surface(field, point, px, py, wavelength, image)?;
+        if defocus == 0. { return Some(ray) }
+        let t = (self.surfaces[image].position().z + defocus - ray.origin.z) / ray.direction.z;
+        ray.optical_path += self.indices_at(wavelength)[image - 1] * t;
+        ray.origin = ray.at(t);
+        Some(ray)
+    }
+}
+
+
+impl SpotDiagram {
+    /// One panel per field in micrometres, rays coloured by wavelength, the Airy disk drawn as
+    /// a circle.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        let width = 360 * self.fields.len().max(1) as u32;
+        save_plot(self, path, (width, 420))
+    }
+}
+
+
+impl Plot for SpotDiagram {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let panels = root.split_evenly((1, self.fields.len().max(1)));
+        let airy = self.airy_radius.map_or(0., |radius| radius * 1e3);
+        for (spot, panel) in self.fields.iter().zip(panels.iter()) {
+            let extent = (spot.geometric_radius * 1e3).max(airy).max(1e-6) * 1.1;
+            let mut chart = ChartBuilder::on(panel)
+                .caption(format!("Field {}: {}, {}", spot.field_index + 1, spot.field.xfield, spot.field.yfield), ("sans-serif", 18))
+                .margin(10)
+                .x_label_area_size(50)
+                .y_label_area_size(50)
+                .build_cartesian_2d(-extent..extent, -extent..extent)?;
+            chart.configure_mesh()
+                .x_desc(format!("RMS {:.3} um, GEO {:.3} um", spot.rms_radius * 1e3, spot.geometric_radius * 1e3))
+                .draw()?;
+            for wavelength in 0..self.wavelengths.len() {
+                let color = Palette99::pick(wavelength).to_rgba();
+                chart.draw_series(spot.points.iter()
+                    .filter(|p| p.wavelength == wavelength)
+                    .map(|p| Circle::new((p.x * 1e3, p.y * 1e3), 2, color.filled())))?;
+            }
+            if airy > 0. && airy.is_finite() {
+                chart.draw_series(LineSeries::new(
+                    (0..=100).map(|k| {
+                        let (sin, cos) = (2. * PI * k as f64 / 100.).sin_cos();
+                        (airy * cos, airy * sin)
+                    }),
+                    &BLACK,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for SpotDiagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.airy_radius {
+            Some(radius) => writeln!(f, "Spot diagram on surface {}, Airy radius {:.4} um", self.surface, radius * 1e3)?,
+            None => writeln!(f, "Spot diagram on surface {}", self.surface)?,
+        }
+        writeln!(f, "Field |    x     |    y     | RMS radius, um | GEO radius, um | Rays | Failed | Not aimed | Chief ray")?;
+        for spot in self.fields.iter() {
+            writeln!(
+                f, "{:5} | {:8.3} | {:8.3} | {:14.4} | {:14.4} | {:4} | {:6} | {:9} | {}",
+                spot.field_index + 1, spot.field.xfield, spot.field.yfield,
+                spot.rms_radius * 1e3, spot.geometric_radius * 1e3, spot.points.len(),
+                spot.failed_rays, spot.aiming_failures, if spot.chief_ray_failed { "failed" } else { "" },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::WavelengthTable;
+    use crate::materials::material::Air;
+    use crate::optical_system::parameters::{Aperture, ApertureType, FieldData, FieldType};
+    use crate::optical_system::surfaces::EvenAsphereSurface;
+    use crate::optical_system::fixtures::{bk7, constant_glass as glass, front_stop_lens, surface};
+
+    /// Singlet behind a stop in front of it, object at infinity, EPD 10 mm.
+    fn singlet() -> SequentialOpticalSystem {
+        let mut system = front_stop_lens(bk7());
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., 5.)]);
+        system
+    }
+
+    #[test]
+    fn test_spot_on_stop_is_the_pupil() {
+        let system = singlet();
+        let settings = SpotDiagramSettings { sampling: PupilSampling::Square { size: 41 }, surface: Some(1), ..Default::default() };
+        let spots = system.spot_diagram(&settings).unwrap();
+        assert_eq!(spots.airy_radius, None);
+        let on_axis = &spots.fields[0];
+        assert_approx_eq!(on_axis.geometric_radius, 5., 1e-9);
+        // uniformly filled disk of radius R has an RMS radius R / √2
+        assert_approx_eq!(on_axis.rms_radius, 5. / 2f64.sqrt(), 5e-2);
+    }
+
+    #[test]
+    fn test_references_and_radii() {
+        let mut system = singlet();
+        system.parameters.wavelengths = WavelengthTable::visible();
+        let chief = system.spot_diagram(&SpotDiagramSettings::default()).unwrap();
+        let centroid = system.spot_diagram(&SpotDiagramSettings { reference: SpotReference::Centroid, ..Default::default() }).unwrap();
+        assert_eq!(chief.fields.len(), 2);
+        assert_eq!(chief.fields[1].points.len(), 3 * 127);
+        for (chief, centroid) in chief.fields.iter().zip(centroid.fields.iter()) {
+            assert!(centroid.rms_radius <= chief.rms_radius + 1e-12);
+            assert!(chief.rms_radius <= chief.geometric_radius);
+        }
+        assert_approx_eq!(chief.fields[0].reference.1, 0.);
+        let first_order = system.first_order_properties().unwrap();
+        assert!(chief.fields.iter().all(|spot| !spot.chief_ray_failed && spot.failed_rays == 0 && spot.aiming_failures == 0));
+        assert_approx_eq!(chief.airy_radius.unwrap(), 1.22 * first_order.wavelength.mm() * first_order.working_f_number);
+    }
+
+    #[test]
+    fn test_ellipsoid_is_diffraction_limited() {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(Box::new(EvenAsphereSurface::new(20., -1. / 2.25, vec![], 60., glass())));
+        system.add_surface(surface(0., 0., glass()));
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: 16. };
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.)]);
+        let settings = SpotDiagramSettings { sampling: PupilSampling::Dithered { size: 15, seed: 1 }, ..Default::default() };
+        let spots = system.spot_diagram(&settings).unwrap();
+        assert!(spots.fields[0].geometric_radius < 1e-9);
+        assert!(spots.airy_radius.unwrap() > 1e-4);
+    }
+
+    #[test]
+    fn test_surface_out_of_range() {
+        let system = singlet();
+        assert!(system.spot_diagram(&SpotDiagramSettings { surface: Some(0), ..Default::default() }).is_none());
+        assert!(system.spot_diagram(&SpotDiagramSettings { surface: Some(5), ..Default::default() }).is_none());
+        assert!(SequentialOpticalSystem::default().spot_diagram(&SpotDiagramSettings::default()).is_none());
+    }
+
+    #[test]
+    fn test_failed_rays_are_counted() {
+        // rays above 4 mm miss the sphere of the first surface
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(surface(4., 5., glass()));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: 10. };
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.)]);
+        let settings = SpotDiagramSettings { sampling: PupilSampling::Square { size: 11 }, ..Default::default() };
+        let spot = &system.spot_diagram(&settings).unwrap().fields[0];
+        let launched = settings.sampling.points().len();
+        assert!(spot.failed_rays > 0);
+        assert_eq!(spot.points.len() + spot.failed_rays + spot.aiming_failures, launched);
+    }
+
+    #[test]
+    fn test_unreachable_field_keeps_the_labels() {
+        let mut system = singlet();
+        let rows = vec![FieldRaw::new(0., 1e6), FieldRaw::new(0., 0.)];
+        system.parameters.field_data = FieldData::new(FieldType::RealImageHeight, rows);
+        let spots = system.spot_diagram(&SpotDiagramSettings::default()).unwrap();
+        assert_eq!(spots.fields.len(), 1);
+        assert_eq!(spots.fields[0].field_index, 1);
+        assert!(spots.to_string().lines().nth(2).unwrap().starts_with("    2 |"));
+    }
+
+    #[test]
+    fn test_plot() {
+        let spots = singlet().spot_diagram(&SpotDiagramSettings::default()).unwrap();
+        let path = std::env::temp_dir().join("opaliha_spots.svg");
+        spots.plot(&path).unwrap();
+        assert!(path.metadata().unwrap().len() > 0);
+        assert_eq!(spots.to_string().lines().count(), 4);
+    }
+}
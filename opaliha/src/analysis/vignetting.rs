@@ -0,0 +1,159 @@
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::geometry::ray::RayValidity;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::pupil_sampling::PupilSampling;
+use crate::optical_system::ray_generation::RaySet;
+use crate::optical_system::ray_path::RayPath;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Fate of the rays launched from one field point.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VignettingPoint {
+    /// Rays launched, including those that could not be aimed.
+    pub total: usize,
+    pub transmitted: usize,
+    /// Rays stopped by the clear aperture of each surface, indexed by surface.
+    pub clipped: Vec<usize>,
+    /// Rays that could not be aimed, missed a surface or were reflected totally.
+    pub failed: usize,
+}
+
+/// Transmitted fraction of the primary wavelength along the y field, entries follow `fields`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Vignetting {
+    /// Y field values in the units of the field table.
+    pub fields: Vec<f64>,
+    pub points: Vec<VignettingPoint>,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Traces the whole pupil at `field_samples` points from the axis to the largest y field
+    /// and stops each ray at the first surface whose semi-diameter it exceeds. Surfaces
+    /// without a semi-diameter do not clip. `None` for a system without an image surface.
+    pub fn vignetting(&self, field_samples: usize, sampling: PupilSampling) -> Option<Vignetting> {
+        let count = field_samples.max(2);
+        let max_field = self.parameters.field_data.max_field();
+        let fields: Vec<f64> = (0..count).map(|k| max_field * k as f64 / (count - 1) as f64).collect();
+        let last = self.image_surface()?;
+        let total = sampling.points().len();
+        let primary = self.primary_wavelength();
+
+        let points = fields.iter().map(|&y| {
+            let mut point = VignettingPoint { total, transmitted: 0, clipped: vec![0; self.surfaces.len()], failed: total };
+            let Some(beam) = self.generate_beam(&FieldRaw::new(0., y), primary, &RaySet::FullPupil(sampling)) else {
+                return point
+            };
+            point.failed = total - beam.rays.len();
+            for generated in beam.rays {
+                let path = self.trace_path(generated.ray, last);
+                match self.clipping_surface(&path) {
+                    Some(surface) => point.clipped[surface] += 1,
+                    None if path.validity == RayValidity::VALID => point.transmitted += 1,
+                    None => point.failed += 1,
+                }
+            }
+            point
+        }).collect();
+        Some(Vignetting { fields, points })
+    }
+
+    /// First surface the path passes outside the clear aperture of.
+    fn clipping_surface(&self, path: &RayPath) -> Option<usize> {
+        path.records.iter().find(|record| {
+            self.surfaces[record.surface].semi_diameter()
+                .is_some_and(|semi_diameter| record.point.x.hypot(record.point.y) > semi_diameter + 1e-9)
+        }).map(|record| record.surface)
+    }
+}
+
+
+impl VignettingPoint {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 { return 0. }
+        self.transmitted as f64 / self.total as f64
+    }
+}
+
+
+impl Vignetting {
+    /// Surfaces that clip any ray.
+    pub fn clipping_surfaces(&self) -> Vec<usize> {
+        let surfaces = self.points.first().map_or(0, |point| point.clipped.len());
+        (0..surfaces).filter(|&k| self.points.iter().any(|point| point.clipped[k] > 0)).collect()
+    }
+
+    /// Transmitted fraction against the y field.
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (600, 400))
+    }
+}
+
+
+impl Plot for Vignetting {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let top = self.fields.last().copied().unwrap_or(0.).max(1e-9);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Vignetting", ("sans-serif", 20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0f64..top, 0f64..1.05)?;
+        chart.configure_mesh()
+            .x_desc("Y field")
+            .y_desc("Transmitted fraction")
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            self.fields.iter().zip(self.points.iter()).map(|(&y, point)| (y, point.fraction())),
+            BLUE,
+        ))?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for Vignetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Vignetting, {} rays per field", self.points.first().map_or(0, |point| point.total))?;
+        writeln!(f, "  Y field | Transmitted, % | Failed | Clipped by")?;
+        for (y, point) in self.fields.iter().zip(self.points.iter()) {
+            let clipped: Vec<String> = point.clipped.iter().enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(|(surface, count)| format!("S{}: {}", surface, count))
+                .collect();
+            writeln!(f, "{:9.4} | {:14.2} | {:6} | {}", y, point.fraction() * 100., point.failed, clipped.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optical_system::fixtures::{apertured_singlet, visible};
+
+    #[test]
+    fn test_rear_surface_clips_off_axis() {
+        let system = visible(apertured_singlet(57., 6., 5.5), 10., 20.);
+
+        let vignetting = system.vignetting(5, PupilSampling::Square { size: 15 }).unwrap();
+        let fractions: Vec<f64> = vignetting.points.iter().map(VignettingPoint::fraction).collect();
+        // the stop sits on the front surface, the oblique beams walk off the rear one
+        assert_eq!(fractions[0], 1.);
+        assert!(fractions[4] < 1.);
+        assert!(fractions.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(vignetting.clipping_surfaces(), vec![2]);
+        let point = &vignetting.points[4];
+        assert_eq!(point.transmitted + point.failed + point.clipped.iter().sum::<usize>(), point.total);
+        assert_eq!(vignetting.to_string().lines().count(), 2 + 5);
+        vignetting.plot(std::env::temp_dir().join("opaliha_vignetting.png")).unwrap();
+    }
+}
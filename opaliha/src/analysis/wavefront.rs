@@ -0,0 +1,247 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::common::plotting::{save_plot, Plot, PlotResult};
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::Ray3;
+use crate::geometry::vector::Vector3;
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Sphere centred on the chief ray image point and passing through the exit pupil, optical
+/// path differences are measured on it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReferenceSphere {
+    pub center: Point3,
+    /// Signed distance along the chief ray from the image point to the exit pupil, negative
+    /// for an exit pupil in front of the image. Infinite for a telecentric image space, the
+    /// sphere is then the plane through the image point normal to the chief ray.
+    pub radius: f64,
+    pub chief_direction: Vector3,
+    /// Optical path of the chief ray up to the sphere.
+    pub chief_optical_path: f64,
+    /// Refraction index of image space.
+    pub index: f64,
+    pub wavelength: Wavelength,
+}
+
+/// Optical path difference over the pupil of one field and wavelength in waves.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WavefrontMap {
+    pub field: FieldRaw,
+    pub wavelength: Wavelength,
+    /// Samples across the pupil diameter in both directions.
+    pub size: usize,
+    /// Row by row from py = -1 upwards, px = -1 first in a row. `None` outside the pupil and
+    /// for failed rays.
+    pub values: Vec<Option<f64>>,
+}
+
+
+impl ReferenceSphere {
+    /// Optical path difference of a ray traced to the image surface, in waves. Positive when
+    /// the ray reaches the sphere with a shorter path than the chief ray.
+    pub fn opd(&self, ray: &Ray3) -> Option<f64> {
+        let offset = ray.origin - self.center;
+        let t = if self.radius.is_finite() {
+            let b = ray.direction.dot(offset);
+            let discriminant = b * b - (offset.dot(offset) - self.radius * self.radius);
+            if discriminant < 0. { return None }
+            if self.radius < 0. { -b - discriminant.sqrt() } else { -b + discriminant.sqrt() }
+        } else {
+            -self.chief_direction.dot(offset) / ray.direction.dot(self.chief_direction)
+        };
+        let path = ray.optical_path + self.index * t;
+        Some((self.chief_optical_path - path) / self.wavelength.mm())
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Reference sphere of the field at the wavelength, built from the real chief ray and the
+    /// paraxial exit pupil position.
+    pub fn reference_sphere(&self, field: &FieldRaw, point: FieldPoint, wavelength: Wavelength) -> Option<ReferenceSphere> {
+        self.defocused_reference_sphere(field, point, wavelength, 0.)
+    }
+
+    /// Wavefront of the field on a square pupil grid of `size` samples across. `None` when the
+    /// chief ray fails.
+    pub fn wavefront_map(&self, field: &FieldRaw, wavelength: Wavelength, size: usize) -> Option<WavefrontMap> {
+        self.defocused_wavefront_map(field, wavelength, size, 0.)
+    }
+
+    /// Reference sphere centred on the chief ray in the image plane shifted by `defocus`.
+    pub(crate) fn defocused_reference_sphere(
+        &self,
+        field: &FieldRaw,
+        point: FieldPoint,
+        wavelength: Wavelength,
+        defocus: f64,
+    ) -> Option<ReferenceSphere> {
+        let chief = self.defocused_image_ray(field, point, 0., 0., wavelength, defocus)?;
+        let radius = (self.pupils().ok()?.exit_position - chief.origin.z) / chief.direction.z;
+        let index = self.indices_at(wavelength)[self.last_refracting_surface()];
+        let chief_optical_path = if radius.is_finite() { chief.optical_path + index * radius } else { chief.optical_path };
+        Some(ReferenceSphere {
+            center: chief.origin,
+            radius,
+            chief_direction: chief.direction,
+            chief_optical_path,
+            index,
+            wavelength,
+        })
+    }
+
+    /// Wavefront against the reference sphere of the image plane shifted by `defocus`.
+    pub(crate) fn defocused_wavefront_map(
+        &self,
+        field: &FieldRaw,
+        wavelength: Wavelength,
+        size: usize,
+        defocus: f64,
+    ) -> Option<WavefrontMap> {
+        let size = size.max(2);
+        let point = self.field_point(field)?;
+        let sphere = self.defocused_reference_sphere(field, point, wavelength, defocus)?;
+        let coordinate = |k: usize| -1. + 2. * k as f64 / (size - 1) as f64;
+        let values = (0..size * size).map(|k| {
+            let (px, py) = (coordinate(k % size), coordinate(k / size));
+            if px.hypot(py) > 1. + 1e-12 { return None }
+            let ray = self.defocused_image_ray(field, point, px, py, wavelength, defocus)?;
+            sphere.opd(&ray)
+        }).collect();
+        Some(WavefrontMap { field: *field, wavelength, size, values })
+    }
+
+    /// Wavefront maps of every field at the primary wavelength.
+    pub fn wavefront_maps(&self, size: usize) -> Vec<WavefrontMap> {
+        let primary = self.primary_wavelength();
+        self.parameters.field_data.rows.iter()
+            .filter_map(|field| self.wavefront_map(field, primary, size))
+            .collect()
+    }
+}
+
+
+impl WavefrontMap {
+    /// Normalized pupil coordinate of a row or column.
+    pub fn pupil_coordinate(&self, index: usize) -> f64 {
+        -1. + 2. * index as f64 / (self.size - 1) as f64
+    }
+
+    /// Valid samples as (px, py, OPD).
+    pub fn samples(&self) -> impl Iterator<Item = (f64, f64, f64)> + '_ {
+        self.values.iter().enumerate().filter_map(|(k, value)| {
+            value.map(|opd| (self.pupil_coordinate(k % self.size), self.pupil_coordinate(k / self.size), opd))
+        })
+    }
+
+    pub fn peak_to_valley(&self) -> f64 {
+        let (min, max) = self.samples().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), s| (min.min(s.2), max.max(s.2)));
+        if min.is_finite() { max - min } else { 0. }
+    }
+
+    /// Root mean square about the mean OPD, piston removed.
+    pub fn rms(&self) -> f64 {
+        let (count, sum, square_sum) = self.samples()
+            .fold((0., 0., 0.), |(n, s, q), sample| (n + 1., s + sample.2, q + sample.2 * sample.2));
+        if count == 0. { return 0. }
+        let mean = sum / count;
+        (square_sum / count - mean * mean).max(0.).sqrt()
+    }
+
+    /// Maréchal estimate exp(-(2π σ)²), good for RMS errors up to about 0.1 wave.
+    pub fn strehl_ratio(&self) -> f64 {
+        (-(2. * PI * self.rms()).powi(2)).exp()
+    }
+
+    pub fn plot<P: AsRef<Path>>(&self, path: P) -> PlotResult {
+        save_plot(self, path, (640, 600))
+    }
+}
+
+
+impl Plot for WavefrontMap {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static,
+    {
+        let (min, max) = self.samples().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), s| (min.min(s.2), max.max(s.2)));
+        let span = if max > min { max - min } else { 1. };
+        let caption = format!(
+            "Field {}, {}  {}  P-V {:.4}, RMS {:.4} waves",
+            self.field.xfield, self.field.yfield, self.wavelength, self.peak_to_valley(), self.rms(),
+        );
+        let mut chart = ChartBuilder::on(root)
+            .caption(caption, ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(-1f64..1f64, -1f64..1f64)?;
+        chart.configure_mesh()
+            .disable_mesh()
+            .x_desc("PX")
+            .y_desc("PY")
+            .draw()?;
+        let half = 1. / (self.size - 1) as f64;
+        chart.draw_series(self.samples().map(|(px, py, opd)| {
+            let level = (opd - min) / span;
+            let color = HSLColor(2. / 3. * (1. - level), 0.8, 0.5);
+            Rectangle::new([(px - half, py - half), (px + half, py + half)], color.filled())
+        }))?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for WavefrontMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Wavefront of field {}, {} at {}, waves", self.field.xfield, self.field.yfield, self.wavelength)?;
+        writeln!(f, "P-V    {:.6}", self.peak_to_valley())?;
+        writeln!(f, "RMS    {:.6}", self.rms())?;
+        writeln!(f, "Strehl {:.6}", self.strehl_ratio())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::fixtures::{focused_singlet, with_pupil_and_field};
+
+    #[test]
+    fn test_spherical_wavefront() {
+        let system = with_pupil_and_field(focused_singlet(), 6., 3.);
+        let primary = system.primary_wavelength();
+        let map = system.wavefront_map(&FieldRaw::new(0., 0.), primary, 65).unwrap();
+        let w040 = system.seidel_aberrations().unwrap().total.spherical / 8. / primary.mm();
+        let edge = map.values[32 * 65 + 64].unwrap();
+        assert_approx_eq!(map.values[32 * 65 + 32].unwrap(), 0., 1e-9);
+        assert_approx_eq!(edge, w040, 2e-2 * w040.abs());
+        assert_approx_eq!(map.values[64 * 65 + 32].unwrap(), edge, 1e-9);
+        // W = a ρ⁴ on a uniform disk has σ = 2a / √45
+        assert_approx_eq!(map.peak_to_valley(), edge.abs(), 1e-9);
+        assert_approx_eq!(map.rms(), 2. * edge.abs() / 45f64.sqrt(), 2e-2 * edge.abs());
+        assert_approx_eq!(map.strehl_ratio(), (-(2. * PI * map.rms()).powi(2)).exp(), 1e-12);
+    }
+
+    #[test]
+    fn test_field_maps_and_plot() {
+        let maps = with_pupil_and_field(focused_singlet(), 2., 3.).wavefront_maps(33);
+        assert_eq!(maps.len(), 2);
+        let off_axis = &maps[1];
+        assert_approx_eq!(off_axis.values[16 * 33 + 16].unwrap(), 0., 1e-9);
+        assert_eq!(off_axis.values.iter().filter(|v| v.is_some()).count(), off_axis.samples().count());
+        assert!(off_axis.rms() > maps[0].rms());
+        assert!(off_axis.strehl_ratio() > 0.5);
+        assert_eq!(off_axis.to_string().lines().count(), 4);
+        let path = std::env::temp_dir().join("opaliha_wavefront.png");
+        off_axis.plot(&path).unwrap();
+        assert!(path.metadata().unwrap().len() > 0);
+    }
+}
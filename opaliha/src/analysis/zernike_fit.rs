@@ -0,0 +1,108 @@
+use std::fmt;
+use crate::analysis::wavefront::WavefrontMap;
+use crate::math::least_squares::least_squares;
+use crate::math::zernike::{ZernikeBasis, ZernikeOrdering};
+
+/// Least-squares Zernike fit of a wavefront map, coefficients in waves.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZernikeFit {
+    pub basis: ZernikeBasis,
+    pub coefficients: Vec<f64>,
+    /// RMS of the map minus the fit over the fitted samples.
+    pub residual_rms: f64,
+    /// Fit evaluated on the grid of the map, `None` where the map has no value or inside the
+    /// obscuration.
+    pub reconstructed: WavefrontMap,
+}
+
+
+impl WavefrontMap {
+    /// Fits the first `terms` polynomials of the ordering to the samples with ρ ≥ `obscuration`.
+    /// `None` when there are too few samples for the terms.
+    pub fn fit_zernike(&self, ordering: ZernikeOrdering, terms: usize, obscuration: f64) -> Option<ZernikeFit> {
+        let basis = ZernikeBasis::new(ordering, terms, obscuration);
+        let inside = |px: f64, py: f64| px.hypot(py) >= obscuration - 1e-12;
+        let samples: Vec<(f64, f64, f64)> = self.samples().filter(|s| inside(s.0, s.1)).collect();
+        let rows: Vec<Vec<f64>> = samples.iter().map(|s| basis.values(s.0, s.1)).collect();
+        let values: Vec<f64> = samples.iter().map(|s| s.2).collect();
+        let coefficients = least_squares(&rows, &values)?;
+
+        let evaluate = |row: &[f64]| row.iter().zip(coefficients.iter()).map(|(z, c)| z * c).sum::<f64>();
+        let square_sum: f64 = rows.iter().zip(values.iter()).map(|(row, value)| (value - evaluate(row)).powi(2)).sum();
+        let residual_rms = (square_sum / samples.len() as f64).sqrt();
+        let reconstructed_values = self.values.iter().enumerate().map(|(k, value)| {
+            let (px, py) = (self.pupil_coordinate(k % self.size), self.pupil_coordinate(k / self.size));
+            value.filter(|_| inside(px, py)).map(|_| evaluate(&basis.values(px, py)))
+        }).collect();
+
+        Some(ZernikeFit {
+            reconstructed: WavefrontMap { values: reconstructed_values, ..self.clone() },
+            basis,
+            coefficients,
+            residual_rms,
+        })
+    }
+}
+
+
+impl fmt::Display for ZernikeFit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} Zernike fit, obscuration {}, waves", self.basis.ordering, self.basis.obscuration)?;
+        for (index, (term, coefficient)) in self.basis.terms().iter().zip(self.coefficients.iter()).enumerate() {
+            writeln!(f, "Z{:<3} n {:2} m {:3} | {:12.6}", index + 1, term.n, term.m, coefficient)?;
+        }
+        writeln!(f, "Residual RMS {:.6}", self.residual_rms)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::LINE_D;
+    use crate::optical_system::parameters::FieldRaw;
+
+    /// Map of `f(px, py)` on a 41 × 41 grid over the unit disk.
+    fn map(f: impl Fn(f64, f64) -> f64) -> WavefrontMap {
+        let size = 41;
+        let coordinate = |k: usize| -1. + 2. * k as f64 / (size - 1) as f64;
+        let values = (0..size * size).map(|k| {
+            let (px, py) = (coordinate(k % size), coordinate(k / size));
+            (px.hypot(py) <= 1.).then(|| f(px, py))
+        }).collect();
+        WavefrontMap { field: FieldRaw::new(0., 0.), wavelength: LINE_D, size, values }
+    }
+
+    #[test]
+    fn test_fringe_spherical() {
+        // ρ⁴ = Z9 / 6 + Z4 / 2 + Z1 / 3 in Fringe terms and the tilt y is Z3
+        let wavefront = map(|x, y| 0.6 * (x * x + y * y).powi(2) + 0.1 * y);
+        let fit = wavefront.fit_zernike(ZernikeOrdering::Fringe, 16, 0.).unwrap();
+        assert_approx_eq!(fit.coefficients[0], 0.2, 1e-9);
+        assert_approx_eq!(fit.coefficients[2], 0.1, 1e-9);
+        assert_approx_eq!(fit.coefficients[3], 0.3, 1e-9);
+        assert_approx_eq!(fit.coefficients[8], 0.1, 1e-9);
+        assert!(fit.residual_rms < 1e-12);
+        assert_approx_eq!(fit.reconstructed.rms(), wavefront.rms(), 1e-9);
+        assert_eq!(fit.to_string().lines().count(), 18);
+    }
+
+    #[test]
+    fn test_annular_fit() {
+        let obscuration = 0.3;
+        let basis = ZernikeBasis::new(ZernikeOrdering::Standard, 11, obscuration);
+        let wavefront = map(|x, y| {
+            let values = basis.values(x, y);
+            0.05 * values[3] - 0.02 * values[6] + 0.01 * values[10]
+        });
+        let fit = wavefront.fit_zernike(ZernikeOrdering::Standard, 11, obscuration).unwrap();
+        assert_approx_eq!(fit.coefficients[3], 0.05, 1e-9);
+        assert_approx_eq!(fit.coefficients[6], -0.02, 1e-9);
+        assert_approx_eq!(fit.coefficients[10], 0.01, 1e-9);
+        assert!(fit.reconstructed.values[20 * 41 + 20].is_none());
+        // a truncated fit leaves the higher orders in the residual
+        let truncated = wavefront.fit_zernike(ZernikeOrdering::Standard, 10, obscuration).unwrap();
+        assert!(truncated.residual_rms > 5e-3);
+    }
+}
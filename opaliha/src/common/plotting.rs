@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+pub type PlotResult = Result<(), Box<dyn Error>>;
+
+/// Something that can draw itself on any plotters backend.
+pub trait Plot {
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> PlotResult
+    where
+        DB::ErrorType: 'static;
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnsupportedPlotFormat(pub String);
+
+
+impl fmt::Display for UnsupportedPlotFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported plot format '{}', expected png or svg", self.0)
+    }
+}
+
+
+impl Error for UnsupportedPlotFormat {}
+
+
+/// Renders the plot into a PNG or SVG file chosen by the extension of `path`.
+pub fn save_plot<T: Plot, P: AsRef<Path>>(plot: &T, path: P, size: (u32, u32)) -> PlotResult {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "png" => render(plot, BitMapBackend::new(path, size).into_drawing_area()),
+        "svg" => render(plot, SVGBackend::new(path, size).into_drawing_area()),
+        _ => Err(Box::new(UnsupportedPlotFormat(extension))),
+    }
+}
+
+
+fn render<T: Plot, DB: DrawingBackend>(plot: &T, root: DrawingArea<DB, Shift>) -> PlotResult
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    plot.draw(&root)?;
+    root.present()?;
+    Ok(())
+}
@@ -1,5 +1,184 @@
-const HeNe: f64 = 632.8;
-const Argon: f64 = 488.0;
-const NdYag1: f64 = 1064.1;
-const NdYag2: f64 = 532.;
-const NdYag3: f64 = 355.;
+use std::fmt;
+
+/// Vacuum wavelength. Stored in micrometres, the unit of dispersion formulas.
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub struct Wavelength {
+    micrometers: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WeightedWavelength {
+    pub wavelength: Wavelength,
+    pub weight: f64,
+}
+
+/// Wavelengths the system is evaluated at; `primary` indexes the one used for
+/// first-order properties, pupils and field definitions. Never empty, `primary` is always
+/// in range.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WavelengthTable {
+    entries: Vec<WeightedWavelength>,
+    primary: usize,
+}
+
+
+impl Wavelength {
+    pub const fn from_nm(nanometers: f64) -> Wavelength {
+        Wavelength { micrometers: nanometers / 1000. }
+    }
+
+    pub const fn from_um(micrometers: f64) -> Wavelength {
+        Wavelength { micrometers }
+    }
+
+    pub fn nm(&self) -> f64 {
+        self.micrometers * 1000.
+    }
+
+    pub fn um(&self) -> f64 {
+        self.micrometers
+    }
+
+    /// Wavelength in millimetres, the default lens unit.
+    pub fn mm(&self) -> f64 {
+        self.micrometers / 1000.
+    }
+}
+
+
+impl fmt::Display for Wavelength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4} um", self.micrometers)
+    }
+}
+
+
+pub const HE_NE: Wavelength = Wavelength::from_nm(632.8);
+pub const ARGON: Wavelength = Wavelength::from_nm(488.0);
+pub const ND_YAG_1: Wavelength = Wavelength::from_nm(1064.1);
+pub const ND_YAG_2: Wavelength = Wavelength::from_nm(532.);
+pub const ND_YAG_3: Wavelength = Wavelength::from_nm(355.);
+
+/// Fraunhofer lines: F (H), d (He), C (H), e (Hg), g (Hg), h (Hg).
+pub const LINE_F: Wavelength = Wavelength::from_nm(486.1327);
+pub const LINE_D: Wavelength = Wavelength::from_nm(587.5618);
+pub const LINE_C: Wavelength = Wavelength::from_nm(656.2725);
+pub const LINE_E: Wavelength = Wavelength::from_nm(546.0740);
+pub const LINE_G: Wavelength = Wavelength::from_nm(435.8343);
+pub const LINE_H: Wavelength = Wavelength::from_nm(404.6561);
+
+
+impl WeightedWavelength {
+    pub fn new(wavelength: Wavelength) -> WeightedWavelength {
+        WeightedWavelength { wavelength, weight: 1. }
+    }
+}
+
+
+impl WavelengthTable {
+    /// Table of unit weight wavelengths, `None` when `primary` is out of range.
+    pub fn new(wavelengths: &[Wavelength], primary: usize) -> Option<WavelengthTable> {
+        WavelengthTable::weighted(wavelengths.iter().map(|w| WeightedWavelength::new(*w)).collect(), primary)
+    }
+
+    /// `None` when `primary` is out of range or a weight is not finite and positive.
+    pub fn weighted(entries: Vec<WeightedWavelength>, primary: usize) -> Option<WavelengthTable> {
+        if primary >= entries.len() { return None }
+        if entries.iter().any(|entry| !(entry.weight.is_finite() && entry.weight > 0.)) { return None }
+        Some(WavelengthTable { entries, primary })
+    }
+
+    pub fn single(wavelength: Wavelength) -> WavelengthTable {
+        WavelengthTable::preset(&[wavelength], 0)
+    }
+
+    fn preset(wavelengths: &[Wavelength], primary: usize) -> WavelengthTable {
+        WavelengthTable::new(wavelengths, primary).expect("preset primary wavelength is in range")
+    }
+
+    /// F, d, C with d primary.
+    pub fn visible() -> WavelengthTable {
+        WavelengthTable::preset(&[LINE_F, LINE_D, LINE_C], 1)
+    }
+
+    /// F, e, C with e primary.
+    pub fn visible_e() -> WavelengthTable {
+        WavelengthTable::preset(&[LINE_F, LINE_E, LINE_C], 1)
+    }
+
+    /// h, g, F, e, d, C, the extended visible band used for apochromats, d primary.
+    pub fn visible_extended() -> WavelengthTable {
+        WavelengthTable::preset(&[LINE_H, LINE_G, LINE_F, LINE_E, LINE_D, LINE_C], 4)
+    }
+
+    /// 0.75, 0.85, 1.0 um (silicon detectors), 0.85 primary.
+    pub fn near_infrared() -> WavelengthTable {
+        WavelengthTable::preset(&[Wavelength::from_um(0.75), Wavelength::from_um(0.85), Wavelength::from_um(1.0)], 1)
+    }
+
+    /// 1.0, 1.3, 1.55, 1.7 um (InGaAs detectors), 1.3 primary.
+    pub fn short_wave_infrared() -> WavelengthTable {
+        WavelengthTable::preset(
+            &[Wavelength::from_um(1.0), Wavelength::from_um(1.3), Wavelength::from_um(1.55), Wavelength::from_um(1.7)],
+            1,
+        )
+    }
+
+    pub fn entries(&self) -> &[WeightedWavelength] {
+        &self.entries
+    }
+
+    /// Index of the primary wavelength in `entries`.
+    pub fn primary(&self) -> usize {
+        self.primary
+    }
+
+    pub fn primary_wavelength(&self) -> Wavelength {
+        self.entries[self.primary].wavelength
+    }
+
+    pub fn wavelengths(&self) -> Vec<Wavelength> {
+        self.entries.iter().map(|entry| entry.wavelength).collect()
+    }
+
+    /// Shortest and longest wavelengths of the table.
+    pub fn band(&self) -> (Wavelength, Wavelength) {
+        let wavelengths = self.wavelengths();
+        let order = |a: &&Wavelength, b: &&Wavelength| a.um().total_cmp(&b.um());
+        let short = wavelengths.iter().min_by(order).expect("wavelength table is never empty");
+        let long = wavelengths.iter().max_by(order).expect("wavelength table is never empty");
+        (*short, *long)
+    }
+}
+
+
+impl Default for WavelengthTable {
+    fn default() -> Self {
+        WavelengthTable::single(LINE_D)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_units_and_tables() {
+        assert_approx_eq!(HE_NE.um(), 0.6328);
+        assert_approx_eq!(Wavelength::from_um(1.55).nm(), 1550.);
+        assert_approx_eq!(LINE_D.mm(), 5.875618e-4);
+
+        let visible = WavelengthTable::visible();
+        assert_eq!(visible.primary_wavelength(), LINE_D);
+        assert_eq!(visible.band(), (LINE_F, LINE_C));
+        assert_eq!(WavelengthTable::short_wave_infrared().band().1, Wavelength::from_um(1.7));
+        assert_eq!(WavelengthTable::new(&[LINE_F, LINE_D], 2), None);
+        assert_eq!(WavelengthTable::new(&[], 0), None);
+        let zero = WeightedWavelength { wavelength: LINE_C, weight: 0. };
+        assert_eq!(WavelengthTable::weighted(vec![WeightedWavelength::new(LINE_F), zero], 0), None);
+        let infinite = WeightedWavelength { wavelength: LINE_C, weight: f64::INFINITY };
+        assert_eq!(WavelengthTable::weighted(vec![infinite], 0), None);
+    }
+}
@@ -1,6 +1,15 @@
 use num::Float;
 use crate::geometry::point::Point3;
 use crate::geometry::ray::Ray3;
+use crate::geometry::vector::Vector3;
+
+
+/// Point where a ray meets a surface and the unit surface normal at that point.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Intersection {
+    pub point: Point3,
+    pub normal: Vector3,
+}
 
 
 pub fn intersect_ray_with_sphere(
@@ -17,18 +26,19 @@ pub fn intersect_ray_with_sphere(
     let t1 = (-b - Float::sqrt(d)) / (2.0 * a);
     let t2 = (-b + Float::sqrt(d)) / (2.0 * a);
 
-    return if t1 < 0.0 {
+    if t1 < 0.0 {
         if t2 >= 0.0 { Some(ray.origin + ray.direction * t2) } else { None }
+    } else if t1 < t2 {
+        Some(ray.origin + ray.direction * t1)
     } else {
-        if t1 < t2 { Some(ray.origin + ray.direction * t1) } else
-        { Some(ray.origin + ray.direction * t2) }
+        Some(ray.origin + ray.direction * t2)
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::geometry::vector::Vector3;
+    use crate::database::wavelengths::LINE_D;
     use crate::geometry::ray::RayValidity;
     use super::*;
 
@@ -38,8 +48,6 @@ mod tests {
         let c1 = Point3{x: 0.0, y: 3.0, z: 0.0 };
 
         let rad1: f64 = 1.0;
-        let rad2: f64 = 2.0;
-        let rad3: f64 = 3.0;
 
         let ray1 = Ray3 {
             origin: c0,
@@ -49,6 +57,8 @@ mod tests {
                 z: 1.0,
             },
             validity: RayValidity::VALID,
+            optical_path: 0.,
+            wavelength: LINE_D,
         };
         let ray2 = Ray3 {
             origin: c0,
@@ -58,8 +68,10 @@ mod tests {
                 z: -1.0,
             },
             validity: RayValidity::VALID,
+            optical_path: 0.,
+            wavelength: LINE_D,
         };
-        let ray3 = Ray3 {origin: c0, direction: Vector3 {x: 0.0, y: 1.0, z: 0.0},validity: RayValidity::VALID};
+        let ray3 = Ray3 {origin: c0, direction: Vector3 {x: 0.0, y: 1.0, z: 0.0},validity: RayValidity::VALID, optical_path: 0., wavelength: LINE_D};
         let ray4 = Ray3 {
             origin: c0,
             direction: Vector3 {
@@ -68,6 +80,8 @@ mod tests {
                 z: 0.0,
             },
             validity: RayValidity::VALID,
+            optical_path: 0.,
+            wavelength: LINE_D,
         };
         assert_eq!(intersect_ray_with_sphere(ray1, c0, rad1), Some(Point3{x: 0.0, y: 0.0, z: 1.0}));
         assert_eq!(intersect_ray_with_sphere(ray2, c0, rad1), Some(Point3{x: 0.0, y: 0.0, z: -1.0}));
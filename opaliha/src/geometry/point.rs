@@ -132,10 +132,7 @@ pub fn max_point(p1: Point3, p2: Point3) -> Point3 {
 
 impl fmt::Display for Point3 {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f, "x - {:.5} y - {:.5} z - {:.5}",
-            self.x, self.y, self.z).map_err(|err| println!("{:?}", err)).ok();
-        Ok(())
+        write!(f, "x - {:.5} y - {:.5} z - {:.5}", self.x, self.y, self.z)
     }
 }
 
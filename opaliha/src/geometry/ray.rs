@@ -1,4 +1,5 @@
 use std::fmt;
+use crate::database::wavelengths::{Wavelength, LINE_D};
 use crate::geometry::{point, sphere};
 use crate::geometry::intersection::intersect_ray_with_sphere;
 use crate::geometry::vector::Vector3;
@@ -14,7 +15,11 @@ pub enum RayValidity {
 pub struct Ray3 {
     pub origin: point::Point3,
     pub direction: Vector3,
-    pub validity: RayValidity
+    pub validity: RayValidity,
+    /// Optical path accumulated since launch, same units as coordinates.
+    pub optical_path: f64,
+    /// Vacuum wavelength materials are evaluated at.
+    pub wavelength: Wavelength,
 }
 
 
@@ -23,8 +28,13 @@ impl Ray3 {
         self.origin + self.direction * t
     }
 
-    fn new(p: point::Point3, v: Vector3) -> Ray3 {
-        Ray3{origin: p, direction: v.clone_normalized(), validity: RayValidity::VALID}
+    /// Ray at the d line, use `with_wavelength` for other wavelengths.
+    pub fn new(p: point::Point3, v: Vector3) -> Ray3 {
+        Ray3{origin: p, direction: v.clone_normalized(), validity: RayValidity::VALID, optical_path: 0., wavelength: LINE_D}
+    }
+
+    pub fn with_wavelength(self, wavelength: Wavelength) -> Ray3 {
+        Ray3 { wavelength, ..self }
     }
 
     pub fn propagate_to_z(&mut self, z: f64) -> bool {
@@ -55,10 +65,7 @@ impl Ray3 {
 
 impl fmt::Display for Ray3 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f, "o - {}, dir - {}, validity - {}",
-            self.origin, self.direction, self.validity).map_err(|err| println!("{:?}", err)).ok();
-        Ok(())
+        write!(f, "o - {}, dir - {}, validity - {}, wavelength - {}", self.origin, self.direction, self.validity, self.wavelength)
     }
 }
 
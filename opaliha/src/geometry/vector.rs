@@ -73,9 +73,9 @@ impl Vector3 {
 
     pub fn normalize(&mut self) -> &mut Vector3 {
         let norm = self.norm();
-        self.x = self.x / norm;
-        self.y = self.y / norm;
-        self.z = self.z / norm;
+        self.x /= norm;
+        self.y /= norm;
+        self.z /= norm;
         self
     }
 
@@ -85,8 +85,7 @@ impl Vector3 {
     }
 
     pub fn build_coordinate_system(&self) -> (Vector3, Vector3, Vector3) {
-        let mut v1 = self.clone();
-        v1 = *v1.normalize();
+        let v1 = self.clone_normalized();
         let mut v2 = zero_vector();
 
         if abs(v1.x) > abs(v2.y) {
@@ -114,21 +113,15 @@ impl Vector3 {
     }
 
     pub fn max_dimension(&self) -> usize {
-        let v = vec![self.x, self.y, self.z];
+        let v = [self.x, self.y, self.z];
         let mut max = v[0];
-        let mut min = v[0];
         let mut max_index = 0;
-        let mut min_index = 0;
 
         for (index, &x) in v.iter().enumerate() {
             if x > max {
                 max = x;
                 max_index = index;
             }
-            if x < min {
-                min = x;
-                min_index = index;
-            }
         }
         max_index
     }
@@ -218,10 +211,7 @@ impl Div<f64> for Vector3 {
 
 impl fmt::Display for Vector3 {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f, "x - {:.5} y - {:.5} z - {:.5}",
-            self.x, self.y, self.z).map_err(|err| println!("{:?}", err)).ok();
-        Ok(())
+        write!(f, "x - {:.5} y - {:.5} z - {:.5}", self.x, self.y, self.z)
     }
 }
 
@@ -377,6 +367,6 @@ mod tests {
         assert_eq!(cross_prod_3_1, Vector3{x: -6., y: 12., z: -6.});
 
         // todo complete tests
-        let vec_sys = v1.build_coordinate_system();
+        let _vec_sys = v1.build_coordinate_system();
     }
 }
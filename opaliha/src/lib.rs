@@ -1,3 +1,11 @@
+pub mod analysis;
+pub mod common;
+pub mod database;
+pub mod geometry;
+pub mod materials;
+pub mod math;
+pub mod optical_system;
+
 #[cfg(test)]
 mod tests {
     #[test]
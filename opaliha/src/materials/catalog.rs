@@ -0,0 +1,57 @@
+use crate::materials::dispersion::Dispersion;
+use crate::materials::glass::Glass;
+
+/// Schott-form Sellmeier coefficients: n² = 1 + Σ Bᵢλ² / (λ² - Cᵢ).
+const SCHOTT: [(&str, [f64; 3], [f64; 3]); 8] = [
+    ("N-BK7", [1.03961212, 0.231792344, 1.01046945], [0.00600069867, 0.0200179144, 103.560653]),
+    ("N-BAF10", [1.5851495, 0.143559385, 1.08521269], [0.00926681282, 0.0424489805, 105.613573]),
+    ("N-BAK1", [1.12365662, 0.309276848, 0.881511957], [0.00644742752, 0.0222284402, 107.297751]),
+    ("N-KZFS4", [1.35055424, 0.197575506, 1.09962992], [0.0087628207, 0.0371767201, 90.3866994]),
+    ("N-SK16", [1.34317774, 0.241144399, 0.994317969], [0.00704687339, 0.0229005, 92.7508526]),
+    ("F2", [1.34533359, 0.209073176, 0.937357162], [0.00997743871, 0.0470450767, 111.886764]),
+    ("SF5", [1.52481889, 0.187085527, 1.42729015], [0.011254756, 0.0588995392, 129.141675]),
+    ("F_SILICA", [0.6961663, 0.4079426, 0.8974794], [0.00467914826, 0.0135120631, 97.9340025]),
+];
+
+/// Catalog names also accept the old notation without the `N-` prefix and with a trailing
+/// `N` (BAFN10 for N-BAF10), case insensitive.
+pub fn glass(name: &str) -> Option<Glass> {
+    let wanted = normalize(name);
+    SCHOTT.iter()
+        .find(|(catalog_name, _, _)| normalize(catalog_name) == wanted)
+        .map(|(catalog_name, b, c)| Glass::new(
+            catalog_name,
+            Dispersion::Sellmeier { a: 1., terms: b.iter().copied().zip(c.iter().copied()).collect() },
+        ))
+}
+
+
+fn normalize(name: &str) -> String {
+    let upper = name.trim().to_uppercase();
+    let stripped = upper.strip_prefix("N-").unwrap_or(&upper);
+    let letters: String = stripped.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    let rest = &stripped[letters.len()..];
+    match letters.strip_suffix('N') {
+        Some(base) if !base.is_empty() && !rest.is_empty() => format!("{}{}", base, rest),
+        _ => stripped.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::LINE_D;
+    use crate::materials::material::Material;
+
+    #[test]
+    fn test_catalog_lookup() {
+        assert_approx_eq!(glass("N-BK7").unwrap().refraction_index_at(LINE_D), 1.5168, 1e-5);
+        assert_approx_eq!(glass("bafn10").unwrap().refraction_index_at(LINE_D), 1.67003, 1e-5);
+        assert_approx_eq!(glass("KZFSN4").unwrap().refraction_index_at(LINE_D), 1.61336, 1e-5);
+        assert_approx_eq!(glass("bak1").unwrap().refraction_index_at(LINE_D), 1.5725, 1e-5);
+        assert_eq!(glass("F2").unwrap().name, "F2");
+        assert!(glass("unobtainium").is_none());
+    }
+}
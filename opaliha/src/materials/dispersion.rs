@@ -0,0 +1,30 @@
+use num::Float;
+use crate::database::wavelengths::Wavelength;
+
+/// Dispersion formula of an optical medium, λ is taken in micrometres.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Dispersion {
+    /// Wavelength independent refractive index.
+    Constant(f64),
+    /// n² = a + Σ bᵢλ² / (λ² - cᵢ), every term is stored as `(bᵢ, cᵢ)`.
+    Sellmeier { a: f64, terms: Vec<(f64, f64)> },
+    /// n² = a + b / (λ² - c) - dλ², common for birefringent crystals.
+    ModifiedSellmeier { a: f64, b: f64, c: f64, d: f64 },
+}
+
+
+impl Dispersion {
+    pub fn refraction_index_at(&self, wavelength: Wavelength) -> f64 {
+        let l2 = wavelength.um() * wavelength.um();
+        match self {
+            Dispersion::Constant(n) => *n,
+            Dispersion::Sellmeier { a, terms } => {
+                let n2 = terms.iter().fold(*a, |acc, (b, c)| acc + b * l2 / (l2 - c));
+                Float::sqrt(n2)
+            }
+            Dispersion::ModifiedSellmeier { a, b, c, d } => {
+                Float::sqrt(a + b / (l2 - c) - d * l2)
+            }
+        }
+    }
+}
@@ -1,9 +1,28 @@
+use crate::database::wavelengths::Wavelength;
+use crate::materials::dispersion::Dispersion;
+use crate::materials::material::Material;
+
+/// Isotropic homogeneous glass described by its dispersion formula.
+#[derive(Debug, Clone)]
 pub struct Glass {
-    name: String,
+    pub name: String,
+    pub dispersion: Dispersion,
 }
 
+
 impl Glass {
-    pub fn new(name: String) -> Self {
-        return Self { name };
+    pub fn new(name: &str, dispersion: Dispersion) -> Glass {
+        Glass { name: name.to_string(), dispersion }
+    }
+}
+
+
+impl Material for Glass {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn refraction_index_at(&self, wavelength: Wavelength) -> f64 {
+        self.dispersion.refraction_index_at(wavelength)
     }
 }
@@ -0,0 +1,122 @@
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::point::Point3;
+use crate::geometry::vector::{zero_vector, Vector3};
+use crate::materials::dispersion::Dispersion;
+use crate::materials::material::Material;
+
+/// Inhomogeneous medium. Points are given in the local frame of the surface the medium
+/// belongs to: the vertex is the origin and z goes along the optical axis.
+pub trait GradientIndex {
+    fn index_at(&self, point: Point3, wavelength: Wavelength) -> f64;
+    fn gradient_at(&self, point: Point3, wavelength: Wavelength) -> Vector3;
+}
+
+/// Radial profile n(r) = n0(λ) + Σ kᵢ r²ⁱ, i = 1, 2, ... (Wood lens, GRIN rods).
+#[derive(Debug, Clone)]
+pub struct RadialGradient {
+    pub name: String,
+    pub base: Dispersion,
+    pub coefficients: Vec<f64>,
+}
+
+/// Axial profile n(z) = n0(λ) + Σ kᵢ zⁱ, i = 1, 2, ... (Gradium-style blanks).
+#[derive(Debug, Clone)]
+pub struct AxialGradient {
+    pub name: String,
+    pub base: Dispersion,
+    pub coefficients: Vec<f64>,
+}
+
+
+impl RadialGradient {
+    pub fn new(name: &str, base: Dispersion, coefficients: Vec<f64>) -> RadialGradient {
+        RadialGradient { name: name.to_string(), base, coefficients }
+    }
+
+    /// Parabolic rod n(r) = n0 (1 - A r² / 2), pitch length is 2π / √A.
+    pub fn parabolic(name: &str, n0: f64, sqrt_a: f64) -> RadialGradient {
+        RadialGradient::new(name, Dispersion::Constant(n0), vec![-n0 * sqrt_a * sqrt_a / 2.])
+    }
+}
+
+
+impl AxialGradient {
+    pub fn new(name: &str, base: Dispersion, coefficients: Vec<f64>) -> AxialGradient {
+        AxialGradient { name: name.to_string(), base, coefficients }
+    }
+}
+
+
+impl GradientIndex for RadialGradient {
+    fn index_at(&self, point: Point3, wavelength: Wavelength) -> f64 {
+        let r2 = point.x * point.x + point.y * point.y;
+        let mut power = 1.;
+        self.coefficients.iter().fold(self.base.refraction_index_at(wavelength), |n, k| {
+            power *= r2;
+            n + k * power
+        })
+    }
+
+    fn gradient_at(&self, point: Point3, _wavelength: Wavelength) -> Vector3 {
+        // dn/d(r²) multiplied by d(r²)/dx = 2x
+        let r2 = point.x * point.x + point.y * point.y;
+        let mut power = 1.;
+        let mut derivative = 0.;
+        for (i, k) in self.coefficients.iter().enumerate() {
+            derivative += (i + 1) as f64 * k * power;
+            power *= r2;
+        }
+        Vector3 { x: 2. * point.x * derivative, y: 2. * point.y * derivative, z: 0. }
+    }
+}
+
+
+impl GradientIndex for AxialGradient {
+    fn index_at(&self, point: Point3, wavelength: Wavelength) -> f64 {
+        let mut power = 1.;
+        self.coefficients.iter().fold(self.base.refraction_index_at(wavelength), |n, k| {
+            power *= point.z;
+            n + k * power
+        })
+    }
+
+    fn gradient_at(&self, point: Point3, _wavelength: Wavelength) -> Vector3 {
+        let mut power = 1.;
+        let mut derivative = 0.;
+        for (i, k) in self.coefficients.iter().enumerate() {
+            derivative += (i + 1) as f64 * k * power;
+            power *= point.z;
+        }
+        Vector3 { z: derivative, ..zero_vector() }
+    }
+}
+
+
+impl Material for RadialGradient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn refraction_index_at(&self, wavelength: Wavelength) -> f64 {
+        self.base.refraction_index_at(wavelength)
+    }
+
+    fn as_gradient(&self) -> Option<&dyn GradientIndex> {
+        Some(self)
+    }
+}
+
+
+impl Material for AxialGradient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn refraction_index_at(&self, wavelength: Wavelength) -> f64 {
+        self.base.refraction_index_at(wavelength)
+    }
+
+    fn as_gradient(&self) -> Option<&dyn GradientIndex> {
+        Some(self)
+    }
+}
@@ -1,6 +1,21 @@
+use crate::database::wavelengths::Wavelength;
+use crate::materials::gradient_index::GradientIndex;
+use crate::materials::uniaxial::UniaxialCrystal;
+
+/// Optical medium filling the space after a surface.
 pub trait Material {
     fn name(&self) -> &str;
-    fn refraction_index_at(&self, wavelength: f64) -> f64;
+    fn refraction_index_at(&self, wavelength: Wavelength) -> f64;
+
+    /// Anisotropic media expose their crystal description, isotropic ones return `None`.
+    fn as_uniaxial(&self) -> Option<&UniaxialCrystal> {
+        None
+    }
+
+    /// Inhomogeneous media expose their index profile, homogeneous ones return `None`.
+    fn as_gradient(&self) -> Option<&dyn GradientIndex> {
+        None
+    }
 }
 
 pub struct Air {
@@ -15,30 +30,14 @@ impl Default for Air {
     }
 }
 
-pub struct Glass {
-    pub name: String,
-}
-
-impl Material for Glass {
-    fn name(&self) -> &str {
-        &self.name
-    }
-
-    fn refraction_index_at(&self, wavelength: f64) -> f64 {
-        // apply lerp here for already readed data,
-        // if wavelength outside range -> raise error
-        1.
-    }
-}
-
 
 impl Material for Air {
     fn name(&self) -> &str {
         &self.name
     }
 
-    fn refraction_index_at(&self, wavelength: f64) -> f64 {
-        1.
+    fn refraction_index_at(&self, _wavelength: Wavelength) -> f64 {
+        self.refraction_index
     }
 
 }
@@ -1,2 +1,6 @@
+pub mod catalog;
+pub mod dispersion;
 pub mod glass;
-pub mod material;
\ No newline at end of file
+pub mod gradient_index;
+pub mod material;
+pub mod uniaxial;
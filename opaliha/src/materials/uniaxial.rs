@@ -0,0 +1,181 @@
+use num::Float;
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::vector::Vector3;
+use crate::materials::dispersion::Dispersion;
+use crate::materials::material::Material;
+
+/// Uniaxial birefringent crystal described by its ordinary and extraordinary
+/// principal indices and the optic axis direction in global coordinates.
+#[derive(Debug, Clone)]
+pub struct UniaxialCrystal {
+    pub name: String,
+    pub ordinary: Dispersion,
+    pub extraordinary: Dispersion,
+    pub optic_axis: Vector3,
+}
+
+
+impl UniaxialCrystal {
+    pub fn new(name: &str, ordinary: Dispersion, extraordinary: Dispersion, optic_axis: Vector3) -> UniaxialCrystal {
+        UniaxialCrystal {
+            name: name.to_string(),
+            ordinary,
+            extraordinary,
+            optic_axis: optic_axis.clone_normalized(),
+        }
+    }
+
+    /// Calcite, G. Ghosh (1999), 0.2 - 2.2 um.
+    pub fn calcite(optic_axis: Vector3) -> UniaxialCrystal {
+        UniaxialCrystal::new(
+            "calcite",
+            Dispersion::Sellmeier {
+                a: 1.73358749,
+                terms: vec![(0.96464345, 1.94325203e-2), (1.82831454, 120.)],
+            },
+            Dispersion::Sellmeier {
+                a: 1.35859695,
+                terms: vec![(0.82427830, 1.06689543e-2), (0.14429128, 120.)],
+            },
+            optic_axis,
+        )
+    }
+
+    /// Crystal quartz, G. Ghosh (1999), 0.2 - 2.0 um.
+    pub fn quartz(optic_axis: Vector3) -> UniaxialCrystal {
+        UniaxialCrystal::new(
+            "quartz",
+            Dispersion::Sellmeier {
+                a: 1.28604141,
+                terms: vec![(1.07044083, 1.00585997e-2), (1.10202242, 100.)],
+            },
+            Dispersion::Sellmeier {
+                a: 1.28851804,
+                terms: vec![(1.09509924, 1.02101864e-2), (1.15662475, 100.)],
+            },
+            optic_axis,
+        )
+    }
+
+    /// Yttrium orthovanadate, 0.4 - 4.0 um.
+    pub fn yvo4(optic_axis: Vector3) -> UniaxialCrystal {
+        UniaxialCrystal::new(
+            "yvo4",
+            Dispersion::ModifiedSellmeier { a: 3.77834, b: 0.069736, c: 0.04724, d: 0.0108133 },
+            Dispersion::ModifiedSellmeier { a: 4.59905, b: 0.110534, c: 0.04813, d: 0.0122676 },
+            optic_axis,
+        )
+    }
+
+    pub fn ordinary_index(&self, wavelength: Wavelength) -> f64 {
+        self.ordinary.refraction_index_at(wavelength)
+    }
+
+    /// Extraordinary index of a wave whose normal is perpendicular to the optic axis.
+    pub fn principal_extraordinary_index(&self, wavelength: Wavelength) -> f64 {
+        self.extraordinary.refraction_index_at(wavelength)
+    }
+
+    /// Phase index of the extraordinary wave travelling along `wave_normal`:
+    /// 1/n² = cos²θ/no² + sin²θ/ne², θ is the angle to the optic axis.
+    pub fn extraordinary_index(&self, wavelength: Wavelength, wave_normal: Vector3) -> f64 {
+        let no = self.ordinary_index(wavelength);
+        let ne = self.principal_extraordinary_index(wavelength);
+        let cos_theta = wave_normal.clone_normalized().dot(self.optic_axis);
+        let sin2_theta = 1. - cos_theta * cos_theta;
+        1. / Float::sqrt(cos_theta * cos_theta / (no * no) + sin2_theta / (ne * ne))
+    }
+
+    /// Energy (Poynting) direction of the extraordinary wave with the given wave normal.
+    pub fn extraordinary_ray_direction(&self, wavelength: Wavelength, wave_normal: Vector3) -> Vector3 {
+        let no = self.ordinary_index(wavelength);
+        let ne = self.principal_extraordinary_index(wavelength);
+        let k = wave_normal.clone_normalized();
+        let anisotropy = 1. / (no * no) - 1. / (ne * ne);
+        (k / (ne * ne) + self.optic_axis * (anisotropy * k.dot(self.optic_axis))).clone_normalized()
+    }
+
+    /// Angle between the extraordinary wave normal and its energy direction, radians.
+    pub fn walk_off_angle(&self, wavelength: Wavelength, wave_normal: Vector3) -> f64 {
+        let k = wave_normal.clone_normalized();
+        let s = self.extraordinary_ray_direction(wavelength, k);
+        Float::acos(k.dot(s).min(1.))
+    }
+
+    /// Extraordinary wave vector (|k| equals the phase index) matching the `tangential`
+    /// component of the incident wave vector on a boundary with unit `normal` oriented
+    /// along propagation. Returns `None` when the extraordinary wave is evanescent.
+    pub fn extraordinary_wave_vector(&self, wavelength: Wavelength, tangential: Vector3, normal: Vector3) -> Option<Vector3> {
+        let no = self.ordinary_index(wavelength);
+        let ne = self.principal_extraordinary_index(wavelength);
+        let anisotropy = 1. / (no * no) - 1. / (ne * ne);
+        let p = tangential.dot(self.optic_axis);
+        let q = normal.dot(self.optic_axis);
+        let a = q * q * anisotropy + 1. / (ne * ne);
+        let b = 2. * p * q * anisotropy;
+        let c = p * p * anisotropy + tangential.dot(tangential) / (ne * ne) - 1.;
+        let d = b * b - 4. * a * c;
+        if d < 0. { return None }
+        let alpha = (-b + Float::sqrt(d)) / (2. * a);
+        if alpha <= 0. { return None }
+        Some(tangential + normal * alpha)
+    }
+}
+
+
+impl Material for UniaxialCrystal {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn refraction_index_at(&self, wavelength: Wavelength) -> f64 {
+        self.ordinary_index(wavelength)
+    }
+
+    fn as_uniaxial(&self) -> Option<&UniaxialCrystal> {
+        Some(self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::HE_NE;
+
+    const SODIUM: Wavelength = Wavelength::from_nm(589.3);
+
+    #[test]
+    fn test_principal_indices() {
+        let calcite = UniaxialCrystal::calcite(Vector3::unit_z());
+        assert_approx_eq!(calcite.ordinary_index(SODIUM), 1.6584, 1e-3);
+        assert_approx_eq!(calcite.principal_extraordinary_index(SODIUM), 1.4864, 1e-3);
+
+        let quartz = UniaxialCrystal::quartz(Vector3::unit_z());
+        assert_approx_eq!(quartz.ordinary_index(SODIUM), 1.5442, 1e-3);
+        assert_approx_eq!(quartz.principal_extraordinary_index(SODIUM), 1.5533, 1e-3);
+
+        let yvo4 = UniaxialCrystal::yvo4(Vector3::unit_z());
+        assert_approx_eq!(yvo4.ordinary_index(HE_NE), 1.9929, 1e-3);
+        assert_approx_eq!(yvo4.principal_extraordinary_index(HE_NE), 2.2154, 1e-3);
+    }
+
+    #[test]
+    fn test_extraordinary_index_and_walk_off() {
+        let calcite = UniaxialCrystal::calcite(Vector3::unit_z());
+        let no = calcite.ordinary_index(SODIUM);
+        let ne = calcite.principal_extraordinary_index(SODIUM);
+
+        assert_approx_eq!(calcite.extraordinary_index(SODIUM, Vector3::unit_z()), no);
+        assert_approx_eq!(calcite.extraordinary_index(SODIUM, Vector3::unit_x()), ne);
+        assert_approx_eq!(calcite.walk_off_angle(SODIUM, Vector3::unit_z()), 0.);
+        assert_approx_eq!(calcite.walk_off_angle(SODIUM, Vector3::unit_y()), 0.);
+
+        // maximal walk-off of calcite is about 6.2 deg at 45 deg to the optic axis
+        let k = Vector3{x: 0., y: 1., z: 1.};
+        let expected = Float::atan((no * no - ne * ne) / (no * no + ne * ne));
+        assert_approx_eq!(calcite.walk_off_angle(SODIUM, k), expected);
+        assert_approx_eq!(expected.to_degrees(), 6.2, 5e-2);
+    }
+}
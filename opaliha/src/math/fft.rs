@@ -0,0 +1,97 @@
+use std::f64::consts::PI;
+use ndarray::{Array2, Axis};
+use num::complex::Complex64;
+
+/// In-place radix-2 FFT with the e^(-2πi kn/N) kernel, the inverse is scaled by 1/N. The length
+/// must be a power of two.
+pub fn fft(data: &mut [Complex64], inverse: bool) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT length {} is not a power of two", n);
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j { data.swap(i, j) }
+    }
+    let sign = if inverse { 1. } else { -1. };
+    let mut length = 2;
+    while length <= n {
+        let step = Complex64::from_polar(1., sign * 2. * PI / length as f64);
+        for start in (0..n).step_by(length) {
+            let mut twiddle = Complex64::new(1., 0.);
+            for k in 0..length / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + length / 2] * twiddle;
+                data[start + k] = even + odd;
+                data[start + k + length / 2] = even - odd;
+                twiddle *= step;
+            }
+        }
+        length <<= 1;
+    }
+    if inverse {
+        data.iter_mut().for_each(|value| *value /= n as f64);
+    }
+}
+
+
+/// FFT over both axes of the grid.
+pub fn fft_2d(grid: &mut Array2<Complex64>, inverse: bool) {
+    for axis in [Axis(0), Axis(1)] {
+        for mut lane in grid.lanes_mut(axis) {
+            let mut buffer = lane.to_vec();
+            fft(&mut buffer, inverse);
+            lane.iter_mut().zip(buffer).for_each(|(value, transformed)| *value = transformed);
+        }
+    }
+}
+
+
+/// Moves the zero frequency from index 0 to the center of both axes, its own inverse for even
+/// sizes.
+pub fn fft_shift<T: Clone>(grid: &Array2<T>) -> Array2<T> {
+    let (rows, columns) = grid.dim();
+    Array2::from_shape_fn((rows, columns), |(i, j)| {
+        grid[((i + rows / 2) % rows, (j + columns / 2) % columns)].clone()
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_against_dft() {
+        let signal: Vec<Complex64> = (0..16).map(|k| Complex64::new((k as f64 * 0.7).sin(), (k * k % 5) as f64)).collect();
+        let mut transformed = signal.clone();
+        fft(&mut transformed, false);
+        for (k, value) in transformed.iter().enumerate() {
+            let expected: Complex64 = signal.iter().enumerate()
+                .map(|(n, x)| x * Complex64::from_polar(1., -2. * PI * (k * n) as f64 / 16.))
+                .sum();
+            assert_approx_eq!(value.re, expected.re, 1e-12);
+            assert_approx_eq!(value.im, expected.im, 1e-12);
+        }
+        fft(&mut transformed, true);
+        for (value, original) in transformed.iter().zip(signal.iter()) {
+            assert_approx_eq!((value - original).norm(), 0., 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_2d_delta_and_shift() {
+        let mut grid = Array2::from_elem((8, 4), Complex64::new(0., 0.));
+        grid[(0, 0)] = Complex64::new(1., 0.);
+        fft_2d(&mut grid, false);
+        assert!(grid.iter().all(|value| (value - Complex64::new(1., 0.)).norm() < 1e-12));
+        let indices = Array2::from_shape_fn((4, 4), |(i, j)| 4 * i + j);
+        assert_eq!(fft_shift(&indices)[(2, 2)], 0);
+        assert_eq!(fft_shift(&fft_shift(&indices)), indices);
+    }
+}
@@ -0,0 +1,57 @@
+/// Least-squares solution of the overdetermined system `rows · x = values` by Householder QR,
+/// which keeps the conditioning of the design matrix instead of squaring it as the normal
+/// equations do. Returns `None` for rank-deficient systems or fewer rows than unknowns.
+pub fn least_squares(rows: &[Vec<f64>], values: &[f64]) -> Option<Vec<f64>> {
+    let m = rows.len();
+    let n = rows.first().map_or(0, |row| row.len());
+    if m < n || n == 0 || values.len() != m { return None }
+    // column-major copy, the reflections work on columns
+    let mut a: Vec<Vec<f64>> = (0..n).map(|j| rows.iter().map(|row| row[j]).collect()).collect();
+    let mut b = values.to_vec();
+    let scale = a.iter().flatten().fold(0f64, |max, v| max.max(v.abs()));
+
+    for k in 0..n {
+        let norm = a[k][k..].iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm <= scale * 1e-13 { return None }
+        let alpha = if a[k][k] > 0. { -norm } else { norm };
+        let mut v = a[k][k..].to_vec();
+        v[0] -= alpha;
+        let v_norm2 = v.iter().map(|x| x * x).sum::<f64>();
+        let reflect = |column: &mut [f64]| {
+            let factor = 2. * v.iter().zip(column.iter()).map(|(v, c)| v * c).sum::<f64>() / v_norm2;
+            column.iter_mut().zip(v.iter()).for_each(|(c, v)| *c -= factor * v);
+        };
+        for column in a[k..].iter_mut() {
+            reflect(&mut column[k..]);
+        }
+        reflect(&mut b[k..]);
+    }
+
+    let mut x = vec![0.; n];
+    for k in (0..n).rev() {
+        let sum = (k + 1..n).map(|j| a[j][k] * x[j]).sum::<f64>();
+        x[k] = (b[k] - sum) / a[k][k];
+    }
+    Some(x)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_line_fit() {
+        // y = 2 + 3x with symmetric residuals
+        let xs = [0., 1., 2., 3.];
+        let rows: Vec<Vec<f64>> = xs.iter().map(|&x| vec![1., x]).collect();
+        let values: Vec<f64> = xs.iter().enumerate()
+            .map(|(i, &x)| 2. + 3. * x + if i % 2 == 0 { 0.1 } else { -0.1 })
+            .collect();
+        let solution = least_squares(&rows, &values).unwrap();
+        assert_approx_eq!(solution[0], 2. + 0.06, 1e-12);
+        assert_approx_eq!(solution[1], 3. - 0.04, 1e-12);
+        assert!(least_squares(&[vec![1., 1.], vec![2., 2.]], &[1., 2.]).is_none());
+    }
+}
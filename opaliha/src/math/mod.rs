@@ -0,0 +1,4 @@
+pub mod fft;
+pub mod least_squares;
+pub mod power_series;
+pub mod zernike;
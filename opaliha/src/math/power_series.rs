@@ -0,0 +1,209 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Highest total degree kept by `PowerSeries`.
+pub const DEGREE: usize = 5;
+const SIZE: usize = DEGREE + 1;
+
+/// Power series in three variables truncated above `DEGREE`, the coefficient of
+/// u^i v^j w^k is stored at `[i][j][k]`. Products drop the terms of higher degree, so
+/// arithmetic on series gives the exact Taylor coefficients of the result up to `DEGREE`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PowerSeries {
+    coefficients: [[[f64; SIZE]; SIZE]; SIZE],
+}
+
+
+impl PowerSeries {
+    pub fn constant(value: f64) -> PowerSeries {
+        let mut series = PowerSeries { coefficients: [[[0.; SIZE]; SIZE]; SIZE] };
+        series.coefficients[0][0][0] = value;
+        series
+    }
+
+    /// Variable 0, 1 or 2 (u, v or w).
+    pub fn variable(index: usize) -> PowerSeries {
+        let mut series = PowerSeries::constant(0.);
+        let exponents = [(1, 0, 0), (0, 1, 0), (0, 0, 1)][index];
+        series.coefficients[exponents.0][exponents.1][exponents.2] = 1.;
+        series
+    }
+
+    /// Coefficient of u^i v^j w^k, zero above `DEGREE`.
+    pub fn coefficient(&self, i: usize, j: usize, k: usize) -> f64 {
+        if i + j + k > DEGREE { return 0. }
+        self.coefficients[i][j][k]
+    }
+
+    pub fn constant_term(&self) -> f64 {
+        self.coefficients[0][0][0]
+    }
+
+    /// Terms up to the total degree `degree`.
+    pub fn truncated(&self, degree: usize) -> PowerSeries {
+        let mut series = *self;
+        for_each_exponent(|i, j, k| if i + j + k > degree { series.coefficients[i][j][k] = 0. });
+        series
+    }
+
+    /// `coefficients[n]` are the Taylor coefficients of f around the constant term a,
+    /// f(a + h) = Σ coefficients[n] hⁿ.
+    fn compose(&self, coefficients: [f64; SIZE]) -> PowerSeries {
+        let mut h = *self;
+        h.coefficients[0][0][0] = 0.;
+        let mut power = PowerSeries::constant(1.);
+        let mut result = PowerSeries::constant(0.);
+        for c in coefficients {
+            result = result + power * c;
+            power = power * h;
+        }
+        result
+    }
+
+    /// Reciprocal, the constant term must not vanish.
+    pub fn recip(&self) -> PowerSeries {
+        let a = self.constant_term();
+        let mut coefficients = [0.; SIZE];
+        let mut term = 1. / a;
+        for c in coefficients.iter_mut() {
+            *c = term;
+            term *= -1. / a;
+        }
+        self.compose(coefficients)
+    }
+
+    /// Square root, the constant term must be positive.
+    pub fn sqrt(&self) -> PowerSeries {
+        let a = self.constant_term();
+        let mut coefficients = [0.; SIZE];
+        // binomial series of (a + h)^½
+        let mut term = a.sqrt();
+        for (n, c) in coefficients.iter_mut().enumerate() {
+            *c = term;
+            term *= (0.5 - n as f64) / ((n + 1) as f64 * a);
+        }
+        self.compose(coefficients)
+    }
+}
+
+
+fn for_each_exponent(mut f: impl FnMut(usize, usize, usize)) {
+    for i in 0..=DEGREE {
+        for j in 0..=DEGREE - i {
+            for k in 0..=DEGREE - i - j {
+                f(i, j, k);
+            }
+        }
+    }
+}
+
+
+impl Add for PowerSeries {
+    type Output = PowerSeries;
+
+    fn add(mut self, other: PowerSeries) -> PowerSeries {
+        for_each_exponent(|i, j, k| self.coefficients[i][j][k] += other.coefficients[i][j][k]);
+        self
+    }
+}
+
+
+impl Add<f64> for PowerSeries {
+    type Output = PowerSeries;
+
+    fn add(mut self, value: f64) -> PowerSeries {
+        self.coefficients[0][0][0] += value;
+        self
+    }
+}
+
+
+impl Sub for PowerSeries {
+    type Output = PowerSeries;
+
+    fn sub(self, other: PowerSeries) -> PowerSeries {
+        self + -other
+    }
+}
+
+
+impl Neg for PowerSeries {
+    type Output = PowerSeries;
+
+    fn neg(self) -> PowerSeries {
+        self * -1.
+    }
+}
+
+
+impl Mul<f64> for PowerSeries {
+    type Output = PowerSeries;
+
+    fn mul(mut self, value: f64) -> PowerSeries {
+        for_each_exponent(|i, j, k| self.coefficients[i][j][k] *= value);
+        self
+    }
+}
+
+
+impl Mul for PowerSeries {
+    type Output = PowerSeries;
+
+    fn mul(self, other: PowerSeries) -> PowerSeries {
+        let mut result = PowerSeries::constant(0.);
+        for_each_exponent(|i, j, k| {
+            let a = self.coefficients[i][j][k];
+            if a == 0. { return }
+            let remaining = DEGREE - i - j - k;
+            for l in 0..=remaining {
+                for m in 0..=remaining - l {
+                    for n in 0..=remaining - l - m {
+                        result.coefficients[i + l][j + m][k + n] += a * other.coefficients[l][m][n];
+                    }
+                }
+            }
+        });
+        result
+    }
+}
+
+
+impl Div for PowerSeries {
+    type Output = PowerSeries;
+
+    fn div(self, other: PowerSeries) -> PowerSeries {
+        self.mul(other.recip())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_series_of_functions() {
+        let (u, v, w) = (PowerSeries::variable(0), PowerSeries::variable(1), PowerSeries::variable(2));
+        // (1 + u)^5 keeps every term, (1 + u)^6 loses u^6
+        let p = (u + 1.) * (u + 1.) * (u + 1.) * (u + 1.) * (u + 1.) * (u + 1.);
+        assert_eq!(p.coefficient(5, 0, 0), 6.);
+        assert_eq!(p.coefficient(6, 0, 0), 0.);
+
+        // 1 / (1 - v) = Σ vⁿ, sqrt(4 + w) = 2 + w/4 - w²/64 + ...
+        let geometric = (PowerSeries::constant(1.) - v).recip();
+        assert_approx_eq!(geometric.coefficient(0, 5, 0), 1.);
+        let root = (w + 4.).sqrt();
+        assert_approx_eq!(root.coefficient(0, 0, 1), 0.25);
+        assert_approx_eq!(root.coefficient(0, 0, 2), -1. / 64.);
+        let square = root * root;
+        assert_approx_eq!(square.constant_term(), 4.);
+        for k in 2..=DEGREE {
+            assert_approx_eq!(square.coefficient(0, 0, k), 0.);
+        }
+
+        let ratio = (u * v + 2.) / (u * v + 2.);
+        assert_approx_eq!(ratio.constant_term(), 1.);
+        assert_approx_eq!(ratio.coefficient(2, 2, 0), 0.);
+        assert_eq!((u * v * w).truncated(2), PowerSeries::constant(0.));
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+
+/// Numbering of Zernike polynomials.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ZernikeOrdering {
+    /// Fringe (University of Arizona) numbering, terms with unit value at the pupil edge.
+    #[default]
+    Fringe,
+    /// Noll numbering, terms with unit RMS over the pupil.
+    Standard,
+}
+
+/// Radial degree `n` and azimuthal frequency `m`, cos mθ for m ≥ 0 and sin |m|θ for m < 0.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ZernikeTerm {
+    pub n: usize,
+    pub m: i32,
+}
+
+/// First terms of an ordering on a circular or annular pupil. On an annulus the radial
+/// polynomials are orthogonalized over ε ≤ ρ ≤ 1 and keep the normalization of the ordering.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZernikeBasis {
+    pub ordering: ZernikeOrdering,
+    /// Inner radius relative to the pupil radius, zero for a circular pupil.
+    pub obscuration: f64,
+    terms: Vec<ZernikeTerm>,
+    /// Coefficients of ρ⁰, ρ¹, ... of the normalized radial polynomial of every term.
+    radial: Vec<Vec<f64>>,
+}
+
+
+impl ZernikeOrdering {
+    /// Term of the 1-based index `j`.
+    pub fn term(&self, j: usize) -> ZernikeTerm {
+        let j = j.max(1);
+        match self {
+            ZernikeOrdering::Standard => {
+                let mut n = 0;
+                while (n + 1) * (n + 2) / 2 < j { n += 1 }
+                let k = j - n * (n + 1) / 2 - 1;
+                let m = if n % 2 == 0 { 2 * k.div_ceil(2) } else { 2 * (k / 2) + 1 } as i32;
+                ZernikeTerm { n, m: if m != 0 && j % 2 == 1 { -m } else { m } }
+            }
+            ZernikeOrdering::Fringe => {
+                // group s holds (n + |m|) / 2 = s with |m| falling from s to 0, (s + 1)² terms up
+                // to it. The classic 37 terms close with (12, 0), the last one of group 6.
+                let position = match j {
+                    37 => 49,
+                    38..=49 => j - 1,
+                    _ => j,
+                };
+                let mut s = 0;
+                while (s + 1) * (s + 1) < position { s += 1 }
+                let k = position - s * s - 1;
+                let m = (s - k / 2) as i32;
+                ZernikeTerm { n: 2 * s - m as usize, m: if k % 2 == 1 { -m } else { m } }
+            }
+        }
+    }
+}
+
+
+impl fmt::Display for ZernikeOrdering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZernikeOrdering::Fringe => write!(f, "Fringe"),
+            ZernikeOrdering::Standard => write!(f, "Standard"),
+        }
+    }
+}
+
+
+impl ZernikeBasis {
+    pub fn new(ordering: ZernikeOrdering, count: usize, obscuration: f64) -> ZernikeBasis {
+        let terms: Vec<ZernikeTerm> = (1..=count).map(|j| ordering.term(j)).collect();
+        let radial = terms.iter().map(|term| {
+            let polynomial = annular_radial(term.n, term.m.unsigned_abs() as usize, obscuration);
+            let scale = match ordering {
+                ZernikeOrdering::Fringe => 1. / polynomial.iter().sum::<f64>(),
+                ZernikeOrdering::Standard => {
+                    let angular = if term.m == 0 { 1. } else { 0.5 };
+                    let mean_square = inner(&polynomial, &polynomial, obscuration) * angular * 2. / (1. - obscuration * obscuration);
+                    1. / mean_square.sqrt()
+                }
+            };
+            polynomial.iter().map(|c| c * scale).collect()
+        }).collect();
+        ZernikeBasis { ordering, obscuration, terms, radial }
+    }
+
+    pub fn terms(&self) -> &[ZernikeTerm] {
+        &self.terms
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Term `index` (0-based) at polar pupil coordinates.
+    pub fn value(&self, index: usize, rho: f64, theta: f64) -> f64 {
+        let term = self.terms[index];
+        let radial = self.radial[index].iter().rev().fold(0., |sum, c| sum * rho + c);
+        let m = term.m.unsigned_abs() as f64;
+        match term.m {
+            0 => radial,
+            m_signed if m_signed > 0 => radial * (m * theta).cos(),
+            _ => radial * (m * theta).sin(),
+        }
+    }
+
+    /// All terms at cartesian pupil coordinates, θ measured from the x axis.
+    pub fn values(&self, x: f64, y: f64) -> Vec<f64> {
+        let (rho, theta) = (x.hypot(y), y.atan2(x));
+        (0..self.len()).map(|index| self.value(index, rho, theta)).collect()
+    }
+}
+
+
+/// Zernike radial polynomial R_n^m of the circular pupil as coefficients of ρ⁰..ρⁿ.
+fn circular_radial(n: usize, m: usize) -> Vec<f64> {
+    let factorial = |k: usize| (1..=k).map(|i| i as f64).product::<f64>();
+    let mut coefficients = vec![0.; n + 1];
+    for k in 0..=(n - m) / 2 {
+        let sign = if k % 2 == 0 { 1. } else { -1. };
+        coefficients[n - 2 * k] = sign * factorial(n - k)
+            / (factorial(k) * factorial((n + m) / 2 - k) * factorial((n - m) / 2 - k));
+    }
+    coefficients
+}
+
+
+/// ∫ p q ρ dρ over ε ≤ ρ ≤ 1.
+fn inner(p: &[f64], q: &[f64], obscuration: f64) -> f64 {
+    let mut sum = 0.;
+    for (a, pa) in p.iter().enumerate() {
+        for (b, qb) in q.iter().enumerate() {
+            let power = (a + b + 2) as i32;
+            sum += pa * qb * (1. - obscuration.powi(power)) / power as f64;
+        }
+    }
+    sum
+}
+
+
+/// Radial polynomial of degree n and frequency m orthogonal over the annulus to the lower ones
+/// of the same frequency, from Gram-Schmidt on the circular polynomials.
+fn annular_radial(n: usize, m: usize, obscuration: f64) -> Vec<f64> {
+    let mut previous: Vec<Vec<f64>> = Vec::new();
+    for degree in (m..=n).step_by(2) {
+        let mut polynomial = circular_radial(degree, m);
+        polynomial.resize(n + 1, 0.);
+        for lower in previous.iter() {
+            let factor = inner(&polynomial, lower, obscuration) / inner(lower, lower, obscuration);
+            polynomial.iter_mut().zip(lower.iter()).for_each(|(c, l)| *c -= factor * l);
+        }
+        previous.push(polynomial);
+    }
+    previous.pop().unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_orderings() {
+        let standard: Vec<(usize, i32)> = (1..=11).map(|j| ZernikeOrdering::Standard.term(j)).map(|t| (t.n, t.m)).collect();
+        assert_eq!(standard, [(0, 0), (1, 1), (1, -1), (2, 0), (2, -2), (2, 2), (3, -1), (3, 1), (3, -3), (3, 3), (4, 0)]);
+        let fringe: Vec<(usize, i32)> = (1..=17).map(|j| ZernikeOrdering::Fringe.term(j)).map(|t| (t.n, t.m)).collect();
+        assert_eq!(fringe, [
+            (0, 0), (1, 1), (1, -1), (2, 0), (2, 2), (2, -2), (3, 1), (3, -1), (4, 0),
+            (3, 3), (3, -3), (4, 2), (4, -2), (5, 1), (5, -1), (6, 0), (4, 4),
+        ]);
+        assert_eq!(ZernikeOrdering::Fringe.term(37), ZernikeTerm { n: 12, m: 0 });
+        assert_eq!(ZernikeOrdering::Fringe.term(38), ZernikeTerm { n: 6, m: 6 });
+        assert_eq!(ZernikeOrdering::Fringe.term(50), ZernikeTerm { n: 7, m: 7 });
+    }
+
+    #[test]
+    fn test_polynomials() {
+        let fringe = ZernikeBasis::new(ZernikeOrdering::Fringe, 9, 0.);
+        assert_approx_eq!(fringe.value(8, 0.5, 0.), 6. * 0.0625 - 6. * 0.25 + 1., 1e-12);
+        let standard = ZernikeBasis::new(ZernikeOrdering::Standard, 11, 0.);
+        assert_approx_eq!(standard.value(10, 1., 0.), 5f64.sqrt(), 1e-12);
+        assert_approx_eq!(standard.value(5, 0.5, 0.3), 6f64.sqrt() * 0.25 * 0.6f64.cos(), 1e-12);
+    }
+
+    #[test]
+    fn test_annular_orthonormality() {
+        let obscuration = 0.4;
+        let basis = ZernikeBasis::new(ZernikeOrdering::Standard, 15, obscuration);
+        let (rings, spokes) = (400, 64);
+        let mut gram = vec![vec![0.; basis.len()]; basis.len()];
+        let mut area = 0.;
+        for i in 0..rings {
+            let rho = obscuration + (1. - obscuration) * (i as f64 + 0.5) / rings as f64;
+            for k in 0..spokes {
+                let (sin, cos) = (2. * PI * k as f64 / spokes as f64).sin_cos();
+                let values = basis.values(rho * cos, rho * sin);
+                area += rho;
+                for (row, a) in gram.iter_mut().zip(values.iter()) {
+                    row.iter_mut().zip(values.iter()).for_each(|(entry, b)| *entry += a * b * rho);
+                }
+            }
+        }
+        for (a, row) in gram.iter().enumerate() {
+            for (b, entry) in row.iter().enumerate() {
+                assert_approx_eq!(entry / area, if a == b { 1. } else { 0. }, 1e-4);
+            }
+        }
+    }
+}
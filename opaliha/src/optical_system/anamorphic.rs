@@ -0,0 +1,177 @@
+use std::fmt;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+use crate::optical_system::transfer_matrix::RayTransferMatrix;
+
+/// Principal section of a system with two symmetry planes.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    /// XZ plane, sagittal for meridional fields.
+    X,
+    /// YZ plane.
+    Y,
+}
+
+/// First-order properties of one section. Distances after the last surface are measured along
+/// the axial ray from its intersection with the last refracting surface.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SectionProperties {
+    pub section: Section,
+    pub effective_focal_length: f64,
+    pub back_focal_length: f64,
+    pub front_focal_length: f64,
+    /// Paraxial image of the axial object point.
+    pub image_distance: f64,
+    /// Lateral magnification, zero for an object at infinity.
+    pub magnification: f64,
+    /// Global z of the entrance pupil.
+    pub entrance_pupil_position: f64,
+    pub exit_pupil_distance: f64,
+    pub exit_pupil_diameter: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnamorphicProperties {
+    pub x: SectionProperties,
+    pub y: SectionProperties,
+    /// X image distance minus Y image distance.
+    pub astigmatic_difference: f64,
+}
+
+
+impl SequentialOpticalSystem {
+    /// First-order matrix of the section up to surface `last` around the axial ray, see
+    /// `differential_matrix`. Works for biconic, cylindrical and tilted surfaces as long as XZ
+    /// and YZ stay symmetry planes.
+    pub fn section_matrix(&self, section: Section, last: usize) -> Option<RayTransferMatrix> {
+        self.differential_matrix(last).map(|matrix| matrix.section(section))
+    }
+
+    pub fn section_properties(&self, section: Section) -> Option<SectionProperties> {
+        let wavelength = self.primary_wavelength();
+        let indices = self.indices_at(wavelength);
+        let last = self.last_refracting_surface();
+        let (n, n_image) = (indices[0], indices[last]);
+        let system = self.section_matrix(section, last)?;
+        let stop = self.section_matrix(section, self.stop_surface().ok()?)?;
+
+        let entrance_pupil_position = n * stop.b / stop.a;
+        let image_distance_of = |(y, nu): (f64, f64)| -y * n_image / nu;
+        let (image_distance, magnification) = if self.object_is_infinite() {
+            (image_distance_of(system.apply(1., 0.)), 0.)
+        } else {
+            let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+            let marginal = system.apply(object_distance / n, 1.);
+            (image_distance_of(marginal), 1. / marginal.1)
+        };
+
+        let chief = system.apply(-stop.b / stop.a, 1.);
+        let exit_pupil_distance = image_distance_of(chief);
+        let radius = self.entrance_pupil_radius(wavelength).ok()?;
+        let (y, nu) = self.paraxial_marginal_start(radius, wavelength).ok()?;
+        let marginal = system.apply(y, nu);
+        let exit_radius = marginal.0 + exit_pupil_distance * marginal.1 / n_image;
+
+        Some(SectionProperties {
+            section,
+            effective_focal_length: -1. / system.c,
+            back_focal_length: image_distance_of(system.apply(1., 0.)),
+            front_focal_length: n * system.d / system.c,
+            image_distance,
+            magnification,
+            entrance_pupil_position,
+            exit_pupil_distance,
+            exit_pupil_diameter: 2. * exit_radius.abs(),
+        })
+    }
+
+    pub fn anamorphic_properties(&self) -> Option<AnamorphicProperties> {
+        let x = self.section_properties(Section::X)?;
+        let y = self.section_properties(Section::Y)?;
+        Some(AnamorphicProperties { x, y, astigmatic_difference: x.image_distance - y.image_distance })
+    }
+}
+
+
+impl fmt::Display for AnamorphicProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "                         |      X       |      Y")?;
+        let rows = [
+            ("Effective focal length ", self.x.effective_focal_length, self.y.effective_focal_length),
+            ("Back focal length      ", self.x.back_focal_length, self.y.back_focal_length),
+            ("Front focal length     ", self.x.front_focal_length, self.y.front_focal_length),
+            ("Image distance         ", self.x.image_distance, self.y.image_distance),
+            ("Magnification          ", self.x.magnification, self.y.magnification),
+            ("Entrance pupil position", self.x.entrance_pupil_position, self.y.entrance_pupil_position),
+            ("Exit pupil distance    ", self.x.exit_pupil_distance, self.y.exit_pupil_distance),
+            ("Exit pupil diameter    ", self.x.exit_pupil_diameter, self.y.exit_pupil_diameter),
+        ];
+        for (name, x, y) in rows {
+            writeln!(f, "{}  | {:12.6} | {:12.6}", name, x, y)?;
+        }
+        writeln!(f, "Astigmatic difference    : {:.6}", self.astigmatic_difference)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use num::Float;
+    use crate::optical_system::fixtures::{air, constant_glass as glass, surface};
+    use crate::optical_system::surfaces::{BiconicSurface, TiltedSurface};
+
+    fn biconic_lens(rx: (f64, f64), ry: (f64, f64)) -> SequentialOpticalSystem {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, air()));
+        system.add_surface(Box::new(BiconicSurface::new(rx.0, ry.0, 5., glass())));
+        system.add_surface(Box::new(BiconicSurface::new(rx.1, ry.1, 40., air())));
+        system.add_surface(surface(0., 0., air()));
+        system
+    }
+
+    fn thick_lens_focal_length(r1: f64, r2: f64) -> f64 {
+        let c = |r: f64| if r == 0. { 0. } else { 1. / r };
+        1. / (0.5 * (c(r1) - c(r2) + 0.5 * 5. * c(r1) * c(r2) / 1.5))
+    }
+
+    #[test]
+    fn test_biconic_sections_match_thick_lenses() {
+        let system = biconic_lens((40., -80.), (60., -60.));
+        let properties = system.anamorphic_properties().unwrap();
+        assert_approx_eq!(properties.x.effective_focal_length, thick_lens_focal_length(40., -80.), 1e-6);
+        assert_approx_eq!(properties.y.effective_focal_length, thick_lens_focal_length(60., -60.), 1e-6);
+        // Y section is what the axially symmetric paraxial trace sees
+        let first_order = system.first_order_properties().unwrap();
+        assert_approx_eq!(properties.y.back_focal_length, first_order.back_focal_length, 1e-6);
+        assert_approx_eq!(properties.y.exit_pupil_diameter, first_order.pupils.exit_diameter, 1e-6);
+        assert!(properties.astigmatic_difference < 0.);
+    }
+
+    #[test]
+    fn test_cylindrical_lens_has_no_power_in_x() {
+        let system = biconic_lens((0., 0.), (50., 0.));
+        let properties = system.anamorphic_properties().unwrap();
+        assert!(properties.x.effective_focal_length.is_infinite() || properties.x.effective_focal_length.abs() > 1e9);
+        assert_approx_eq!(properties.y.effective_focal_length, 100., 1e-6);
+        assert_approx_eq!(properties.y.back_focal_length, 100. - 5. / 1.5, 1e-6);
+    }
+
+    #[test]
+    fn test_tilted_plate_astigmatism() {
+        // diverging beam from a point 50 mm in front of a plate tilted by 30°
+        let (tilt, thickness) = (30f64.to_radians(), 10.);
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., 50., air()));
+        system.add_surface(Box::new(TiltedSurface::around_x(30., thickness / tilt.cos(), glass())));
+        system.add_surface(Box::new(TiltedSurface::around_x(30., 20., air())));
+        system.add_surface(surface(0., 0., air()));
+        let properties = system.anamorphic_properties().unwrap();
+
+        let refracted = Float::asin(tilt.sin() / 1.5);
+        let (cos_i, cos_r) = (tilt.cos(), refracted.cos());
+        let expected = thickness * (cos_r * cos_r - cos_i * cos_i) / (1.5 * cos_r.powi(3));
+        assert_approx_eq!(properties.astigmatic_difference.abs(), expected, 1e-6);
+        assert_approx_eq!(properties.x.magnification, 1., 1e-6);
+    }
+}
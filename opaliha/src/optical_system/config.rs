@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
+use crate::database::wavelengths::{Wavelength, WavelengthTable, WeightedWavelength};
+use crate::geometry::point::Point3;
+use crate::materials::catalog;
+use crate::materials::material::{Air, Material};
+use crate::optical_system::parameters::{Aperture, ApertureType};
+use crate::optical_system::pupils::ApertureError;
+use crate::optical_system::sequential_optical_system::{OpticalSurfaceType, SequentialOpticalSystem, StandardSurface};
+use crate::optical_system::surfaces::{BiconicSurface, EvenAsphereSurface, TiltedSurface};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Yaml(yaml_rust::ScanError),
+    Format(String),
+    UnknownMaterial(String),
+    Aperture(ApertureError),
+}
+
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "can not read config: {}", err),
+            ConfigError::Yaml(err) => write!(f, "invalid yaml: {}", err),
+            ConfigError::Format(msg) => write!(f, "invalid config: {}", msg),
+            ConfigError::UnknownMaterial(name) => write!(f, "unknown material: {}", name),
+            ConfigError::Aperture(err) => write!(f, "invalid aperture: {}", err),
+        }
+    }
+}
+
+
+impl Error for ConfigError {}
+
+
+/// Reads a sequential system from a yaml config (see `configs/`). Every element describes
+/// a surface, either nested under the `surface` key or written next to it. The first element
+/// is the object, the last one the image, the `stop` surface role selects the aperture stop.
+/// Biconic surfaces take `radius`/`conic` for the YZ section and `radius_x`/`conic_x` for the
+/// XZ one, tilted planes take `x_tangent` and `y_tangent`. Even aspheres take `radius`, `conic`
+/// and the list of `coefficients` of r⁴, r⁶, ...
+/// Surfaces keep their optional `name` and `comment`.
+/// An object without thickness is placed at infinity. Optional `wavelengths` hold `values` in
+/// micrometres (plain numbers or `{value, weight}`) and the index of the `primary` one.
+pub fn load_sequential_system<P: AsRef<Path>>(path: P) -> Result<SequentialOpticalSystem, ConfigError> {
+    let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    parse_sequential_system(&text)
+}
+
+
+pub fn parse_sequential_system(text: &str) -> Result<SequentialOpticalSystem, ConfigError> {
+    let documents = YamlLoader::load_from_str(text).map_err(ConfigError::Yaml)?;
+    let root = documents.first().ok_or_else(|| ConfigError::Format("empty document".to_string()))?;
+    let optical_system = &root["optical_system"];
+    match optical_system["type"].as_str() {
+        Some("sequential") | None => {}
+        Some(other) => return Err(ConfigError::Format(format!("unsupported system type {}", other))),
+    }
+    let elements = optical_system["elements"].as_vec()
+        .ok_or_else(|| ConfigError::Format("no elements".to_string()))?;
+
+    let mut system = SequentialOpticalSystem::default();
+    for (index, element) in elements.iter().enumerate() {
+        let surface = match &element["surface"] {
+            Yaml::Hash(_) => &element["surface"],
+            _ => element,
+        };
+        let role = surface["surface_role"].as_str().unwrap_or("");
+        if role == "stop" {
+            system.parameters.stop_surface = index;
+        }
+        let default_thickness = if index == 0 { f64::INFINITY } else { 0. };
+        let name = surface["name"].as_str().unwrap_or("").to_string();
+        let comment = surface["comment"].as_str().unwrap_or("").to_string();
+        let thickness = value(&surface["thickness"]).unwrap_or(default_thickness);
+        let material = material(&surface["material"])?;
+        let semi_diameter = value(&surface["clear_semi_diameter"]);
+        let position = Point3::origin();
+        match surface["surface_type"].as_str() {
+            Some("standard") | None => system.add_surface(Box::new(StandardSurface {
+                name, comment, surface_type: OpticalSurfaceType::Standard,
+                radius: value(&surface["radius"]).unwrap_or(0.),
+                thickness, material, position, semi_diameter,
+            })),
+            Some("biconic") => system.add_surface(Box::new(BiconicSurface {
+                name, comment, surface_type: OpticalSurfaceType::Biconic,
+                radius_x: value(&surface["radius_x"]).unwrap_or(0.),
+                radius_y: value(&surface["radius"]).unwrap_or(0.),
+                conic_x: value(&surface["conic_x"]).unwrap_or(0.),
+                conic_y: value(&surface["conic"]).unwrap_or(0.),
+                thickness, material, position, semi_diameter,
+            })),
+            Some("even_asphere") => system.add_surface(Box::new(EvenAsphereSurface {
+                name, comment, surface_type: OpticalSurfaceType::EvenAsphere,
+                radius: value(&surface["radius"]).unwrap_or(0.),
+                conic: value(&surface["conic"]).unwrap_or(0.),
+                coefficients: surface["coefficients"].as_vec()
+                    .map_or_else(Vec::new, |values| values.iter().filter_map(number).collect()),
+                thickness, material, position, semi_diameter,
+            })),
+            Some("tilted") => system.add_surface(Box::new(TiltedSurface {
+                name, comment, surface_type: OpticalSurfaceType::Tilted,
+                x_tangent: value(&surface["x_tangent"]).unwrap_or(0.),
+                y_tangent: value(&surface["y_tangent"]).unwrap_or(0.),
+                thickness, material, position, semi_diameter,
+            })),
+            Some(other) => return Err(ConfigError::Format(format!("unsupported surface type {}", other))),
+        }
+    }
+
+    if let Yaml::Hash(_) = optical_system["aperture"] {
+        let aperture = &optical_system["aperture"];
+        let aperture_type = match aperture["type"].as_str() {
+            Some("entrance_pupil_diameter") => ApertureType::EntrancePupilDiameter,
+            Some("image_space_f_number") => ApertureType::ImageSpaceFNumber,
+            Some("object_space_na") => ApertureType::ObjectSpaceNA,
+            Some("paraxial_working_f_number") => ApertureType::ParaxialWorkingFNumber,
+            Some("float_by_stop_size") => ApertureType::FloatByStopSize,
+            other => return Err(ConfigError::Format(format!("unknown aperture type {:?}", other))),
+        };
+        system.parameters.aperture = Aperture { aperture_type, value: number(&aperture["value"]).unwrap_or(0.) };
+    }
+
+    if let Yaml::Hash(_) = optical_system["wavelengths"] {
+        system.parameters.wavelengths = wavelengths(&optical_system["wavelengths"])?;
+    }
+    system.check_aperture().map_err(ConfigError::Aperture)?;
+    Ok(system)
+}
+
+
+/// Value of `{value: ..., is_fixed: ...}` parameters, plain numbers are accepted as well.
+fn value(node: &Yaml) -> Option<f64> {
+    match node {
+        Yaml::Hash(_) => number(&node["value"]),
+        _ => number(node),
+    }
+}
+
+
+fn number(node: &Yaml) -> Option<f64> {
+    match node {
+        Yaml::Real(_) => node.as_f64(),
+        Yaml::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+
+fn wavelengths(node: &Yaml) -> Result<WavelengthTable, ConfigError> {
+    let values = node["values"].as_vec()
+        .ok_or_else(|| ConfigError::Format("no wavelength values".to_string()))?;
+    let entries = values.iter()
+        .map(|entry| {
+            let micrometers = value(entry).ok_or_else(|| ConfigError::Format("invalid wavelength".to_string()))?;
+            let weight = number(&entry["weight"]).unwrap_or(1.);
+            if !(weight.is_finite() && weight > 0.) {
+                return Err(ConfigError::Format(format!("wavelength weight {} is not positive", weight)));
+            }
+            Ok(WeightedWavelength { wavelength: Wavelength::from_um(micrometers), weight })
+        })
+        .collect::<Result<Vec<_>, ConfigError>>()?;
+    let primary = node["primary"].as_i64().unwrap_or(0) as usize;
+    WavelengthTable::weighted(entries, primary)
+        .ok_or_else(|| ConfigError::Format(format!("primary wavelength {} out of range", primary)))
+}
+
+
+fn material(node: &Yaml) -> Result<Box<dyn Material>, ConfigError> {
+    let name = node["name"].as_str().unwrap_or("air");
+    match node["material_type"].as_str() {
+        Some("air") | None if name.eq_ignore_ascii_case("air") => Ok(Box::new(Air::default())),
+        _ => catalog::glass(name)
+            .map(|glass| Box::new(glass) as Box<dyn Material>)
+            .ok_or_else(|| ConfigError::UnknownMaterial(name.to_string())),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_load_apochromat() {
+        let system = load_sequential_system(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/apochromat3.yaml")).unwrap();
+        assert_eq!(system.surfaces.len(), 7);
+        assert_eq!(system.parameters.stop_surface, 2);
+        assert!(system.object_is_infinite());
+        assert_eq!(system.surfaces[2].radius(), Some(960.041));
+        assert_eq!(system.surfaces[2].material().name(), "N-BAF10");
+        assert_eq!(system.surfaces[3].material().name(), "N-KZFS4");
+        assert_eq!(system.surfaces[2].semi_diameter(), Some(80.));
+        assert_approx_eq!(system.surfaces[2].position().z, 100.);
+        // the last lens is followed by air and the image sits at the best focus, next to the
+        // paraxial one
+        assert_eq!(system.surfaces[5].material().name(), "air");
+        assert_approx_eq!(system.surfaces[6].position().z, 190. + 1461.656);
+        let paraxial = system.paraxial_image_position(system.primary_wavelength());
+        assert!((paraxial - system.surfaces[6].position().z).abs() < 1.);
+        assert_eq!(system.parameters.wavelengths.entries().len(), 6);
+        assert_approx_eq!(system.primary_wavelength().um(), 0.5875618);
+
+        // nothing refracts in front of the stop, so the entrance pupil sits on it
+        let pupils = system.pupils().unwrap();
+        assert_approx_eq!(pupils.entrance_position, 100.);
+    }
+
+    #[test]
+    fn test_cylindrical_surface() {
+        let text = "optical_system:\n  elements:\n    - surface:\n        surface_role: object\n    - surface:\n        surface_type: biconic\n        radius: 50\n        thickness: 5\n        material:\n          name: N-BK7\n    - surface:\n        thickness: 90\n    - surface:\n        surface_role: image\n";
+        let system = parse_sequential_system(text).unwrap();
+        assert_eq!(system.surfaces[1].radius(), Some(50.));
+        let properties = system.anamorphic_properties().unwrap();
+        assert!(properties.x.effective_focal_length.abs() > 1e9);
+    }
+
+    #[test]
+    fn test_surface_name() {
+        let text = "optical_system:\n  elements:\n    - surface:\n        surface_role: object\n    - surface:\n        name: front\n        surface_role: stop\n        radius: 50\n        thickness: 5\n        material:\n          name: N-BK7\n    - surface:\n        surface_role: image\n";
+        let system = parse_sequential_system(text).unwrap();
+        assert_eq!(system.surfaces[1].name(), "front");
+        assert_eq!(system.surfaces[2].name(), "");
+        assert_eq!(system.parameters.stop_surface, 1);
+    }
+
+    #[test]
+    fn test_even_asphere_surface() {
+        let text = "optical_system:\n  elements:\n    - surface:\n        surface_role: object\n    - surface:\n        surface_type: even_asphere\n        radius: 40\n        conic: -0.5\n        coefficients: [1.0e-6, 0]\n        thickness: 5\n        material:\n          name: N-BK7\n    - surface:\n        surface_role: image\n";
+        let system = parse_sequential_system(text).unwrap();
+        assert_eq!(system.surfaces[1].radius(), Some(40.));
+        assert_approx_eq!(system.surfaces[1].fourth_order_deformation(), -0.5 / (8. * 40f64.powi(3)) + 1e-6);
+    }
+
+    #[test]
+    fn test_stop_on_image() {
+        let text = "optical_system:\n  elements:\n    - surface:\n        surface_role: object\n    - surface:\n        radius: 50\n        thickness: 5\n        material:\n          name: N-BK7\n    - surface:\n        surface_role: stop\n";
+        assert!(matches!(parse_sequential_system(text), Err(ConfigError::Aperture(ApertureError::StopOutOfRange(2)))));
+    }
+
+    #[test]
+    fn test_zero_wavelength_weight() {
+        let text = "optical_system:\n  wavelengths:\n    values:\n      - value: 0.55\n        weight: 0\n  elements:\n    - surface:\n        surface_role: object\n";
+        assert!(matches!(parse_sequential_system(text), Err(ConfigError::Format(_))));
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        let text = "optical_system:\n  elements:\n    - surface:\n        material:\n          name: nope\n";
+        assert!(matches!(parse_sequential_system(text), Err(ConfigError::UnknownMaterial(_))));
+    }
+
+    #[test]
+    fn test_numerical_aperture_at_infinity() {
+        let text = "optical_system:\n  aperture:\n    type: object_space_na\n    value: 0.1\n  elements:\n    - surface:\n        surface_role: object\n    - surface:\n        radius: 50\n        thickness: 5\n        material:\n          name: N-BK7\n    - surface:\n        surface_role: image\n";
+        assert!(matches!(
+            parse_sequential_system(text),
+            Err(ConfigError::Aperture(ApertureError::NumericalApertureAtInfinity))
+        ));
+    }
+}
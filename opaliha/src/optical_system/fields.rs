@@ -0,0 +1,201 @@
+use crate::geometry::point::Point3;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::geometry::vector::Vector3;
+use crate::optical_system::parameters::{FieldRaw, FieldType};
+use crate::optical_system::pupils::ApertureError;
+use crate::optical_system::sequential_optical_system::{SequentialOpticalSystem, Trace};
+
+const REAL_IMAGE_HEIGHT_ITERATIONS: usize = 50;
+const REAL_IMAGE_HEIGHT_TOLERANCE: f64 = 1e-10;
+
+/// Object-space description of a field point.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldPoint {
+    /// Plane wave from an object at infinity travelling along the direction.
+    Direction(Vector3),
+    /// Point on the object surface.
+    Point(Point3),
+}
+
+
+impl SequentialOpticalSystem {
+    /// Point the chief ray passes through: the center of the paraxial entrance pupil.
+    pub fn pupil_reference(&self) -> Result<Point3, ApertureError> {
+        let z = self.entrance_pupil_position(self.primary_wavelength())?;
+        Ok(Point3 { z, ..Point3::origin() })
+    }
+
+    /// Point of the paraxial entrance pupil at normalized pupil coordinates.
+    pub fn pupil_point(&self, px: f64, py: f64) -> Result<Point3, ApertureError> {
+        let radius = self.entrance_pupil_radius(self.primary_wavelength())?;
+        Ok(Point3 { x: px * radius, y: py * radius, ..self.pupil_reference()? })
+    }
+
+    /// Ray from the field point through normalized pupil coordinates (px, py).
+    pub fn pupil_ray(&self, point: FieldPoint, px: f64, py: f64) -> Result<Ray3, ApertureError> {
+        self.launch_ray(point, self.pupil_point(px, py)?)
+    }
+
+    /// Field point from object coordinates: direction tangents (tan θx, tan θy) for an
+    /// object at infinity, object heights otherwise.
+    pub fn field_point_at(&self, cx: f64, cy: f64) -> FieldPoint {
+        if self.object_is_infinite() {
+            return FieldPoint::Direction(Vector3 { x: cx, y: cy, z: 1. }.clone_normalized())
+        }
+        let z = self.surfaces.first().map_or(0., |s| s.position().z);
+        FieldPoint::Point(Point3 { x: cx, y: cy, z })
+    }
+
+    /// Converts a row of the field table into an object-space field point. Returns `None` when
+    /// the field type does not fit the object (object height at infinity) or when the real
+    /// image height can not be reached.
+    pub fn field_point(&self, field: &FieldRaw) -> Option<FieldPoint> {
+        let field_type = self.parameters.field_data.field_type;
+        match field_type {
+            FieldType::AngleDeg => {
+                let (tx, ty) = (field.xfield.to_radians().tan(), field.yfield.to_radians().tan());
+                if self.object_is_infinite() { return Some(self.field_point_at(tx, ty)) }
+                let object_z = self.surfaces.first()?.position().z;
+                let reference = self.pupil_reference().ok()?;
+                let to_object = object_z - reference.z;
+                Some(self.field_point_at(reference.x + tx * to_object, reference.y + ty * to_object))
+            }
+            FieldType::ObjectHeight => {
+                if self.object_is_infinite() { return None }
+                Some(self.field_point_at(field.xfield, field.yfield))
+            }
+            FieldType::ParaxImageHeight => {
+                let scale = self.paraxial_image_height_per_object_unit();
+                Some(self.field_point_at(field.xfield / scale, field.yfield / scale))
+            }
+            FieldType::RealImageHeight => self.solve_real_image_height(field.xfield, field.yfield),
+        }
+    }
+
+    /// Ray from the field point towards `target` at the primary wavelength. Rays from an object
+    /// at infinity start on the plane wavefront passing through the pupil reference, so all of
+    /// them share a zero phase.
+    pub fn launch_ray(&self, point: FieldPoint, target: Point3) -> Result<Ray3, ApertureError> {
+        let ray = match point {
+            FieldPoint::Direction(direction) => {
+                let shift = (target - self.pupil_reference()?).dot(direction);
+                Ray3::new(target + direction * (-shift), direction)
+            }
+            FieldPoint::Point(origin) => Ray3::new(origin, target - origin),
+        };
+        Ok(ray.with_wavelength(self.primary_wavelength()))
+    }
+
+    pub fn chief_ray(&self, point: FieldPoint) -> Result<Ray3, ApertureError> {
+        self.launch_ray(point, self.pupil_reference()?)
+    }
+
+    /// Position of the real chief ray on the image surface.
+    pub fn real_image_height(&self, point: FieldPoint) -> Option<(f64, f64)> {
+        let ray = self.trace_ray(self.chief_ray(point).ok()?);
+        if ray.validity != RayValidity::VALID { return None }
+        Some((ray.origin.x, ray.origin.y))
+    }
+
+    /// Height of the paraxial chief ray on the image surface per unit of object coordinate,
+    /// NaN when the system fails `check_aperture`.
+    pub fn paraxial_image_height_per_object_unit(&self) -> f64 {
+        let n = self.indices_at(self.primary_wavelength())[0];
+        let reference = self.entrance_pupil_position(self.primary_wavelength()).unwrap_or(f64::NAN);
+        let (y, u) = if self.object_is_infinite() {
+            (-reference, 1.)
+        } else {
+            let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+            let u = -1. / (reference + object_distance);
+            (1. + u * object_distance, u)
+        };
+        let rays = self.trace_paraxial(y, n * u, self.primary_wavelength());
+        self.image_surface().map_or(f64::NAN, |image| rays[image].y)
+    }
+
+    /// Newton iteration on object coordinates until the real chief ray lands on (x, y).
+    fn solve_real_image_height(&self, x: f64, y: f64) -> Option<FieldPoint> {
+        let scale = self.paraxial_image_height_per_object_unit();
+        let mut c = (x / scale, y / scale);
+        let tolerance = REAL_IMAGE_HEIGHT_TOLERANCE * x.hypot(y).max(1.);
+
+        for _ in 0..REAL_IMAGE_HEIGHT_ITERATIONS {
+            let h = self.real_image_height(self.field_point_at(c.0, c.1))?;
+            let residual = (h.0 - x, h.1 - y);
+            if residual.0.hypot(residual.1) < tolerance {
+                return Some(self.field_point_at(c.0, c.1))
+            }
+            let delta = 1e-7 * c.0.hypot(c.1).max(1. / scale.abs());
+            let hx = self.real_image_height(self.field_point_at(c.0 + delta, c.1))?;
+            let hy = self.real_image_height(self.field_point_at(c.0, c.1 + delta))?;
+            let (j11, j21) = ((hx.0 - h.0) / delta, (hx.1 - h.1) / delta);
+            let (j12, j22) = ((hy.0 - h.0) / delta, (hy.1 - h.1) / delta);
+            let determinant = j11 * j22 - j12 * j21;
+            if determinant == 0. { return None }
+            c.0 -= (j22 * residual.0 - j12 * residual.1) / determinant;
+            c.1 -= (j11 * residual.1 - j21 * residual.0) / determinant;
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::parameters::FieldData;
+    use crate::optical_system::fixtures::constant_singlet as singlet;
+
+    #[test]
+    fn test_vignetting_factors() {
+        let mut field = FieldRaw::new(0., 10.);
+        field.vdy = 0.1;
+        field.vcy = 0.2;
+        field.vcx = 0.5;
+        let (px, py) = field.vignetted_pupil(1., 1.);
+        assert_approx_eq!(px, 0.5);
+        assert_approx_eq!(py, 0.9);
+
+        field.van = 90.;
+        let (px, py) = field.vignetted_pupil(1., 0.);
+        assert_approx_eq!(px, -0.1);
+        assert_approx_eq!(py, 0.5);
+    }
+
+    #[test]
+    fn test_field_types_at_infinity() {
+        let mut system = singlet(f64::INFINITY);
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 5.)]);
+        let angle = system.field_point(&system.parameters.field_data.rows[0]).unwrap();
+        assert_eq!(angle, FieldPoint::Direction(Vector3{x: 0., y: 5f64.to_radians().tan(), z: 1.}.clone_normalized()));
+
+        system.parameters.field_data.field_type = FieldType::ObjectHeight;
+        assert_eq!(system.field_point(&system.parameters.field_data.rows[0]), None);
+
+        system.parameters.field_data = FieldData::new(FieldType::ParaxImageHeight, vec![FieldRaw::new(0., -2.)]);
+        let paraxial = system.field_point(&system.parameters.field_data.rows[0]).unwrap();
+        let scale = system.paraxial_image_height_per_object_unit();
+        match paraxial {
+            FieldPoint::Direction(d) => assert_approx_eq!(d.y / d.z * scale, -2.),
+            FieldPoint::Point(_) => panic!(),
+        }
+
+        system.parameters.field_data.field_type = FieldType::RealImageHeight;
+        let real = system.field_point(&system.parameters.field_data.rows[0]).unwrap();
+        let (x, y) = system.real_image_height(real).unwrap();
+        assert_approx_eq!(x, 0.);
+        assert_approx_eq!(y, -2., 1e-9);
+    }
+
+    #[test]
+    fn test_object_height_field() {
+        let mut system = singlet(200.);
+        system.parameters.field_data = FieldData::new(FieldType::ObjectHeight, vec![FieldRaw::new(1., 3.)]);
+        let point = system.field_point(&system.parameters.field_data.rows[0]).unwrap();
+        assert_eq!(point, FieldPoint::Point(Point3{x: 1., y: 3., z: -200.}));
+        let chief = system.chief_ray(point).unwrap();
+        assert_approx_eq!(chief.direction.y / chief.direction.z, -3. / 200.);
+        assert_eq!(system.parameters.field_data.max_field(), 10f64.sqrt());
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::pupils::{ApertureError, Pupils};
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Gaussian properties of an axially symmetric system at the primary wavelength.
+/// Positions are global z, distances are signed along the axis.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FirstOrderProperties {
+    pub wavelength: Wavelength,
+    pub effective_focal_length: f64,
+    /// From the last refracting surface to the rear focal point.
+    pub back_focal_length: f64,
+    /// From the first surface to the front focal point.
+    pub front_focal_length: f64,
+    pub front_principal_plane: f64,
+    pub back_principal_plane: f64,
+    /// Where the paraxial marginal ray crosses the axis in image space.
+    pub paraxial_image_position: f64,
+    /// Height of the paraxial chief ray of the largest field in the paraxial image plane.
+    pub paraxial_image_height: f64,
+    /// Lateral magnification, zero for an object at infinity.
+    pub magnification: f64,
+    /// EFL over the entrance pupil diameter.
+    pub image_space_f_number: f64,
+    /// 1 / (2 n' |u'|) of the paraxial marginal ray.
+    pub working_f_number: f64,
+    pub pupils: Pupils,
+    /// n ū y − n u ȳ of the marginal and the largest field chief ray.
+    pub lagrange_invariant: f64,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Reduced angle gained after the last refracting surface by rays starting with unit height
+    /// on the first surface and with unit reduced angle in object space. These are the C and D
+    /// elements of the system matrix.
+    fn image_space_slopes(&self, wavelength: Wavelength) -> (f64, f64) {
+        let last = self.last_refracting_surface();
+        let c = self.trace_paraxial(1., 0., wavelength)[last].nu;
+        let d = self.trace_paraxial(0., 1., wavelength)[last].nu;
+        (c, d)
+    }
+
+    /// Object coordinate of the largest field: direction tangent at infinity, height otherwise.
+    pub(crate) fn largest_field_coordinate(&self) -> f64 {
+        let field_data = &self.parameters.field_data;
+        let largest = field_data.rows.iter()
+            .max_by(|a, b| a.xfield.hypot(a.yfield).total_cmp(&b.xfield.hypot(b.yfield)));
+        match largest.and_then(|row| self.field_point(row)) {
+            Some(FieldPoint::Direction(d)) => d.x.hypot(d.y) / d.z,
+            Some(FieldPoint::Point(p)) => p.x.hypot(p.y),
+            None => 0.,
+        }
+    }
+
+    /// Global z where paraxial rays from the axial object point of the wavelength cross the
+    /// axis in image space. It does not depend on the aperture.
+    pub fn paraxial_image_position(&self, wavelength: Wavelength) -> f64 {
+        let last = self.last_refracting_surface();
+        let axial = if self.object_is_infinite() {
+            self.trace_paraxial(1., 0., wavelength)
+        } else {
+            let n = self.indices_at(wavelength)[0];
+            self.trace_paraxial(self.surfaces[0].thickness().unwrap_or(0.), n, wavelength)
+        };
+        self.surfaces[last].position().z - axial[last].y * self.indices_at(wavelength)[last] / axial[last].nu
+    }
+
+    pub fn first_order_properties(&self) -> Result<FirstOrderProperties, ApertureError> {
+        let wavelength = self.primary_wavelength();
+        let indices = self.indices_at(wavelength);
+        let last = self.last_refracting_surface();
+        let (n, n_image) = (indices[0], indices[last]);
+        let z_last = self.surfaces[last].position().z;
+
+        let (c, d) = self.image_space_slopes(wavelength);
+        let power = -c;
+        let parallel = self.trace_paraxial(1., 0., wavelength)[last];
+        let back_focal_length = -parallel.y * n_image / parallel.nu;
+        let front_focal_length = n * d / c;
+        let back_principal_plane = z_last + back_focal_length - n_image / power;
+        let front_principal_plane = front_focal_length + n / power;
+
+        let marginal = self.paraxial_marginal_ray(wavelength)?;
+        let paraxial_image_position = self.paraxial_image_position(wavelength);
+        let field = self.largest_field_coordinate();
+        let chief: Vec<_> = self.paraxial_chief_ray(wavelength)?.iter()
+            .map(|ray| (ray.y * field, ray.nu * field))
+            .collect();
+        let paraxial_image_height = chief[last].0 + (paraxial_image_position - z_last) * chief[last].1 / n_image;
+
+        let magnification = if self.object_is_infinite() { 0. } else { marginal[0].nu / marginal[last].nu };
+        let pupils = self.pupils()?;
+        Ok(FirstOrderProperties {
+            wavelength,
+            effective_focal_length: self.effective_focal_length(wavelength),
+            back_focal_length,
+            front_focal_length,
+            front_principal_plane,
+            back_principal_plane,
+            paraxial_image_position,
+            paraxial_image_height,
+            magnification,
+            image_space_f_number: self.effective_focal_length(wavelength) / pupils.entrance_diameter,
+            working_f_number: 1. / (2. * marginal[last].nu.abs()),
+            pupils,
+            lagrange_invariant: chief[1].1 * marginal[1].y - marginal[1].nu * chief[1].0,
+        })
+    }
+}
+
+
+impl fmt::Display for FirstOrderProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Wavelength                  : {}", self.wavelength)?;
+        writeln!(f, "Effective focal length      : {:.6}", self.effective_focal_length)?;
+        writeln!(f, "Back focal length           : {:.6}", self.back_focal_length)?;
+        writeln!(f, "Front focal length          : {:.6}", self.front_focal_length)?;
+        writeln!(f, "Front principal plane       : {:.6}", self.front_principal_plane)?;
+        writeln!(f, "Back principal plane        : {:.6}", self.back_principal_plane)?;
+        writeln!(f, "Paraxial image position     : {:.6}", self.paraxial_image_position)?;
+        writeln!(f, "Paraxial image height       : {:.6}", self.paraxial_image_height)?;
+        writeln!(f, "Paraxial magnification      : {:.6}", self.magnification)?;
+        writeln!(f, "Image space F/#             : {:.6}", self.image_space_f_number)?;
+        writeln!(f, "Paraxial working F/#        : {:.6}", self.working_f_number)?;
+        writeln!(f, "Entrance pupil position     : {:.6}", self.pupils.entrance_position)?;
+        writeln!(f, "Entrance pupil diameter     : {:.6}", self.pupils.entrance_diameter)?;
+        writeln!(f, "Exit pupil position         : {:.6}", self.pupils.exit_position)?;
+        writeln!(f, "Exit pupil diameter         : {:.6}", self.pupils.exit_diameter)?;
+        writeln!(f, "Lagrange invariant          : {:.6}", self.lagrange_invariant)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::catalog;
+    use crate::materials::material::Air;
+    use crate::optical_system::parameters::{FieldData, FieldRaw, FieldType};
+    use crate::optical_system::fixtures::{constant_singlet as singlet, surface};
+
+    /// Cooke triplet from the Zemax samples, f = 50 mm, F/5, 20° half field.
+    fn cooke_triplet() -> SequentialOpticalSystem {
+        let sk16 = || Box::new(catalog::glass("SK16").unwrap());
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(surface(22.01359, 3.25896, sk16()));
+        system.add_surface(surface(-435.7604, 6.007637, Box::new(Air::default())));
+        system.add_surface(surface(-22.21328, 0.99997, Box::new(catalog::glass("F2").unwrap())));
+        system.add_surface(surface(20.29192, 4.750409, Box::new(Air::default())));
+        system.add_surface(surface(79.6836, 2.95208, sk16()));
+        system.add_surface(surface(-18.3783, 42.20778, Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system.parameters.stop_surface = 4;
+        system.parameters.field_data = FieldData::new(
+            FieldType::AngleDeg, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., 14.), FieldRaw::new(0., 20.)],
+        );
+        system
+    }
+
+    #[test]
+    fn test_thick_singlet() {
+        let properties = singlet(f64::INFINITY).first_order_properties().unwrap();
+        // lensmaker's equation for a thick lens with n = 1.5, R = ±50, t = 5
+        let power = 0.5 * (2. / 50. - 0.5 * 5. / (1.5 * 50. * 50.));
+        let f = 1. / power;
+        let shift = f * 0.5 * 5. / (1.5 * 50.);
+        assert_approx_eq!(properties.effective_focal_length, f);
+        assert_approx_eq!(properties.back_focal_length, f - shift);
+        assert_approx_eq!(properties.front_focal_length, -(f - shift));
+        assert_approx_eq!(properties.back_principal_plane, 5. - shift);
+        assert_approx_eq!(properties.front_principal_plane, shift);
+        assert_approx_eq!(properties.paraxial_image_position, 5. + f - shift);
+        assert_approx_eq!(properties.image_space_f_number, f / 10.);
+        assert_approx_eq!(properties.working_f_number, f / 10.);
+        assert_eq!(properties.magnification, 0.);
+
+        // Newton's equation from the principal planes for a finite object
+        let mut finite = singlet(200.);
+        finite.parameters.field_data = FieldData::new(FieldType::ObjectHeight, vec![FieldRaw::new(0., 4.)]);
+        let properties = finite.first_order_properties().unwrap();
+        let s = -200. - properties.front_principal_plane;
+        let s_image = properties.paraxial_image_position - properties.back_principal_plane;
+        assert_approx_eq!(1. / s_image - 1. / s, power);
+        assert_approx_eq!(properties.magnification, s_image / s);
+        assert_approx_eq!(properties.paraxial_image_height, 4. * properties.magnification);
+    }
+
+    #[test]
+    fn test_cooke_triplet() {
+        let system = cooke_triplet();
+        let properties = system.first_order_properties().unwrap();
+        // the sample uses the old SK16 and F2 melts, the catalog has the current ones
+        assert_approx_eq!(properties.effective_focal_length, 50., 5e-2);
+        assert_approx_eq!(properties.back_focal_length, 42.3, 1e-1);
+        assert_approx_eq!(properties.paraxial_image_height, properties.effective_focal_length * 20f64.to_radians().tan());
+        assert_approx_eq!(properties.image_space_f_number, properties.effective_focal_length / 10.);
+        let image_z = system.surfaces[7].position().z;
+        assert_approx_eq!(properties.pupils.entrance_position, 11.512, 1e-2);
+        assert_approx_eq!(properties.pupils.exit_position - image_z, -50.962, 1e-2);
+        assert_approx_eq!(properties.pupils.exit_diameter, 10.236, 1e-2);
+
+        // H = −n'u'h' in the image plane
+        let marginal = system.paraxial_marginal_ray(properties.wavelength).unwrap();
+        assert_approx_eq!(properties.lagrange_invariant, -marginal[6].nu * properties.paraxial_image_height);
+    }
+}
@@ -0,0 +1,108 @@
+//! Surfaces and small systems shared by the tests.
+use crate::database::wavelengths::WavelengthTable;
+use crate::geometry::point::Point3;
+use crate::materials::catalog;
+use crate::materials::dispersion::Dispersion;
+use crate::materials::glass::Glass;
+use crate::materials::material::{Air, Material};
+use crate::optical_system::parameters::{Aperture, ApertureType, FieldData, FieldRaw, FieldType};
+use crate::optical_system::sequential_optical_system::{OpticalSurfaceType, SequentialOpticalSystem, StandardSurface};
+
+pub fn surface(radius: f64, thickness: f64, material: Box<dyn Material>) -> Box<StandardSurface> {
+    apertured_surface(radius, thickness, material, None)
+}
+
+pub fn apertured_surface(radius: f64, thickness: f64, material: Box<dyn Material>, semi_diameter: Option<f64>) -> Box<StandardSurface> {
+    Box::new(StandardSurface {
+        name: "".to_string(),
+        comment: "".to_string(),
+        surface_type: OpticalSurfaceType::Standard,
+        radius,
+        thickness,
+        material,
+        position: Point3::origin(),
+        semi_diameter,
+    })
+}
+
+pub fn air() -> Box<dyn Material> {
+    Box::new(Air::default())
+}
+
+pub fn bk7() -> Box<dyn Material> {
+    Box::new(catalog::glass("N-BK7").unwrap())
+}
+
+/// Dispersionless glass with n = 1.5.
+pub fn constant_glass() -> Box<dyn Material> {
+    Box::new(Glass::new("n1.5", Dispersion::Constant(1.5)))
+}
+
+/// Dispersionless biconvex lens of R = ±50, 5 mm thick, the object `object_distance` in front
+/// of it and the image surface 45 mm behind it.
+pub fn constant_singlet(object_distance: f64) -> SequentialOpticalSystem {
+    let mut system = SequentialOpticalSystem::default();
+    system.add_surface(surface(0., object_distance, air()));
+    system.add_surface(surface(50., 5., constant_glass()));
+    system.add_surface(surface(-50., 45., air()));
+    system.add_surface(surface(0., 0., air()));
+    system
+}
+
+/// Stop plane 5 mm in front of a biconvex lens of R = ±50, 5 mm thick, object at infinity and
+/// the image surface 47 mm behind the lens.
+pub fn front_stop_lens(material: Box<dyn Material>) -> SequentialOpticalSystem {
+    let mut system = SequentialOpticalSystem::default();
+    system.add_surface(surface(0., f64::INFINITY, air()));
+    system.add_surface(surface(0., 5., air()));
+    system.add_surface(surface(50., 5., material));
+    system.add_surface(surface(-50., 47., air()));
+    system.add_surface(surface(0., 0., air()));
+    system
+}
+
+/// Biconvex lens of R = ±60, 4 mm thick, object at infinity, stop on the first surface and
+/// the image surface `image_distance` behind the lens.
+pub fn lens(material: Box<dyn Material>, image_distance: f64) -> SequentialOpticalSystem {
+    apertured_lens(material, image_distance, None, None)
+}
+
+fn apertured_lens(material: Box<dyn Material>, image_distance: f64, front: Option<f64>, rear: Option<f64>) -> SequentialOpticalSystem {
+    let mut system = SequentialOpticalSystem::default();
+    system.add_surface(surface(0., f64::INFINITY, air()));
+    system.add_surface(apertured_surface(60., 4., material, front));
+    system.add_surface(apertured_surface(-60., image_distance, air(), rear));
+    system.add_surface(surface(0., 0., air()));
+    system
+}
+
+/// N-BK7 `lens`.
+pub fn singlet(image_distance: f64) -> SequentialOpticalSystem {
+    lens(bk7(), image_distance)
+}
+
+/// N-BK7 `lens` with the semi-diameters of its front and rear surfaces set.
+pub fn apertured_singlet(image_distance: f64, front: f64, rear: f64) -> SequentialOpticalSystem {
+    apertured_lens(bk7(), image_distance, Some(front), Some(rear))
+}
+
+/// N-BK7 `lens` with the image surface in its paraxial focus.
+pub fn focused_singlet() -> SequentialOpticalSystem {
+    let system = singlet(50.);
+    let marginal = system.paraxial_marginal_ray(system.primary_wavelength()).unwrap();
+    singlet(-marginal[2].y / marginal[2].nu)
+}
+
+/// Sets the entrance pupil diameter and fields on axis and `max_field` degrees along y.
+pub fn with_pupil_and_field(mut system: SequentialOpticalSystem, diameter: f64, max_field: f64) -> SequentialOpticalSystem {
+    system.parameters.aperture = Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: diameter };
+    system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![FieldRaw::new(0., 0.), FieldRaw::new(0., max_field)]);
+    system
+}
+
+/// `with_pupil_and_field` with the visible wavelength table.
+pub fn visible(system: SequentialOpticalSystem, diameter: f64, max_field: f64) -> SequentialOpticalSystem {
+    let mut system = with_pupil_and_field(system, diameter, max_field);
+    system.parameters.wavelengths = WavelengthTable::visible();
+    system
+}
@@ -1,3 +1,18 @@
+pub mod anamorphic;
+pub mod config;
+pub mod fields;
+pub mod first_order;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod paraxial;
 pub mod parameters;
+pub mod pupil_sampling;
+pub mod pupils;
+pub mod ray_aiming;
+pub mod ray_batch;
+pub mod ray_generation;
+pub mod ray_path;
 pub mod sequential_optical_system;
+pub mod surfaces;
 pub mod tracing;
+pub mod transfer_matrix;
@@ -1,30 +1,149 @@
-enum FieldType {
+use num::Float;
+use crate::database::wavelengths::WavelengthTable;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FieldType {
+    /// Angle in object space, degrees. Requires nothing but the entrance pupil.
+    #[default]
     AngleDeg,
+    /// Height on the object surface, lens units. Requires a finite object.
     ObjectHeight,
+    /// Height of the paraxial chief ray on the image surface.
     ParaxImageHeight,
+    /// Height of the real chief ray on the image surface, found iteratively.
     RealImageHeight,
 }
 
-enum FieldNormalization {
-    RADIAL,
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FieldNormalization {
+    #[default]
+    Radial,
 }
 
+/// One row of the field table. Vignetting factors rescale normalized pupil coordinates:
+/// decenter (VDX, VDY), compression (VCX, VCY) and rotation angle in degrees (VAN).
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct FieldRaw {
-    field_type: FieldType,
-    xfield: f64,
-    yfield: f64,
-    weight: f64,
-    vdy: f64,
-    vcx: f64,
-    vcy: f64,
-    van: f64,
+    pub xfield: f64,
+    pub yfield: f64,
+    pub weight: f64,
+    pub vdx: f64,
+    pub vdy: f64,
+    pub vcx: f64,
+    pub vcy: f64,
+    pub van: f64,
 }
 
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct FieldData {
-    rows: Vec<FieldRaw>,
+    pub field_type: FieldType,
+    pub normalization: FieldNormalization,
+    pub rows: Vec<FieldRaw>,
+}
+
+/// What fixes the size of the system aperture.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ApertureType {
+    #[default]
+    EntrancePupilDiameter,
+    /// Paraxial F/# for an object at infinity, EFL / EPD.
+    ImageSpaceFNumber,
+    /// n·sin of the marginal ray angle in object space, finite objects only.
+    ObjectSpaceNA,
+    /// 1 / (2 n' u') of the paraxial marginal ray in image space.
+    ParaxialWorkingFNumber,
+    /// The semi-diameter of the stop surface sets the aperture, the value is ignored.
+    FloatByStopSize,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Aperture {
+    pub aperture_type: ApertureType,
+    pub value: f64,
+}
+
+/// How normalized pupil coordinates are turned into launched rays.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum RayAiming {
+    /// Rays go through the paraxial entrance pupil.
+    #[default]
+    Paraxial,
+    /// Rays are iterated onto the real stop. With `pupil_shift_and_compression` the first
+    /// guess is corrected for the real entrance pupil shift and compression.
+    Real { pupil_shift_and_compression: bool },
 }
 
+#[derive(Debug, Clone)]
 pub struct SequentialParameters {
     pub field_data: FieldData,
+    pub aperture: Aperture,
+    /// Index of the aperture stop surface.
+    pub stop_surface: usize,
+    pub ray_aiming: RayAiming,
+    pub wavelengths: WavelengthTable,
     pub name: String,
 }
+
+
+impl FieldRaw {
+    pub fn new(xfield: f64, yfield: f64) -> FieldRaw {
+        FieldRaw { xfield, yfield, weight: 1., vdx: 0., vdy: 0., vcx: 0., vcy: 0., van: 0. }
+    }
+
+    /// Maps normalized pupil coordinates of the unvignetted pupil onto the vignetted one.
+    pub fn vignetted_pupil(&self, px: f64, py: f64) -> (f64, f64) {
+        let x = self.vdx + px * (1. - self.vcx);
+        let y = self.vdy + py * (1. - self.vcy);
+        let (sin, cos) = Float::sin_cos(self.van.to_radians());
+        (x * cos - y * sin, x * sin + y * cos)
+    }
+}
+
+
+impl Default for Aperture {
+    fn default() -> Self {
+        Aperture { aperture_type: ApertureType::EntrancePupilDiameter, value: 10. }
+    }
+}
+
+
+impl Default for SequentialParameters {
+    fn default() -> Self {
+        SequentialParameters {
+            field_data: FieldData::default(),
+            aperture: Aperture::default(),
+            stop_surface: 1,
+            ray_aiming: RayAiming::default(),
+            wavelengths: WavelengthTable::default(),
+            name: String::new(),
+        }
+    }
+}
+
+
+impl Default for FieldRaw {
+    fn default() -> Self {
+        FieldRaw::new(0., 0.)
+    }
+}
+
+
+impl FieldData {
+    pub fn new(field_type: FieldType, rows: Vec<FieldRaw>) -> FieldData {
+        FieldData { field_type, normalization: FieldNormalization::default(), rows }
+    }
+
+    /// Largest radial field value, used to normalize field coordinates.
+    pub fn max_field(&self) -> f64 {
+        self.rows.iter().map(|row| row.xfield.hypot(row.yfield)).fold(0., f64::max)
+    }
+
+    /// Normalized field coordinates (Hx, Hy) of a row.
+    pub fn normalized(&self, row: &FieldRaw) -> (f64, f64) {
+        let max_field = self.max_field();
+        if max_field == 0. { return (0., 0.) }
+        match self.normalization {
+            FieldNormalization::Radial => (row.xfield / max_field, row.yfield / max_field),
+        }
+    }
+}
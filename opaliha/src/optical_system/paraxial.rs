@@ -0,0 +1,58 @@
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Paraxial ray at a surface: height on the vertex plane and reduced angle n·u
+/// in the space after the surface.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ParaxialRay {
+    pub y: f64,
+    pub nu: f64,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Refraction index of the space after every surface.
+    pub fn indices_at(&self, wavelength: Wavelength) -> Vec<f64> {
+        self.surfaces.iter().map(|s| s.material().refraction_index_at(wavelength)).collect()
+    }
+
+    pub fn curvatures(&self) -> Vec<f64> {
+        self.surfaces.iter().map(|s| s.radius().map_or(0., |r| 1. / r)).collect()
+    }
+
+    pub fn object_is_infinite(&self) -> bool {
+        self.surfaces.first().and_then(|s| s.thickness()).is_some_and(|t| t.is_infinite())
+    }
+
+    /// y-nu trace of a ray with height `y` on the first surface after the object and reduced
+    /// angle `nu` in object space. Entry `k` holds the ray at surface `k`; entry 0 is the object
+    /// surface, its height is infinite for inclined rays from an object at infinity.
+    pub fn trace_paraxial(&self, y: f64, nu: f64, wavelength: Wavelength) -> Vec<ParaxialRay> {
+        let indices = self.indices_at(wavelength);
+        let curvatures = self.curvatures();
+        let mut rays = Vec::with_capacity(self.surfaces.len());
+        if self.surfaces.is_empty() { return rays }
+
+        let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+        let object_height = if nu == 0. { y } else { y - object_distance * nu / indices[0] };
+        rays.push(ParaxialRay { y: object_height, nu });
+
+        let mut ray = ParaxialRay { y, nu };
+        for k in 1..self.surfaces.len() {
+            if k > 1 {
+                let thickness = self.surfaces[k - 1].thickness().unwrap_or(0.);
+                ray.y += thickness * ray.nu / indices[k - 1];
+            }
+            ray.nu -= ray.y * curvatures[k] * (indices[k] - indices[k - 1]);
+            rays.push(ray);
+        }
+        rays
+    }
+
+    /// Effective focal length, the reciprocal of the system power between the first surface
+    /// after the object and the last surface.
+    pub fn effective_focal_length(&self, wavelength: Wavelength) -> f64 {
+        let rays = self.trace_paraxial(1., 0., wavelength);
+        -1. / rays.last().map_or(0., |ray| ray.nu)
+    }
+}
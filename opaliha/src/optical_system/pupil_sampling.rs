@@ -0,0 +1,102 @@
+use std::f64::consts::PI;
+
+/// How normalized pupil coordinates are sampled inside the unit circle.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PupilSampling {
+    /// Square grid with `size` points across the pupil diameter.
+    Square { size: usize },
+    /// Center point and `rings` concentric rings, ring k holding 6k points.
+    Hexapolar { rings: usize },
+    /// Square grid with every point shifted randomly inside its cell, reproducible for a seed.
+    Dithered { size: usize, seed: u64 },
+}
+
+
+impl Default for PupilSampling {
+    fn default() -> Self {
+        PupilSampling::Hexapolar { rings: 6 }
+    }
+}
+
+
+impl PupilSampling {
+    /// Points (px, py) inside the unit circle.
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        match *self {
+            PupilSampling::Square { size } => grid(size, |_| (0., 0.)),
+            PupilSampling::Hexapolar { rings } => {
+                let mut points = vec![(0., 0.)];
+                for ring in 1..=rings {
+                    let radius = ring as f64 / rings as f64;
+                    let count = 6 * ring;
+                    points.extend((0..count).map(|k| {
+                        let (sin, cos) = (2. * PI * k as f64 / count as f64).sin_cos();
+                        (radius * sin, radius * cos)
+                    }));
+                }
+                points
+            }
+            PupilSampling::Dithered { size, seed } => {
+                let mut random = SplitMix64(seed);
+                grid(size, |step| {
+                    (step * (random.next_f64() - 0.5), step * (random.next_f64() - 0.5))
+                })
+            }
+        }
+    }
+}
+
+
+/// Square grid over [-1, 1]² clipped to the unit circle, `offset` shifts every point and gets
+/// the cell size.
+fn grid(size: usize, mut offset: impl FnMut(f64) -> (f64, f64)) -> Vec<(f64, f64)> {
+    if size < 2 { return vec![(0., 0.)] }
+    let step = 2. / (size - 1) as f64;
+    let mut points = Vec::with_capacity(size * size);
+    for i in 0..size {
+        for j in 0..size {
+            let shift = offset(step);
+            let point = (-1. + i as f64 * step + shift.0, -1. + j as f64 * step + shift.1);
+            if point.0.hypot(point.1) <= 1. + 1e-12 {
+                points.push(point);
+            }
+        }
+    }
+    points
+}
+
+
+/// Small deterministic generator, enough for dithering.
+struct SplitMix64(u64);
+
+
+impl SplitMix64 {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_patterns() {
+        assert_eq!(PupilSampling::Hexapolar { rings: 3 }.points().len(), 1 + 6 + 12 + 18);
+        let square = PupilSampling::Square { size: 5 }.points();
+        assert_eq!(square.len(), 13);
+        assert!(square.contains(&(0., 1.)));
+
+        let dithered = PupilSampling::Dithered { size: 20, seed: 7 }.points();
+        assert_eq!(dithered, PupilSampling::Dithered { size: 20, seed: 7 }.points());
+        assert_ne!(dithered, PupilSampling::Dithered { size: 20, seed: 8 }.points());
+        assert!(dithered.iter().all(|p| p.0.hypot(p.1) <= 1. + 1e-12));
+        assert!(dithered.len() > 250);
+    }
+}
@@ -0,0 +1,329 @@
+use std::error::Error;
+use std::fmt;
+use num::Float;
+use crate::database::wavelengths::Wavelength;
+use crate::optical_system::paraxial::ParaxialRay;
+use crate::optical_system::parameters::ApertureType;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Paraxial pupils. Positions are global z of the pupil planes, the entrance pupil lives in
+/// object space and the exit pupil in image space.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Pupils {
+    pub entrance_position: f64,
+    pub entrance_diameter: f64,
+    pub exit_position: f64,
+    pub exit_diameter: f64,
+    pub stop_semi_diameter: f64,
+}
+
+/// Aperture definitions the system can not turn into an entrance pupil.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ApertureError {
+    /// Object-space numerical aperture needs an object at a finite distance.
+    NumericalApertureAtInfinity,
+    /// Float by stop size needs the semi-diameter of the stop surface.
+    StopWithoutSemiDiameter,
+    /// The paraxial entrance pupil lies at infinity, the system is telecentric in object space.
+    EntrancePupilAtInfinity,
+    /// The stop index is the object, the image or past the last surface.
+    StopOutOfRange(usize),
+}
+
+
+impl fmt::Display for ApertureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApertureError::NumericalApertureAtInfinity => write!(f, "object-space NA needs a finite object distance"),
+            ApertureError::StopWithoutSemiDiameter => write!(f, "float by stop size needs a stop semi-diameter"),
+            ApertureError::EntrancePupilAtInfinity => write!(f, "entrance pupil is at infinity"),
+            ApertureError::StopOutOfRange(stop) => write!(f, "stop surface {} is not between the object and the image", stop),
+        }
+    }
+}
+
+
+impl Error for ApertureError {}
+
+
+impl SequentialOpticalSystem {
+    /// Index of the aperture stop, an error unless it lies between the object and the image.
+    pub fn stop_surface(&self) -> Result<usize, ApertureError> {
+        let stop = self.parameters.stop_surface;
+        if stop == 0 || stop > self.last_refracting_surface() { return Err(ApertureError::StopOutOfRange(stop)) }
+        Ok(stop)
+    }
+
+    /// Makes surface `stop` the aperture stop, the parameter is left unchanged when it is not a
+    /// surface between the object and the image.
+    pub fn set_stop_surface(&mut self, stop: usize) -> Result<(), ApertureError> {
+        if stop == 0 || stop > self.last_refracting_surface() { return Err(ApertureError::StopOutOfRange(stop)) }
+        self.parameters.stop_surface = stop;
+        Ok(())
+    }
+
+    /// Index of the last surface before the image, its medium fills image space.
+    pub fn last_refracting_surface(&self) -> usize {
+        self.surfaces.len().saturating_sub(2)
+    }
+
+    /// Index of the image surface, `None` unless the system has an object and an image.
+    pub fn image_surface(&self) -> Option<usize> {
+        self.surfaces.len().checked_sub(1).filter(|&image| image > 0)
+    }
+
+    /// Surface an analysis is evaluated on: `surface` when it lies after the object and not
+    /// past the image, the image surface when `None`.
+    pub fn checked_surface(&self, surface: Option<usize>) -> Option<usize> {
+        let image = self.image_surface()?;
+        match surface {
+            None => Some(image),
+            Some(surface) => (1..=image).contains(&surface).then_some(surface),
+        }
+    }
+
+    /// Paraxial image of the stop center in object space.
+    pub fn entrance_pupil_position(&self, wavelength: Wavelength) -> Result<f64, ApertureError> {
+        let n = self.indices_at(wavelength)[0];
+        let stop = self.stop_surface()?;
+        let tilted = self.trace_paraxial(0., n, wavelength)[stop].y;
+        let shifted = self.trace_paraxial(1., 0., wavelength)[stop].y;
+        // the unit height parallel ray crossing the stop center puts the pupil at infinity
+        if shifted.abs() < 1e-12 || !shifted.is_finite() { return Err(ApertureError::EntrancePupilAtInfinity) }
+        // the ray with slope 1 crossing the stop center has height -tilted / shifted on surface 1
+        Ok(tilted / shifted)
+    }
+
+    /// Checks that the aperture definition gives an entrance pupil at the primary wavelength.
+    pub fn check_aperture(&self) -> Result<(), ApertureError> {
+        let wavelength = self.primary_wavelength();
+        self.entrance_pupil_position(wavelength)?;
+        self.entrance_pupil_radius(wavelength)?;
+        Ok(())
+    }
+
+    /// Start values (height on surface 1, reduced angle) of the paraxial chief ray with unit
+    /// object-space slope, or unit object height for a finite object.
+    pub fn paraxial_chief_start(&self, wavelength: Wavelength) -> Result<(f64, f64), ApertureError> {
+        let n = self.indices_at(wavelength)[0];
+        let entrance = self.entrance_pupil_position(wavelength)?;
+        if self.object_is_infinite() { return Ok((-entrance, n)) }
+        let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+        let u = -1. / (entrance + object_distance);
+        Ok((1. + u * object_distance, n * u))
+    }
+
+    /// Start values of the paraxial marginal ray through the edge of an entrance pupil of
+    /// the given radius.
+    pub fn paraxial_marginal_start(&self, radius: f64, wavelength: Wavelength) -> Result<(f64, f64), ApertureError> {
+        if self.object_is_infinite() { return Ok((radius, 0.)) }
+        let n = self.indices_at(wavelength)[0];
+        let entrance = self.entrance_pupil_position(wavelength)?;
+        let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+        let u = radius / (entrance + object_distance);
+        Ok((u * object_distance, n * u))
+    }
+
+    pub fn paraxial_marginal_ray(&self, wavelength: Wavelength) -> Result<Vec<ParaxialRay>, ApertureError> {
+        let radius = self.entrance_pupil_radius(wavelength)?;
+        let (y, nu) = self.paraxial_marginal_start(radius, wavelength)?;
+        Ok(self.trace_paraxial(y, nu, wavelength))
+    }
+
+    pub fn paraxial_chief_ray(&self, wavelength: Wavelength) -> Result<Vec<ParaxialRay>, ApertureError> {
+        let (y, nu) = self.paraxial_chief_start(wavelength)?;
+        Ok(self.trace_paraxial(y, nu, wavelength))
+    }
+
+    /// Reduced angle n' |u'| of the paraxial marginal ray in image space.
+    pub fn image_space_aperture(&self, wavelength: Wavelength) -> Result<f64, ApertureError> {
+        Ok(self.paraxial_marginal_ray(wavelength)?[self.last_refracting_surface()].nu.abs())
+    }
+
+    /// Entrance pupil radius following the aperture definition of the system.
+    pub fn entrance_pupil_radius(&self, wavelength: Wavelength) -> Result<f64, ApertureError> {
+        let aperture = self.parameters.aperture;
+        let unit = || -> Result<Vec<ParaxialRay>, ApertureError> {
+            let (y, nu) = self.paraxial_marginal_start(1., wavelength)?;
+            Ok(self.trace_paraxial(y, nu, wavelength))
+        };
+        let radius = match aperture.aperture_type {
+            ApertureType::EntrancePupilDiameter => aperture.value / 2.,
+            ApertureType::ImageSpaceFNumber => {
+                (self.effective_focal_length(wavelength) / (2. * aperture.value)).abs()
+            }
+            ApertureType::ObjectSpaceNA => {
+                if self.object_is_infinite() { return Err(ApertureError::NumericalApertureAtInfinity) }
+                let n = self.indices_at(wavelength)[0];
+                let object_distance = self.surfaces[0].thickness().unwrap_or(0.);
+                let slope = Float::tan(Float::asin(aperture.value / n));
+                (slope * (self.entrance_pupil_position(wavelength)? + object_distance)).abs()
+            }
+            ApertureType::ParaxialWorkingFNumber => {
+                let nu = unit()?[self.last_refracting_surface()].nu;
+                1. / (2. * aperture.value * nu.abs())
+            }
+            ApertureType::FloatByStopSize => {
+                let stop = self.stop_surface()?;
+                let semi_diameter = self.surfaces[stop].semi_diameter().ok_or(ApertureError::StopWithoutSemiDiameter)?;
+                semi_diameter / unit()?[stop].y.abs()
+            }
+        };
+        Ok(radius)
+    }
+
+    pub fn pupils(&self) -> Result<Pupils, ApertureError> {
+        let wavelength = self.primary_wavelength();
+        let entrance_position = self.entrance_pupil_position(wavelength)?;
+        let entrance_radius = self.entrance_pupil_radius(wavelength)?;
+        let indices = self.indices_at(wavelength);
+        let last = self.last_refracting_surface();
+        let z_last = self.surfaces[last].position().z;
+        let marginal = self.paraxial_marginal_ray(wavelength)?;
+        let chief = self.paraxial_chief_ray(wavelength)?;
+
+        let chief_slope = chief[last].nu / indices[last];
+        let exit_position = z_last - chief[last].y / chief_slope;
+        let marginal_slope = marginal[last].nu / indices[last];
+        let exit_radius = marginal[last].y + marginal_slope * (exit_position - z_last);
+
+        Ok(Pupils {
+            entrance_position,
+            entrance_diameter: 2. * entrance_radius,
+            exit_position,
+            exit_diameter: 2. * exit_radius.abs(),
+            stop_semi_diameter: marginal[self.stop_surface()?].y.abs(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use crate::geometry::ray::RayValidity;
+    use crate::materials::catalog;
+    use crate::materials::material::Air;
+    use crate::optical_system::fields::FieldPoint;
+    use crate::optical_system::parameters::{Aperture, ApertureType};
+    use crate::optical_system::pupils::ApertureError;
+    use crate::optical_system::sequential_optical_system::{SequentialOpticalSystem, Trace};
+    use crate::geometry::vector::Vector3;
+    use crate::optical_system::fixtures::apertured_surface as surface;
+
+    /// Singlet with the stop 20 mm behind it.
+    fn rear_stop_singlet(object_distance: f64) -> SequentialOpticalSystem {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., object_distance, Box::new(Air::default()), None));
+        system.add_surface(surface(60., 6., Box::new(catalog::glass("N-BK7").unwrap()), None));
+        system.add_surface(surface(-60., 20., Box::new(Air::default()), None));
+        system.add_surface(surface(0., 40., Box::new(Air::default()), Some(3.)));
+        system.add_surface(surface(0., 0., Box::new(Air::default()), None));
+        system.parameters.stop_surface = 3;
+        system
+    }
+
+    #[test]
+    fn test_entrance_pupil_images_stop_center() {
+        let system = rear_stop_singlet(f64::INFINITY);
+        let pupils = system.pupils().unwrap();
+        // the stop behind a positive lens is imaged further behind it
+        assert!(pupils.entrance_position > 26.);
+
+        let direction = Vector3{x: 0., y: 0.001, z: 1.};
+        let chief = system.chief_ray(FieldPoint::Direction(direction.clone_normalized())).unwrap();
+        let mut truncated = rear_stop_singlet(f64::INFINITY);
+        truncated.surfaces.truncate(4);
+        let at_stop = truncated.trace_ray(chief);
+        assert_eq!(at_stop.validity, RayValidity::VALID);
+        assert_approx_eq!(at_stop.origin.y, 0., 1e-7);
+    }
+
+    #[test]
+    fn test_aperture_types() {
+        let mut system = rear_stop_singlet(f64::INFINITY);
+        let efl = system.effective_focal_length(system.primary_wavelength());
+
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::ImageSpaceFNumber, value: 5. };
+        assert_approx_eq!(system.pupils().unwrap().entrance_diameter, efl / 5.);
+
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::ParaxialWorkingFNumber, value: 5. };
+        let marginal = system.paraxial_marginal_ray(system.primary_wavelength()).unwrap();
+        assert_approx_eq!(1. / (2. * marginal[3].nu.abs()), 5.);
+
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::FloatByStopSize, value: 0. };
+        assert_approx_eq!(system.pupils().unwrap().stop_semi_diameter, 3.);
+
+        let mut finite = rear_stop_singlet(200.);
+        finite.parameters.aperture = Aperture { aperture_type: ApertureType::ObjectSpaceNA, value: 0.02 };
+        let entrance = finite.pupils().unwrap();
+        let slope = (entrance.entrance_diameter / 2.) / (entrance.entrance_position + 200.);
+        assert_approx_eq!(slope.atan().sin(), 0.02);
+    }
+
+    #[test]
+    fn test_invalid_apertures() {
+        let mut system = rear_stop_singlet(f64::INFINITY);
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::ObjectSpaceNA, value: 0.02 };
+        assert_eq!(system.check_aperture(), Err(ApertureError::NumericalApertureAtInfinity));
+
+        system.parameters.aperture = Aperture { aperture_type: ApertureType::FloatByStopSize, value: 0. };
+        system.parameters.stop_surface = 2;
+        assert_eq!(system.pupils(), Err(ApertureError::StopWithoutSemiDiameter));
+        assert_eq!(system.paraxial_marginal_ray(system.primary_wavelength()), Err(ApertureError::StopWithoutSemiDiameter));
+        assert_eq!(system.seidel_aberrations().map(|_| ()), Err(ApertureError::StopWithoutSemiDiameter));
+        assert_eq!(system.diffraction_cutoff(system.primary_wavelength()), None);
+
+        assert_eq!(system.set_stop_surface(4), Err(ApertureError::StopOutOfRange(4)));
+        assert_eq!(system.set_stop_surface(0), Err(ApertureError::StopOutOfRange(0)));
+        assert_eq!(system.parameters.stop_surface, 2);
+        system.parameters.stop_surface = 4;
+        assert_eq!(system.check_aperture(), Err(ApertureError::StopOutOfRange(4)));
+        system.set_stop_surface(3).unwrap();
+        assert_eq!(system.stop_surface(), Ok(3));
+
+        // stop in the back focal plane, the chief ray is parallel to the axis in object space
+        let wavelength = system.primary_wavelength();
+        let parallel = system.trace_paraxial(1., 0., wavelength)[2];
+        let mut telecentric = SequentialOpticalSystem::default();
+        telecentric.add_surface(surface(0., f64::INFINITY, Box::new(Air::default()), None));
+        telecentric.add_surface(surface(60., 6., Box::new(catalog::glass("N-BK7").unwrap()), None));
+        telecentric.add_surface(surface(-60., -parallel.y / parallel.nu, Box::new(Air::default()), None));
+        telecentric.add_surface(surface(0., 10., Box::new(Air::default()), None));
+        telecentric.add_surface(surface(0., 0., Box::new(Air::default()), None));
+        telecentric.parameters.stop_surface = 3;
+        assert_eq!(telecentric.entrance_pupil_position(wavelength), Err(ApertureError::EntrancePupilAtInfinity));
+        assert_eq!(telecentric.paraxial_chief_ray(wavelength), Err(ApertureError::EntrancePupilAtInfinity));
+        // the image of the axial object point does not depend on the pupil
+        assert!(telecentric.paraxial_image_position(wavelength).is_finite());
+    }
+
+    #[test]
+    fn test_image_surface() {
+        let mut system = SequentialOpticalSystem::default();
+        assert_eq!(system.image_surface(), None);
+        assert_eq!(system.checked_surface(None), None);
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default()), None));
+        assert_eq!(system.image_surface(), None);
+
+        let system = rear_stop_singlet(f64::INFINITY);
+        let image = system.surfaces.len() - 1;
+        assert_eq!(system.image_surface(), Some(image));
+        assert_eq!(system.checked_surface(None), Some(image));
+        assert_eq!(system.checked_surface(Some(1)), Some(1));
+        assert_eq!(system.checked_surface(Some(0)), None);
+        assert_eq!(system.checked_surface(Some(image + 1)), None);
+    }
+
+    #[test]
+    fn test_exit_pupil_of_front_stop_system() {
+        let mut system = rear_stop_singlet(f64::INFINITY);
+        system.parameters.stop_surface = 1;
+        let pupils = system.pupils().unwrap();
+        assert_approx_eq!(pupils.entrance_position, 0.);
+        assert_approx_eq!(pupils.entrance_diameter, 10.);
+        assert_approx_eq!(pupils.stop_semi_diameter, 5.);
+        // exit pupil is the image of the first surface through the lens, inside the lens
+        assert!(pupils.exit_position > 0. && pupils.exit_position < 6.);
+    }
+}
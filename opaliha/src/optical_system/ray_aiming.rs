@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::optical_system::fields::FieldPoint;
+use crate::optical_system::parameters::RayAiming;
+use crate::optical_system::pupils::ApertureError;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+const AIMING_ITERATIONS: usize = 30;
+/// Allowed miss on the stop relative to the stop radius.
+const AIMING_TOLERANCE: f64 = 1e-10;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RayAimingError {
+    /// A ray of the iteration did not reach the stop: it missed a surface or was totally
+    /// reflected.
+    RayFailed,
+    /// Iterations ran out, `residual` is the remaining miss distance on the stop.
+    NotConverged { residual: f64 },
+    /// The aperture definition gives no entrance pupil to aim through.
+    Aperture(ApertureError),
+}
+
+
+impl fmt::Display for RayAimingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RayAimingError::RayFailed => write!(f, "ray aiming failed: ray does not reach the stop"),
+            RayAimingError::NotConverged { residual } => {
+                write!(f, "ray aiming did not converge, residual on stop {:e}", residual)
+            }
+            RayAimingError::Aperture(error) => write!(f, "ray aiming failed: {}", error),
+        }
+    }
+}
+
+
+impl Error for RayAimingError {}
+
+
+impl From<ApertureError> for RayAimingError {
+    fn from(error: ApertureError) -> Self {
+        RayAimingError::Aperture(error)
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Ray from the field point through normalized pupil coordinates following the ray aiming
+    /// mode of the system.
+    pub fn aimed_ray(&self, point: FieldPoint, px: f64, py: f64) -> Result<Ray3, RayAimingError> {
+        match self.parameters.ray_aiming {
+            RayAiming::Paraxial => Ok(self.pupil_ray(point, px, py)?),
+            RayAiming::Real { pupil_shift_and_compression } => {
+                self.aim_to_stop(point, px, py, pupil_shift_and_compression)
+            }
+        }
+    }
+
+    /// Newton iteration on the point the ray is launched towards in the paraxial entrance pupil
+    /// plane until the real ray crosses the stop at (px, py) times the paraxial stop radius.
+    pub fn aim_to_stop(
+        &self,
+        point: FieldPoint,
+        px: f64,
+        py: f64,
+        pupil_shift_and_compression: bool,
+    ) -> Result<Ray3, RayAimingError> {
+        let stop_radius = self.pupils()?.stop_semi_diameter;
+        let goal = (px * stop_radius, py * stop_radius);
+        let pupil_radius = self.entrance_pupil_radius(self.primary_wavelength())?;
+        let reference = self.pupil_reference()?;
+        let tolerance = AIMING_TOLERANCE * stop_radius.max(1.);
+        let delta = 1e-6 * pupil_radius.max(1e-3);
+
+        let mut aim = (px * pupil_radius, py * pupil_radius);
+        if pupil_shift_and_compression {
+            aim = self.shift_and_compress(point, goal, pupil_radius).unwrap_or(aim);
+        }
+        let target = |aim: (f64, f64)| Point3 { x: aim.0, y: aim.1, ..reference };
+
+        let mut residual = f64::INFINITY;
+        for _ in 0..AIMING_ITERATIONS {
+            let h = self.stop_hit(point, target(aim)).ok_or(RayAimingError::RayFailed)?;
+            let miss = (h.0 - goal.0, h.1 - goal.1);
+            residual = miss.0.hypot(miss.1);
+            if residual < tolerance {
+                return Ok(self.launch_ray(point, target(aim))?)
+            }
+            let hx = self.stop_hit(point, target((aim.0 + delta, aim.1))).ok_or(RayAimingError::RayFailed)?;
+            let hy = self.stop_hit(point, target((aim.0, aim.1 + delta))).ok_or(RayAimingError::RayFailed)?;
+            let (j11, j21) = ((hx.0 - h.0) / delta, (hx.1 - h.1) / delta);
+            let (j12, j22) = ((hy.0 - h.0) / delta, (hy.1 - h.1) / delta);
+            let determinant = j11 * j22 - j12 * j21;
+            if determinant == 0. { break }
+            aim.0 -= (j22 * miss.0 - j12 * miss.1) / determinant;
+            aim.1 -= (j11 * miss.1 - j21 * miss.0) / determinant;
+        }
+        Err(RayAimingError::NotConverged { residual })
+    }
+
+    /// Position of the real ray on the stop surface, relative to the stop vertex.
+    fn stop_hit(&self, point: FieldPoint, target: Point3) -> Option<(f64, f64)> {
+        let stop = self.stop_surface().ok()?;
+        let ray = self.trace_ray_to(self.launch_ray(point, target).ok()?, stop);
+        if ray.validity != RayValidity::VALID { return None }
+        let vertex = self.surfaces[stop].position();
+        Some((ray.origin.x - vertex.x, ray.origin.y - vertex.y))
+    }
+
+    /// First guess corrected for the real pupil: the shift of the chief ray and the scale of the
+    /// pupil on the stop are measured with three rays and inverted linearly.
+    fn shift_and_compress(&self, point: FieldPoint, goal: (f64, f64), pupil_radius: f64) -> Option<(f64, f64)> {
+        let reference = self.pupil_reference().ok()?;
+        let center = self.stop_hit(point, reference)?;
+        let edge_x = self.stop_hit(point, Point3 { x: pupil_radius, ..reference })?;
+        let edge_y = self.stop_hit(point, Point3 { y: pupil_radius, ..reference })?;
+        let compression = ((edge_x.0 - center.0) / pupil_radius, (edge_y.1 - center.1) / pupil_radius);
+        if compression.0 == 0. || compression.1 == 0. { return None }
+        Some(((goal.0 - center.0) / compression.0, (goal.1 - center.1) / compression.1))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::geometry::vector::Vector3;
+    use crate::materials::catalog;
+    use crate::materials::material::Air;
+    use crate::optical_system::fixtures::surface;
+
+    /// Singlet with the stop behind it, off axis its real entrance pupil is shifted noticeably.
+    fn rear_stop_lens() -> SequentialOpticalSystem {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(surface(40., 8., Box::new(catalog::glass("N-SK16").unwrap())));
+        system.add_surface(surface(-120., 10., Box::new(Air::default())));
+        system.add_surface(surface(0., 30., Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system.parameters.stop_surface = 3;
+        system
+    }
+
+    fn field(degrees: f64) -> FieldPoint {
+        FieldPoint::Direction(Vector3 { x: 0., y: degrees.to_radians().tan(), z: 1. }.clone_normalized())
+    }
+
+    #[test]
+    fn test_real_aiming_hits_stop() {
+        let mut system = rear_stop_lens();
+        let stop_radius = system.pupils().unwrap().stop_semi_diameter;
+        let paraxial = system.aimed_ray(field(20.), 0., 0.).unwrap();
+        let paraxial_miss = system.trace_ray_to(paraxial, 3).origin.y;
+        assert!(paraxial_miss.abs() > 0.1);
+
+        for shift_and_compression in [false, true] {
+            system.parameters.ray_aiming = RayAiming::Real { pupil_shift_and_compression: shift_and_compression };
+            for (px, py) in [(0., 0.), (0., 1.), (0., -1.), (1., 0.)] {
+                let ray = system.aimed_ray(field(20.), px, py).unwrap();
+                let at_stop = system.trace_ray_to(ray, 3);
+                assert_approx_eq!(at_stop.origin.x, px * stop_radius, 1e-8);
+                assert_approx_eq!(at_stop.origin.y, py * stop_radius, 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unreachable_stop_point_is_reported() {
+        let system = rear_stop_lens();
+        let result = system.aim_to_stop(field(20.), 0., 100., false);
+        assert_eq!(result, Err(RayAimingError::RayFailed));
+    }
+}
@@ -0,0 +1,429 @@
+use std::error::Error;
+use std::fmt;
+use ndarray::Array1;
+use num::Float;
+use crate::database::wavelengths::{Wavelength, LINE_D};
+use crate::geometry::point::Point3;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::geometry::vector::Vector3;
+use crate::optical_system::sequential_optical_system::{OpticalSurface, SequentialOpticalSystem};
+use crate::optical_system::tracing::BirefringenceMode;
+
+/// Rays of one wavelength stored column by column. Directions (l, m, n) are unit vectors.
+/// Columns are contiguous and of equal length, `from_rays` and `from_columns` check them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RayBatch {
+    x: Array1<f64>,
+    y: Array1<f64>,
+    z: Array1<f64>,
+    l: Array1<f64>,
+    m: Array1<f64>,
+    n: Array1<f64>,
+    /// Optical path accumulated since launch.
+    optical_path: Array1<f64>,
+    validity: Array1<RayValidity>,
+    wavelength: Wavelength,
+}
+
+/// Rays that can not share a batch.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RayBatchError {
+    /// A batch is traced at a single wavelength.
+    MixedWavelengths,
+    /// Columns of different lengths, the length of the offending column.
+    ColumnLength(usize),
+    /// A column that is not contiguous in memory.
+    NonContiguousColumn,
+}
+
+/// Unit surface normals at the last intersection, one per ray of the batch.
+struct Normals {
+    x: Array1<f64>,
+    y: Array1<f64>,
+    z: Array1<f64>,
+}
+
+
+impl fmt::Display for RayBatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RayBatchError::MixedWavelengths => write!(f, "rays of a batch must share one wavelength"),
+            RayBatchError::ColumnLength(length) => write!(f, "batch column of length {} differs from the others", length),
+            RayBatchError::NonContiguousColumn => write!(f, "batch columns must be contiguous"),
+        }
+    }
+}
+
+
+impl Error for RayBatchError {}
+
+
+impl RayBatch {
+    /// Batch of rays of one wavelength, an empty batch is at the d line.
+    pub fn from_rays(rays: &[Ray3]) -> Result<RayBatch, RayBatchError> {
+        let wavelength = rays.first().map_or(LINE_D, |ray| ray.wavelength);
+        if rays.iter().any(|ray| ray.wavelength != wavelength) {
+            return Err(RayBatchError::MixedWavelengths)
+        }
+        let directions: Vec<Vector3> = rays.iter().map(|ray| ray.direction.clone_normalized()).collect();
+        Ok(RayBatch {
+            x: rays.iter().map(|ray| ray.origin.x).collect(),
+            y: rays.iter().map(|ray| ray.origin.y).collect(),
+            z: rays.iter().map(|ray| ray.origin.z).collect(),
+            l: directions.iter().map(|d| d.x).collect(),
+            m: directions.iter().map(|d| d.y).collect(),
+            n: directions.iter().map(|d| d.z).collect(),
+            optical_path: rays.iter().map(|ray| ray.optical_path).collect(),
+            validity: rays.iter().map(|ray| ray.validity).collect(),
+            wavelength,
+        })
+    }
+
+    /// Batch of the given columns, directions are normalized.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_columns(
+        x: Array1<f64>,
+        y: Array1<f64>,
+        z: Array1<f64>,
+        l: Array1<f64>,
+        m: Array1<f64>,
+        n: Array1<f64>,
+        optical_path: Array1<f64>,
+        validity: Array1<RayValidity>,
+        wavelength: Wavelength,
+    ) -> Result<RayBatch, RayBatchError> {
+        let columns = [&x, &y, &z, &l, &m, &n, &optical_path];
+        let layouts = columns.iter().map(|column| (column.len(), column.is_standard_layout()))
+            .chain([(validity.len(), validity.is_standard_layout())]);
+        for (length, contiguous) in layouts {
+            if length != x.len() { return Err(RayBatchError::ColumnLength(length)) }
+            if !contiguous { return Err(RayBatchError::NonContiguousColumn) }
+        }
+        let norm = (&l * &l + &m * &m + &n * &n).mapv(f64::sqrt);
+        Ok(RayBatch { x, y, z, l: l / &norm, m: m / &norm, n: n / &norm, optical_path, validity, wavelength })
+    }
+
+    pub fn x(&self) -> &Array1<f64> {
+        &self.x
+    }
+
+    pub fn y(&self) -> &Array1<f64> {
+        &self.y
+    }
+
+    pub fn z(&self) -> &Array1<f64> {
+        &self.z
+    }
+
+    pub fn l(&self) -> &Array1<f64> {
+        &self.l
+    }
+
+    pub fn m(&self) -> &Array1<f64> {
+        &self.m
+    }
+
+    pub fn n(&self) -> &Array1<f64> {
+        &self.n
+    }
+
+    pub fn optical_path(&self) -> &Array1<f64> {
+        &self.optical_path
+    }
+
+    pub fn validity(&self) -> &Array1<RayValidity> {
+        &self.validity
+    }
+
+    pub fn wavelength(&self) -> Wavelength {
+        self.wavelength
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.is_empty()
+    }
+
+    pub fn ray(&self, index: usize) -> Ray3 {
+        Ray3 {
+            origin: Point3 { x: self.x[index], y: self.y[index], z: self.z[index] },
+            direction: Vector3 { x: self.l[index], y: self.m[index], z: self.n[index] },
+            validity: self.validity[index],
+            optical_path: self.optical_path[index],
+            wavelength: self.wavelength,
+        }
+    }
+
+    pub fn to_rays(&self) -> Vec<Ray3> {
+        (0..self.len()).map(|index| self.ray(index)).collect()
+    }
+
+    fn set_ray(&mut self, index: usize, ray: Ray3) {
+        self.x[index] = ray.origin.x;
+        self.y[index] = ray.origin.y;
+        self.z[index] = ray.origin.z;
+        self.l[index] = ray.direction.x;
+        self.m[index] = ray.direction.y;
+        self.n[index] = ray.direction.z;
+        self.optical_path[index] = ray.optical_path;
+        self.validity[index] = ray.validity;
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Traces the batch up to surface `last` inclusive, one surface at a time across all rays.
+    /// Spheres and planes are intersected and isotropic media refract in tight loops over the
+    /// columns, other surfaces are intersected ray by ray. Systems with gradient media or
+    /// traced for the extraordinary wave fall back to the scalar trace.
+    pub fn trace_batch(&self, batch: &mut RayBatch, last: usize) {
+        let Some(image) = self.image_surface() else { return };
+        if batch.is_empty() { return }
+        let last = last.min(image);
+        if !self.batch_supported(last) {
+            for index in 0..batch.len() {
+                let ray = self.trace_ray_to(batch.ray(index), last);
+                batch.set_ray(index, ray);
+            }
+            return
+        }
+
+        let wavelength = batch.wavelength;
+        let mut normals = Normals {
+            x: Array1::zeros(batch.len()),
+            y: Array1::zeros(batch.len()),
+            z: Array1::zeros(batch.len()),
+        };
+        let mut index_before = self.surfaces[0].material().refraction_index_at(wavelength);
+        for surface in self.surfaces.iter().take(last + 1).skip(1) {
+            match surface.standard_curvature() {
+                Some(c) => intersect_standard_batch(batch, &mut normals, surface.position(), c, index_before),
+                None => intersect_each(batch, &mut normals, surface.as_ref(), index_before),
+            }
+            let index_after = surface.material().refraction_index_at(wavelength);
+            refract_batch(batch, &normals, index_before, index_after);
+            index_before = index_after;
+        }
+    }
+
+    /// Every medium up to `last` has a single index per wavelength.
+    fn batch_supported(&self, last: usize) -> bool {
+        let extraordinary = matches!(self.birefringence_mode, BirefringenceMode::Extraordinary);
+        self.surfaces.iter().take(last + 1).all(|surface| {
+            let material = surface.material();
+            material.as_gradient().is_none() && !(extraordinary && material.as_uniaxial().is_some())
+        })
+    }
+}
+
+
+fn column(values: &mut Array1<f64>) -> &mut [f64] {
+    values.as_slice_mut().expect("batch columns are contiguous")
+}
+
+
+/// `intersect_standard` over the columns, moving the rays onto the surface and adding the
+/// optical path in the medium of index `index`.
+fn intersect_standard_batch(batch: &mut RayBatch, normals: &mut Normals, vertex: Point3, c: f64, index: f64) {
+    let count = batch.len();
+    let (x, y, z) = (&mut column(&mut batch.x)[..count], &mut column(&mut batch.y)[..count], &mut column(&mut batch.z)[..count]);
+    let (l, m, n) = (&column(&mut batch.l)[..count], &column(&mut batch.m)[..count], &column(&mut batch.n)[..count]);
+    let optical_path = &mut column(&mut batch.optical_path)[..count];
+    let validity = &mut batch.validity.as_slice_mut().expect("batch columns are contiguous")[..count];
+    let (nx, ny, nz) = (&mut column(&mut normals.x)[..count], &mut column(&mut normals.y)[..count], &mut column(&mut normals.z)[..count]);
+
+    for i in 0..count {
+        if validity[i] != RayValidity::VALID { continue }
+        let (dx, dy, dz) = (l[i], m[i], n[i]);
+        if dz == 0. {
+            validity[i] = RayValidity::INVALID;
+            continue
+        }
+        let (ox, oy, oz) = (x[i] - vertex.x, y[i] - vertex.y, z[i] - vertex.z);
+        let to_plane = -oz / dz;
+        let (px, py, pz) = (ox + dx * to_plane, oy + dy * to_plane, oz + dz * to_plane);
+        let f = c * (px * px + py * py);
+        let g = dz - c * (px * dx + py * dy);
+        let discriminant = g * g - c * f;
+        let denominator = g + Float::sqrt(discriminant.max(0.));
+        if discriminant < 0. || denominator == 0. {
+            validity[i] = RayValidity::INVALID;
+            continue
+        }
+        let to_surface = f / denominator;
+        let (hx, hy, hz) = (px + dx * to_surface, py + dy * to_surface, pz + dz * to_surface);
+        optical_path[i] += index * (dx * (hx - ox) + dy * (hy - oy) + dz * (hz - oz));
+        x[i] = vertex.x + hx;
+        y[i] = vertex.y + hy;
+        z[i] = vertex.z + hz;
+        let (ux, uy, uz) = (-c * hx, -c * hy, 1. - c * hz);
+        let norm = Float::sqrt(ux * ux + uy * uy + uz * uz);
+        nx[i] = ux / norm;
+        ny[i] = uy / norm;
+        nz[i] = uz / norm;
+    }
+}
+
+
+/// Intersection through `OpticalSurface::intersect` for surfaces without a batch kernel.
+fn intersect_each(batch: &mut RayBatch, normals: &mut Normals, surface: &dyn OpticalSurface, index: f64) {
+    for i in 0..batch.len() {
+        if batch.validity[i] != RayValidity::VALID { continue }
+        let ray = batch.ray(i);
+        match surface.intersect(&ray) {
+            Some(hit) => {
+                batch.optical_path[i] += index * ray.direction.dot(hit.point - ray.origin);
+                batch.x[i] = hit.point.x;
+                batch.y[i] = hit.point.y;
+                batch.z[i] = hit.point.z;
+                normals.x[i] = hit.normal.x;
+                normals.y[i] = hit.normal.y;
+                normals.z[i] = hit.normal.z;
+            }
+            None => batch.validity[i] = RayValidity::INVALID,
+        }
+    }
+}
+
+
+/// `refract_isotropic` over the columns, from index `before` into index `after`. Totally
+/// reflected rays keep their direction and are marked `TIR`.
+fn refract_batch(batch: &mut RayBatch, normals: &Normals, before: f64, after: f64) {
+    let count = batch.len();
+    let (l, m, n) = (&mut column(&mut batch.l)[..count], &mut column(&mut batch.m)[..count], &mut column(&mut batch.n)[..count]);
+    let validity = &mut batch.validity.as_slice_mut().expect("batch columns are contiguous")[..count];
+    let (nx, ny, nz) = (&normals.x.as_slice().expect("normals are contiguous")[..count],
+                        &normals.y.as_slice().expect("normals are contiguous")[..count],
+                        &normals.z.as_slice().expect("normals are contiguous")[..count]);
+
+    for i in 0..count {
+        if validity[i] != RayValidity::VALID { continue }
+        let (kx, ky, kz) = (before * l[i], before * m[i], before * n[i]);
+        let mut k_normal = kx * nx[i] + ky * ny[i] + kz * nz[i];
+        // normal oriented along propagation
+        let sign = if k_normal < 0. { -1. } else { 1. };
+        k_normal *= sign;
+        let (ux, uy, uz) = (sign * nx[i], sign * ny[i], sign * nz[i]);
+        let (tx, ty, tz) = (kx - ux * k_normal, ky - uy * k_normal, kz - uz * k_normal);
+        let normal_component = after * after - (tx * tx + ty * ty + tz * tz);
+        if normal_component < 0. {
+            validity[i] = RayValidity::TIR;
+            continue
+        }
+        let root = Float::sqrt(normal_component);
+        let (wx, wy, wz) = (tx + ux * root, ty + uy * root, tz + uz * root);
+        let norm = Float::sqrt(wx * wx + wy * wy + wz * wz);
+        l[i] = wx / norm;
+        m[i] = wy / norm;
+        n[i] = wz / norm;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::catalog;
+    use crate::materials::material::Air;
+    use crate::optical_system::config::load_sequential_system;
+    use crate::optical_system::parameters::{FieldData, FieldRaw, FieldType};
+    use crate::optical_system::pupil_sampling::PupilSampling;
+    use crate::optical_system::ray_generation::RaySet;
+    use crate::optical_system::surfaces::EvenAsphereSurface;
+    use crate::optical_system::sequential_optical_system::{OpticalSurfaceType, StandardSurface};
+
+    fn assert_same(batch: &RayBatch, scalar: &[Ray3]) {
+        for (index, expected) in scalar.iter().enumerate() {
+            let ray = batch.ray(index);
+            assert_eq!(ray.validity, expected.validity);
+            if expected.validity != RayValidity::VALID { continue }
+            assert_approx_eq!(ray.origin.x, expected.origin.x, 1e-9);
+            assert_approx_eq!(ray.origin.y, expected.origin.y, 1e-9);
+            assert_approx_eq!(ray.origin.z, expected.origin.z, 1e-9);
+            assert_approx_eq!(ray.direction.y, expected.direction.y, 1e-12);
+            assert_approx_eq!(ray.direction.z, expected.direction.z, 1e-12);
+            assert_approx_eq!(ray.optical_path, expected.optical_path, 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_batch_matches_scalar() {
+        let mut system = load_sequential_system(concat!(env!("CARGO_MANIFEST_DIR"), "/configs/apochromat3.yaml")).unwrap();
+        let field = FieldRaw::new(0., 1.);
+        system.parameters.field_data = FieldData::new(FieldType::AngleDeg, vec![field]);
+        let wavelength = system.parameters.wavelengths.wavelengths()[0];
+        let beam = system.generate_beam(&field, wavelength, &RaySet::FullPupil(PupilSampling::Square { size: 21 })).unwrap();
+        let rays: Vec<Ray3> = beam.rays.iter().map(|generated| generated.ray).collect();
+        let last = system.image_surface().unwrap();
+
+        let mut batch = RayBatch::from_rays(&rays).unwrap();
+        assert_eq!(batch.len(), rays.len());
+        assert_eq!(batch.wavelength(), wavelength);
+        system.trace_batch(&mut batch, last);
+        let scalar: Vec<Ray3> = rays.iter().map(|&ray| system.trace_ray_to(ray, last)).collect();
+        assert_same(&batch, &scalar);
+        // every ray leaves the last lens into air and reaches the image plane
+        assert!(batch.validity().iter().all(|&validity| validity == RayValidity::VALID));
+        assert_approx_eq!(batch.z()[0], system.surfaces[last].position().z, 1e-9);
+        assert_eq!(batch.to_rays().len(), rays.len());
+        assert_eq!(batch.y()[0], batch.ray(0).origin.y);
+
+        let mut mixed = rays.clone();
+        mixed[1] = mixed[1].with_wavelength(system.primary_wavelength());
+        assert_eq!(RayBatch::from_rays(&mixed), Err(RayBatchError::MixedWavelengths));
+        assert!(RayBatch::from_rays(&[]).unwrap().is_empty());
+
+        let columns = |batch: &RayBatch| (
+            batch.x().clone(), batch.y().clone(), batch.z().clone(),
+            batch.l().clone(), batch.m().clone(), batch.n().clone(),
+            batch.optical_path().clone(), batch.validity().clone(),
+        );
+        let (x, y, z, l, m, n, path, validity) = columns(&batch);
+        let rebuilt = RayBatch::from_columns(x, y, z, l * 2., m * 2., n * 2., path, validity, wavelength).unwrap();
+        assert_same(&rebuilt, &scalar);
+        let (x, y, z, l, m, n, path, validity) = columns(&batch);
+        let short = y.slice(ndarray::s![1..]).to_owned();
+        assert_eq!(
+            RayBatch::from_columns(x, short, z, l, m, n, path, validity, wavelength),
+            Err(RayBatchError::ColumnLength(rays.len() - 1)),
+        );
+        let (x, y, mut z, l, m, n, path, validity) = columns(&batch);
+        z.invert_axis(ndarray::Axis(0));
+        assert_eq!(
+            RayBatch::from_columns(x, y, z, l, m, n, path, validity, wavelength),
+            Err(RayBatchError::NonContiguousColumn),
+        );
+    }
+
+    #[test]
+    fn test_generic_surface_and_failed_rays() {
+        let mut system = SequentialOpticalSystem::default();
+        let standard = |radius: f64, thickness: f64| Box::new(StandardSurface {
+            name: "".to_string(),
+            comment: "".to_string(),
+            surface_type: OpticalSurfaceType::Standard,
+            radius,
+            thickness,
+            material: Box::new(Air::default()),
+            position: Point3::origin(),
+            semi_diameter: None,
+        });
+        system.add_surface(standard(0., f64::INFINITY));
+        system.add_surface(Box::new(EvenAsphereSurface::new(30., -1., vec![1e-6], 5., Box::new(catalog::glass("N-BK7").unwrap()))));
+        system.add_surface(standard(-40., 50.));
+        system.add_surface(standard(0., 0.));
+
+        let ray = |y: f64, angle: f64| Ray3::new(Point3 { x: 0., y, z: -5. }, Vector3 { x: 0., y: angle.to_radians().sin(), z: angle.to_radians().cos() });
+        // the last ray misses the rear sphere, the one before is reflected totally inside the lens
+        let rays = vec![ray(0., 0.), ray(4., 0.), ray(-6., 3.), ray(0., 89.), ray(12., 0.), ray(0., 70.), ray(35., 0.)];
+        let mut batch = RayBatch::from_rays(&rays).unwrap();
+        system.trace_batch(&mut batch, 3);
+        let scalar: Vec<Ray3> = rays.iter().map(|&ray| system.trace_ray_to(ray, 3)).collect();
+        assert!(scalar.iter().any(|ray| ray.validity != RayValidity::VALID));
+        assert_same(&batch, &scalar);
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::optical_system::parameters::FieldRaw;
+use crate::optical_system::pupil_sampling::PupilSampling;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Pattern of normalized pupil coordinates a beam is made of.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RaySet {
+    /// Through the center of the pupil.
+    Chief,
+    /// Through the top edge of the pupil, (0, 1).
+    Marginal,
+    /// `rays` evenly spaced along the y pupil axis from -1 to 1.
+    MeridionalFan { rays: usize },
+    /// `rays` evenly spaced along the x pupil axis from -1 to 1.
+    SagittalFan { rays: usize },
+    /// The whole pupil.
+    FullPupil(PupilSampling),
+}
+
+/// Ray of a beam with the unvignetted pupil coordinates it was generated for.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BeamRay {
+    pub px: f64,
+    pub py: f64,
+    pub ray: Ray3,
+}
+
+/// Rays of one field and wavelength. Rays that can not be aimed are left out, traced rays keep
+/// their validity so vignetted ones stay visible.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Beam {
+    pub field: FieldRaw,
+    pub wavelength: Wavelength,
+    pub rays: Vec<BeamRay>,
+}
+
+
+impl RaySet {
+    /// Normalized pupil coordinates (px, py) of the set.
+    pub fn pupil_coordinates(&self) -> Vec<(f64, f64)> {
+        let fan = |rays: usize| -> Vec<f64> {
+            if rays < 2 { return vec![0.] }
+            (0..rays).map(|k| -1. + 2. * k as f64 / (rays - 1) as f64).collect()
+        };
+        match *self {
+            RaySet::Chief => vec![(0., 0.)],
+            RaySet::Marginal => vec![(0., 1.)],
+            RaySet::MeridionalFan { rays } => fan(rays).into_iter().map(|py| (0., py)).collect(),
+            RaySet::SagittalFan { rays } => fan(rays).into_iter().map(|px| (px, 0.)).collect(),
+            RaySet::FullPupil(sampling) => sampling.points(),
+        }
+    }
+}
+
+
+impl fmt::Display for RaySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaySet::Chief => write!(f, "chief ray"),
+            RaySet::Marginal => write!(f, "marginal ray"),
+            RaySet::MeridionalFan { rays } => write!(f, "meridional fan of {} rays", rays),
+            RaySet::SagittalFan { rays } => write!(f, "sagittal fan of {} rays", rays),
+            RaySet::FullPupil(sampling) => write!(f, "full pupil, {:?}", sampling),
+        }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Object space rays of the set for one field and wavelength, aimed the way the system
+    /// aims rays and squeezed by the vignetting factors of the field. `None` when the field
+    /// does not fit the object.
+    pub fn generate_beam(&self, field: &FieldRaw, wavelength: Wavelength, set: &RaySet) -> Option<Beam> {
+        let point = self.field_point(field)?;
+        let rays = set.pupil_coordinates().into_iter().filter_map(|(px, py)| {
+            let (vx, vy) = field.vignetted_pupil(px, py);
+            let ray = self.aimed_ray(point, vx, vy).ok()?.with_wavelength(wavelength);
+            Some(BeamRay { px, py, ray })
+        }).collect();
+        Some(Beam { field: *field, wavelength, rays })
+    }
+
+    /// Beams of every field and wavelength of the system, fields outer.
+    pub fn generate_beams(&self, set: &RaySet) -> Vec<Beam> {
+        let wavelengths = self.parameters.wavelengths.wavelengths();
+        self.parameters.field_data.rows.iter()
+            .flat_map(|field| wavelengths.iter().filter_map(|&wavelength| self.generate_beam(field, wavelength, set)))
+            .collect()
+    }
+
+    /// Generates the beams of every field and wavelength and traces them to `surface`, the
+    /// image surface when `None`. `None` when `surface` is not a surface after the object.
+    pub fn trace_beams(&self, set: &RaySet, surface: Option<usize>) -> Option<Vec<Beam>> {
+        let last = self.checked_surface(surface)?;
+        let mut beams = self.generate_beams(set);
+        for beam in beams.iter_mut() {
+            beam.rays.iter_mut().for_each(|generated| generated.ray = self.trace_ray_to(generated.ray, last));
+        }
+        Some(beams)
+    }
+}
+
+
+impl Beam {
+    /// Rays that are still valid.
+    pub fn valid_rays(&self) -> impl Iterator<Item = &BeamRay> {
+        self.rays.iter().filter(|generated| generated.ray.validity == RayValidity::VALID)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::optical_system::fixtures;
+
+    #[test]
+    fn test_ray_sets() {
+        assert_eq!(RaySet::Chief.pupil_coordinates(), vec![(0., 0.)]);
+        assert_eq!(RaySet::Marginal.pupil_coordinates(), vec![(0., 1.)]);
+        assert_eq!(RaySet::MeridionalFan { rays: 5 }.pupil_coordinates()[1], (0., -0.5));
+        assert_eq!(RaySet::SagittalFan { rays: 5 }.pupil_coordinates()[4], (1., 0.));
+        assert_eq!(RaySet::FullPupil(PupilSampling::Hexapolar { rings: 2 }).pupil_coordinates().len(), 19);
+    }
+
+    #[test]
+    fn test_fields_and_wavelengths_in_one_call() {
+        let system = fixtures::visible(fixtures::singlet(57.), 10., 5.);
+        let beams = system.trace_beams(&RaySet::MeridionalFan { rays: 7 }, None).unwrap();
+        assert_eq!(beams.len(), 2 * 3);
+        assert_eq!(beams[4].field, FieldRaw::new(0., 5.));
+        assert_eq!(beams[4].wavelength, system.parameters.wavelengths.wavelengths()[1]);
+        for beam in beams.iter() {
+            assert_eq!(beam.valid_rays().count(), 7);
+            // a meridional fan stays in the y-z plane
+            assert!(beam.rays.iter().all(|generated| generated.ray.origin.x.abs() < 1e-12));
+        }
+        let image_z = system.surfaces[3].position().z;
+        assert_approx_eq!(beams[0].rays[0].ray.origin.z, image_z, 1e-9);
+
+        // the middle ray of the fan is the chief ray
+        let chief = system.trace_beams(&RaySet::Chief, None).unwrap();
+        let point = system.field_point(&FieldRaw::new(0., 5.)).unwrap();
+        let height = system.real_image_height(point).unwrap();
+        assert_approx_eq!(chief[4].rays[0].ray.origin.y, beams[4].rays[3].ray.origin.y, 1e-12);
+        assert_approx_eq!(chief[4].rays[0].ray.origin.y, height.1, 1e-9);
+
+        // generated rays start in object space, on the entrance pupil edge
+        let marginal = system.generate_beams(&RaySet::Marginal);
+        assert_approx_eq!(marginal[0].rays[0].ray.origin.y, 5., 1e-9);
+    }
+}
@@ -0,0 +1,175 @@
+use json::JsonValue;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::geometry::vector::Vector3;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Column names of the CSV export, one row per ray and surface.
+pub const CSV_HEADER: &str = "ray,surface,x,y,z,in_l,in_m,in_n,out_l,out_m,out_n,normal_l,normal_m,normal_n,aoi_deg,optical_path,validity";
+
+/// State of a ray on one surface it reached.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SurfaceRecord {
+    pub surface: usize,
+    pub point: Point3,
+    pub incoming_direction: Vector3,
+    /// Direction after refraction, the incoming one after total internal reflection.
+    pub outgoing_direction: Vector3,
+    pub normal: Vector3,
+    /// Angle between the incoming direction and the normal, degrees.
+    pub angle_of_incidence: f64,
+    /// Optical path from the launch point to the surface.
+    pub optical_path: f64,
+    pub validity: RayValidity,
+}
+
+/// Launched ray and its record on every surface it reached, in order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RayPath {
+    pub start: Ray3,
+    pub records: Vec<SurfaceRecord>,
+    /// Validity at the end of the trace, `INVALID` when a surface was missed.
+    pub validity: RayValidity,
+}
+
+
+impl SequentialOpticalSystem {
+    /// Traces the ray up to surface `last` inclusive recording every surface on the way.
+    pub fn trace_path(&self, ray: Ray3, last: usize) -> RayPath {
+        let mut records = Vec::new();
+        let end = self.trace_ray_visiting(ray, last, |record| records.push(*record));
+        RayPath { start: ray, records, validity: end.validity }
+    }
+}
+
+
+impl RayPath {
+    pub fn record(&self, surface: usize) -> Option<&SurfaceRecord> {
+        self.records.iter().find(|record| record.surface == surface)
+    }
+
+    /// Surface where the trace failed: the one missed or the one reflecting totally.
+    pub fn failed_surface(&self) -> Option<usize> {
+        match self.validity {
+            RayValidity::VALID => None,
+            RayValidity::TIR => self.records.last().map(|record| record.surface),
+            RayValidity::INVALID => Some(self.records.last().map_or(1, |record| record.surface + 1)),
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let vector = |v: Vector3| json::array![v.x, v.y, v.z];
+        let records: Vec<JsonValue> = self.records.iter().map(|record| json::object! {
+            surface: record.surface,
+            point: json::array![record.point.x, record.point.y, record.point.z],
+            incoming_direction: vector(record.incoming_direction),
+            outgoing_direction: vector(record.outgoing_direction),
+            normal: vector(record.normal),
+            angle_of_incidence: record.angle_of_incidence,
+            optical_path: record.optical_path,
+            validity: record.validity.to_string(),
+        }).collect();
+        json::object! {
+            wavelength: self.start.wavelength.um(),
+            origin: json::array![self.start.origin.x, self.start.origin.y, self.start.origin.z],
+            direction: vector(self.start.direction),
+            validity: self.validity.to_string(),
+            records: records,
+        }
+    }
+}
+
+
+/// Paths as CSV with `CSV_HEADER`, rays numbered in the order given.
+pub fn paths_to_csv(paths: &[RayPath]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for (index, path) in paths.iter().enumerate() {
+        for r in path.records.iter() {
+            let (i, o, n) = (r.incoming_direction, r.outgoing_direction, r.normal);
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                index, r.surface, r.point.x, r.point.y, r.point.z, i.x, i.y, i.z, o.x, o.y, o.z,
+                n.x, n.y, n.z, r.angle_of_incidence, r.optical_path, r.validity,
+            ));
+        }
+    }
+    csv
+}
+
+
+/// Paths as a pretty-printed JSON array.
+pub fn paths_to_json(paths: &[RayPath]) -> String {
+    JsonValue::Array(paths.iter().map(RayPath::to_json).collect()).pretty(2)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::gradient_index::RadialGradient;
+    use crate::optical_system::fixtures;
+
+    #[test]
+    fn test_path_matches_trace() {
+        let system = fixtures::singlet(57.);
+        let ray = Ray3::new(Point3 { x: 0., y: 3., z: -5. }, Vector3 { x: 0., y: 0., z: 1. });
+        let path = system.trace_path(ray, 3);
+        let traced = system.trace_ray_to(ray, 3);
+        assert_eq!(path.records.len(), 3);
+        assert_eq!(path.validity, RayValidity::VALID);
+        assert_eq!(path.failed_surface(), None);
+        let image = path.record(3).unwrap();
+        assert_eq!(image.point, traced.origin);
+        assert_eq!(image.outgoing_direction, traced.direction);
+        assert_approx_eq!(image.optical_path, traced.optical_path, 1e-12);
+        // chained directions and the angle of incidence of the first surface, sin θ = y / R
+        let first = *path.record(1).unwrap();
+        assert_eq!(path.record(2).unwrap().incoming_direction, first.outgoing_direction);
+        assert_approx_eq!(first.angle_of_incidence, (3f64 / 60.).asin().to_degrees(), 1e-9);
+        assert!(path.records.windows(2).all(|pair| pair[1].optical_path > pair[0].optical_path));
+
+        let csv = paths_to_csv(&[path.clone(), path.clone()]);
+        assert_eq!(csv.lines().count(), 1 + 2 * 3);
+        assert!(csv.lines().nth(6).unwrap().starts_with("1,3,"));
+        let parsed = json::parse(&paths_to_json(&[path])).unwrap();
+        assert_eq!(parsed[0]["records"].len(), 3);
+        assert_eq!(parsed[0]["records"][2]["validity"], "valid");
+        assert_approx_eq!(parsed[0]["records"][0]["point"][1].as_f64().unwrap(), first.point.y, 1e-12);
+    }
+
+    #[test]
+    fn test_path_through_gradient_rod() {
+        // a quarter pitch rod bends a ray entering parallel at height y to the slope -y √A on
+        // the exit face, refraction into air multiplies it by n0
+        let (sqrt_a, y) = (0.3, 0.02);
+        let length = std::f64::consts::PI / (2. * sqrt_a);
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(fixtures::surface(0., 1., fixtures::air()));
+        system.add_surface(fixtures::surface(0., length, Box::new(RadialGradient::parabolic("rod", 1.6, sqrt_a))));
+        system.add_surface(fixtures::surface(0., 0., fixtures::air()));
+        system.gradient_step = 0.01;
+
+        let path = system.trace_path(Ray3::new(Point3 { x: 0., y, z: -1. }, Vector3::unit_z()), 2);
+        assert_eq!(path.validity, RayValidity::VALID);
+        let exit = *path.record(2).unwrap();
+        assert_approx_eq!(path.record(1).unwrap().outgoing_direction.y, 0., 1e-12);
+        assert_approx_eq!(exit.incoming_direction.y, -y * sqrt_a, 1e-5);
+        assert_approx_eq!(exit.outgoing_direction.y, -1.6 * y * sqrt_a, 1e-5);
+        assert_approx_eq!(exit.angle_of_incidence, exit.incoming_direction.y.abs().asin().to_degrees(), 1e-9);
+        let csv = paths_to_csv(&[path]);
+        let in_m: f64 = csv.lines().nth(2).unwrap().split(',').nth(6).unwrap().parse().unwrap();
+        assert_eq!(in_m, exit.incoming_direction.y);
+    }
+
+    #[test]
+    fn test_missed_surface() {
+        let system = fixtures::singlet(57.);
+        let ray = Ray3::new(Point3 { x: 0., y: 70., z: -5. }, Vector3 { x: 0., y: 0., z: 1. });
+        let path = system.trace_path(ray, 3);
+        assert_eq!(path.validity, RayValidity::INVALID);
+        assert!(path.records.is_empty());
+        assert_eq!(path.failed_surface(), Some(1));
+    }
+}
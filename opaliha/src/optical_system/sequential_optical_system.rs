@@ -1,15 +1,23 @@
 use std::fmt::Formatter;
 use std::fmt;
+use num::Float;
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::intersection::Intersection;
 use crate::geometry::point::Point3;
+use crate::geometry::vector::Vector3;
 use crate::materials;
-use crate::geometry::ray::Ray3;
+use crate::geometry::ray::{Ray3, RayValidity};
 use crate::geometry::sphere;
+use crate::optical_system::parameters::SequentialParameters;
+use crate::optical_system::ray_path::SurfaceRecord;
+use crate::optical_system::tracing::{propagate_in_gradient, refract, refract_isotropic, BirefringenceMode, WaveState};
 
+#[derive(Default)]
 pub enum OpticalSurfaceType {
-    // Biconic,
+    Biconic,
     // BiconicZernike,
     // ChebyshevPolynomial,
-    // EvenAsphere,
+    EvenAsphere,
     // ExtendedAsphere,
     // ExtendedOddAsphere,
     // ExtendedPolynomial,
@@ -22,9 +30,10 @@ pub enum OpticalSurfaceType {
     // Polynomial,
     // QTypeAsphere,
     // QTypeFreeform,
+    #[default]
     Standard,
     // Superconic,
-    // Tilted,
+    Tilted,
     // Toroidal,
     // ZernikeFringeSag,
     // ZernikeStandardSag,
@@ -35,24 +44,49 @@ impl fmt::Display for OpticalSurfaceType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             OpticalSurfaceType::Standard => write!(f, "Standard"),
+            OpticalSurfaceType::Biconic => write!(f, "Biconic"),
+            OpticalSurfaceType::EvenAsphere => write!(f, "EvenAsphere"),
+            OpticalSurfaceType::Tilted => write!(f, "Tilted"),
         }
     }
 }
 
-impl Default for OpticalSurfaceType {
-    fn default() -> Self {
-        OpticalSurfaceType::Standard
-    }
-}
 
 pub trait OpticalSurface : fmt::Debug {
     fn name(&self) -> &str;
     fn comment(&self) -> &str;
     fn surface_type(&self) -> &OpticalSurfaceType;
     fn radius(&self) -> Option<f64>;
+    /// Paraxial curvatures of the XZ and YZ sections.
+    fn section_curvatures(&self) -> (f64, f64) {
+        let c = self.radius().map_or(0., |r| 1. / r);
+        (c, c)
+    }
+    /// Coefficient of r⁴ in the departure of the sag from the vertex sphere, the aspheric part
+    /// seen by third-order aberrations.
+    fn fourth_order_deformation(&self) -> f64 { 0. }
+    /// Coefficient of r⁶ in the departure of the sag from the vertex sphere, seen by
+    /// fifth-order aberrations.
+    fn sixth_order_deformation(&self) -> f64 { 0. }
     fn thickness(&self) -> Option<f64>;
     fn position(&self) -> Point3;
-    fn trace(&self, ray: Ray3) -> Option<Ray3>;
+    /// Clear semi-diameter, `None` when the surface is not limited.
+    fn semi_diameter(&self) -> Option<f64>;
+    fn set_position(&mut self, position: Point3);
+    /// Changes the distance to the next surface, `update_positions` moves the following ones.
+    fn set_thickness(&mut self, thickness: f64);
+    /// Medium filling the space after the surface.
+    fn material(&self) -> &dyn materials::material::Material;
+    fn intersect(&self, ray: &Ray3) -> Option<Intersection>;
+    /// Moves the ray onto the surface keeping its direction, `None` when it misses. Refraction
+    /// needs the medium in front of the surface and is done by the system.
+    fn trace(&self, ray: Ray3) -> Option<Ray3> {
+        let hit = self.intersect(&ray)?;
+        Some(Ray3 { origin: hit.point, ..ray })
+    }
+    /// Curvature of a sphere or plane intersected by `intersect_standard`, lets batch tracing
+    /// handle the surface without going through `intersect` ray by ray.
+    fn standard_curvature(&self) -> Option<f64> { None }
 }
 
 pub struct StandardSurface {
@@ -63,13 +97,18 @@ pub struct StandardSurface {
     pub thickness: f64,
     pub material: Box<dyn materials::material::Material>,
     pub position: Point3,
+    pub semi_diameter: Option<f64>,
 }
 
 
 impl StandardSurface {
     pub fn as_sphere(&self) -> Option<sphere::Sphere> {
-        if self.radius <= 0.0 { return None }
-        Some(sphere::Sphere{origin: self.position, radius: self.radius})
+        if self.radius == 0.0 { return None }
+        Some(sphere::Sphere{origin: self.position + Vector3{x: 0., y: 0., z: self.radius}, radius: self.radius.abs()})
+    }
+
+    pub fn curvature(&self) -> f64 {
+        if self.radius == 0.0 { 0.0 } else { 1.0 / self.radius }
     }
 }
 
@@ -93,114 +132,289 @@ impl OpticalSurface for StandardSurface {
     fn surface_type(&self) -> &OpticalSurfaceType {
         &self.surface_type
     }
-    fn radius(&self) -> Option<f64> {Some(self.radius)}
+    fn radius(&self) -> Option<f64> { (self.radius != 0.0).then_some(self.radius) }
     fn thickness(&self) -> Option<f64> { Some(self.thickness)}
     fn position(&self) -> Point3 { self.position }
-    fn trace(&self, ray: Ray3) -> Option<Ray3> {trace(ray, self)}
+    fn semi_diameter(&self) -> Option<f64> { self.semi_diameter }
+    fn set_position(&mut self, position: Point3) { self.position = position }
+    fn set_thickness(&mut self, thickness: f64) { self.thickness = thickness }
+    fn material(&self) -> &dyn materials::material::Material { self.material.as_ref() }
+    fn intersect(&self, ray: &Ray3) -> Option<Intersection> {
+        intersect_standard(ray, self.position, self.curvature())
+    }
+    fn standard_curvature(&self) -> Option<f64> { Some(self.curvature()) }
 }
 
 
-#[derive(Default, Debug)]
+/// Sequential system in the Zemax sense: the first surface is the object surface, rays are
+/// launched in its medium and refracted by every following surface in order.
+/// Vertex of the surface after the object is the global origin.
+#[derive(Debug)]
 pub struct SequentialOpticalSystem {
-    pub surfaces: Vec<Box<dyn OpticalSurface + 'static>>
+    pub surfaces: Vec<Box<dyn OpticalSurface + 'static>>,
+    pub parameters: SequentialParameters,
+    pub birefringence_mode: BirefringenceMode,
+    /// Integration step inside gradient index media.
+    pub gradient_step: f64,
 }
 
 
-impl Trace for SequentialOpticalSystem {
-    fn trace_ray(&self, mut ray: Ray3) -> Ray3 {
-        for surface in self.surfaces.iter() {
-            ray = surface.trace(ray).unwrap();
+impl Default for SequentialOpticalSystem {
+    fn default() -> Self {
+        SequentialOpticalSystem {
+            surfaces: Vec::new(),
+            parameters: SequentialParameters::default(),
+            birefringence_mode: BirefringenceMode::default(),
+            gradient_step: 0.05,
         }
-        ray
     }
 }
 
 
-impl SequentialOpticalSystem {
-    pub fn add_surface(&mut self, surface: Box<dyn OpticalSurface>) {
-        self.surfaces.push(surface)
+impl Trace for SequentialOpticalSystem {
+    fn trace_ray(&self, ray: Ray3) -> Ray3 {
+        self.trace_ray_to(ray, self.surfaces.len().saturating_sub(1))
     }
 }
 
 
-pub fn trace(ray: Ray3, surface: &StandardSurface) -> Option<Ray3> {
-    match surface.surface_type() {
-        OpticalSurfaceType::Standard => {
-            match surface.radius() {
-                None => trace_plane(ray, surface),
-                Some(_) => trace_sphere(ray, surface)
-            }
-        }
-    };
+impl SequentialOpticalSystem {
+    /// Traces the ray up to surface `last` inclusive, the ray is left on that surface.
+    pub fn trace_ray_to(&self, ray: Ray3, last: usize) -> Ray3 {
+        self.trace_ray_visiting(ray, last, |_| {})
+    }
 
-    Some(ray)
-}
+    /// `trace_ray_to` handing every surface the ray reaches to `visit`, after the refraction.
+    /// A missed surface ends the trace without a record.
+    pub(crate) fn trace_ray_visiting(&self, mut ray: Ray3, last: usize, mut visit: impl FnMut(&SurfaceRecord)) -> Ray3 {
+        let Some(object) = self.surfaces.first() else { return ray };
+        let wavelength = ray.wavelength;
+        let object_index = object.material().refraction_index_at(wavelength);
+        let mut wave = WaveState::isotropic(ray.direction, object_index);
 
+        for (index, surface) in self.surfaces.iter().enumerate().take(last + 1).skip(1) {
+            if ray.validity != RayValidity::VALID { break }
+            let previous = &self.surfaces[index - 1];
+            let hit = match previous.material().as_gradient() {
+                Some(gradient) => propagate_in_gradient(
+                    &ray, gradient, previous.position(), surface.as_ref(), self.gradient_step,
+                ).map(|propagation| {
+                    wave = propagation.wave;
+                    ray.direction = propagation.wave.ray_direction;
+                    ray.optical_path += propagation.optical_path;
+                    propagation.intersection
+                }),
+                None => surface.intersect(&ray).inspect(|hit| {
+                    ray.optical_path += wave.wave_vector.dot(hit.point - ray.origin);
+                }),
+            };
+            let Some(hit) = hit else {
+                ray.validity = RayValidity::INVALID;
+                break
+            };
+            ray.origin = hit.point;
+            let incoming = ray.direction;
+            let material = surface.material();
+            let refracted = match material.as_gradient() {
+                Some(gradient) => {
+                    let local = hit.point + (Point3::origin() - surface.position());
+                    refract_isotropic(wave, hit.normal, gradient.index_at(local, wavelength))
+                }
+                None => refract(wave, hit.normal, material, wavelength, self.birefringence_mode),
+            };
+            match refracted {
+                Some(refracted) => {
+                    wave = refracted;
+                    ray.direction = refracted.ray_direction;
+                }
+                None => ray.validity = RayValidity::TIR,
+            }
+            let cosine = incoming.abs_dot(hit.normal) / (incoming.norm() * hit.normal.norm());
+            visit(&SurfaceRecord {
+                surface: index,
+                point: hit.point,
+                incoming_direction: incoming,
+                outgoing_direction: ray.direction,
+                normal: hit.normal,
+                angle_of_incidence: cosine.min(1.).acos().to_degrees(),
+                optical_path: ray.optical_path,
+                validity: ray.validity,
+            });
+        }
+        ray
+    }
 
-pub fn trace_sphere(ray: Ray3, surface: &StandardSurface) -> Option<Ray3> {
-    // let intersection = ray.intersect_with_sphere();
-    Some(ray)
-}
+    /// Wavelength of first-order properties, pupils and field definitions.
+    pub fn primary_wavelength(&self) -> Wavelength {
+        self.parameters.wavelengths.primary_wavelength()
+    }
 
+    pub fn add_surface(&mut self, surface: Box<dyn OpticalSurface>) {
+        self.surfaces.push(surface);
+        self.update_positions();
+    }
 
-pub fn trace_plane(ray: Ray3, surface: &StandardSurface) -> Option<Ray3> {
-    // ray go from origin to Z-plane by default
-    // if refraction angle is OK -> trace
-    // if refraction is broken - return unit vector to Z with origin from brokent point
-    trace_plane_ray(ray, surface.position().z)
+    /// Places surface vertices along z from thicknesses, the object surface lies
+    /// at the negative object distance (infinitely far for an infinite object).
+    pub fn update_positions(&mut self) {
+        let mut z = 0.0;
+        for (index, surface) in self.surfaces.iter_mut().enumerate() {
+            let thickness = surface.thickness().unwrap_or(0.0);
+            let position = surface.position();
+            if index == 0 {
+                surface.set_position(Point3 { z: -thickness, ..position });
+                continue
+            }
+            surface.set_position(Point3 { z, ..position });
+            z += thickness;
+        }
+    }
 }
 
 
-fn trace_plane_ray(mut ray: Ray3, z: f64) -> Option<Ray3> {
-    if ray.propagate_to_z(z) {
-        return Some(ray)
-    }
-    None
+/// Intersection with a sphere of curvature `c` (plane for zero curvature) with the vertex at
+/// `vertex`, following Welford: the ray is transferred to the vertex tangent plane and then
+/// to the surface, which picks the sheet of the sphere passing through the vertex.
+pub fn intersect_standard(ray: &Ray3, vertex: Point3, c: f64) -> Option<Intersection> {
+    let d = ray.direction.clone_normalized();
+    if d.z == 0. { return None }
+    let local = ray.origin - vertex;
+    let to_plane = -local.z / d.z;
+    let p = local + d * to_plane;
+    let f = c * (p.x * p.x + p.y * p.y);
+    let g = d.z - c * (p.x * d.x + p.y * d.y);
+    let discriminant = g * g - c * f;
+    if discriminant < 0. { return None }
+    let denominator = g + Float::sqrt(discriminant);
+    if denominator == 0. { return None }
+    let hit = p + d * (f / denominator);
+    let normal = Vector3 { x: -c * hit.x, y: -c * hit.y, z: 1. - c * hit.z };
+    Some(Intersection { point: vertex + hit, normal: normal.clone_normalized() })
 }
 
 
 impl fmt::Display for SequentialOpticalSystem {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f, "N  |   Type   | Comment |  Radius  | Thickness | Material | Semi-diameter\n"
-        ).map_err(|err| println!("{:?}", err)).ok();
+        writeln!(f, "N  |   Type   | Comment |  Radius  | Thickness | Material | Semi-diameter")?;
         for (pos, el) in self.surfaces.iter().enumerate() {
-            write!(f, "{}  |", (pos + 1).to_string()).map_err(|err| println!("{:?}", err)).ok();
-            write!(f, " {} |", el.surface_type()).map_err(|err| println!("{:?}", err)).ok();
-            write!(f, "         |").map_err(|err| println!("{:?}", err)).ok();
-            write!(f, " {:.3}   |", el.radius().unwrap_or(0.0)).map_err(|err| println!("{:?}", err)).ok();
-            write!(f, " {:.3}   |", el.thickness().unwrap_or(0.0)).map_err(|err| println!("{:?}", err)).ok();
-            write!(f, "          |\n").map_err(|err| println!("{:?}", err)).ok();
+            write!(f, "{}  |", pos + 1)?;
+            write!(f, " {} |", el.surface_type())?;
+            write!(f, "         |")?;
+            write!(f, " {:.3}   |", el.radius().unwrap_or(0.0))?;
+            write!(f, " {:.3}   |", el.thickness().unwrap_or(0.0))?;
+            write!(f, " {} |", el.material().name())?;
+            writeln!(f, " {:.3}", el.semi_diameter().unwrap_or(0.0))?;
         }
         Ok(())
     }
 }
 
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::geometry::vector::Vector3;
-//     use super::*;
-//
-//     #[test]
-//     fn test_ray_sphere_intersection() {
-//         let c0 = Point3::origin();
-//         let c1 = Point3{x: 0.0, y: 3.0, z: 0.0 };
-//
-//         let rad1: f64 = 1.0;
-//         let rad2: f64 = 2.0;
-//         let rad3: f64 = 3.0;
-//
-//         let ray1 = Ray3 {
-//             origin: c0,
-//             direction: Vector3 {
-//                 x: 0.0,
-//                 y: 0.0,
-//                 z: 1.0,
-//             },
-//             validity: RayValidity::VALID,
-//         };
-//         let inter1 = intersect_ray_with_sphere(ray1, c0, rad1);
-//         println!("{}", inter1.unwrap());
-//     }
-// }
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::database::wavelengths::{LINE_C, LINE_F};
+    use crate::materials::catalog;
+    use crate::materials::dispersion::Dispersion;
+    use crate::materials::gradient_index::{AxialGradient, RadialGradient};
+    use crate::materials::material::Air;
+    use crate::materials::uniaxial::UniaxialCrystal;
+    use crate::optical_system::fixtures::surface;
+
+    #[test]
+    fn test_sphere_intersection_sheet() {
+        let ray = Ray3::new(Point3{x: 0., y: 1., z: -10.}, Vector3::unit_z());
+        let convex = intersect_standard(&ray, Point3::origin(), 1. / 5.).unwrap();
+        let concave = intersect_standard(&ray, Point3::origin(), -1. / 5.).unwrap();
+        let sag = 5. - Float::sqrt(24.);
+        assert_approx_eq!(convex.point.z, sag);
+        assert_approx_eq!(concave.point.z, -sag);
+        assert_approx_eq!(convex.normal.y, -1. / 5.);
+
+        let lens = surface(5., 1., Box::new(Air::default()));
+        let moved = lens.trace(ray).unwrap();
+        assert_eq!(moved.origin, convex.point);
+        assert_eq!(moved.direction, ray.direction);
+    }
+
+    #[test]
+    fn test_blue_rays_focus_shorter() {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., 10., Box::new(Air::default())));
+        system.add_surface(surface(50., 5., Box::new(catalog::glass("N-BK7").unwrap())));
+        system.add_surface(surface(0., 90., Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+
+        let ray = Ray3::new(Point3{x: 0., y: 5., z: -10.}, Vector3::unit_z());
+        let blue = system.trace_ray(ray.with_wavelength(LINE_F));
+        let red = system.trace_ray(ray.with_wavelength(LINE_C));
+        assert_eq!(blue.wavelength, LINE_F);
+        assert!(blue.direction.y < red.direction.y);
+    }
+
+    #[test]
+    fn test_calcite_beam_displacer() {
+        let axis = Vector3{x: 0., y: 1., z: 1.};
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., 10., Box::new(Air::default())));
+        system.add_surface(surface(0., 20., Box::new(UniaxialCrystal::calcite(axis))));
+        system.add_surface(surface(0., 5., Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        let sodium = Wavelength::from_nm(589.3);
+
+        let ray = Ray3::new(Point3{x: 0., y: 0., z: -10.}, Vector3::unit_z()).with_wavelength(sodium);
+        let ordinary = system.trace_ray(ray);
+        assert_eq!(ordinary.validity, RayValidity::VALID);
+        assert_approx_eq!(ordinary.origin.y, 0.);
+        assert_approx_eq!(ordinary.origin.z, 25.);
+
+        system.birefringence_mode = BirefringenceMode::Extraordinary;
+        let extraordinary = system.trace_ray(ray);
+        let crystal = UniaxialCrystal::calcite(axis);
+        let walk_off = crystal.walk_off_angle(sodium, Vector3::unit_z());
+        assert_eq!(extraordinary.validity, RayValidity::VALID);
+        assert_approx_eq!(extraordinary.origin.y, -20. * walk_off.tan());
+        assert_approx_eq!(extraordinary.direction.z, 1.);
+    }
+
+    #[test]
+    fn test_uniform_gradient_matches_homogeneous_medium() {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., 10., Box::new(Air::default())));
+        system.add_surface(surface(20., 15., Box::new(AxialGradient::new("flat", Dispersion::Constant(1.6), vec![]))));
+        system.add_surface(surface(-20., 0., Box::new(Air::default())));
+
+        let ray = system.trace_ray(Ray3::new(Point3{x: 0., y: 2., z: -10.}, Vector3::unit_z()));
+        let first = intersect_standard(&Ray3::new(Point3{x: 0., y: 2., z: -10.}, Vector3::unit_z()), Point3::origin(), 1. / 20.).unwrap();
+        let inside = refract_isotropic(WaveState::isotropic(Vector3::unit_z(), 1.), first.normal, 1.6).unwrap();
+        let second = intersect_standard(&Ray3::new(first.point, inside.ray_direction), Point3{x: 0., y: 0., z: 15.}, -1. / 20.).unwrap();
+
+        assert_eq!(ray.validity, RayValidity::VALID);
+        assert_approx_eq!(ray.origin.y, second.point.y, 1e-9);
+        assert_approx_eq!(ray.origin.z, second.point.z, 1e-9);
+        let expected_path = (first.point.z + 10.) + 1.6 * (second.point - first.point).norm();
+        assert_approx_eq!(ray.optical_path, expected_path, 1e-9);
+    }
+
+    #[test]
+    fn test_quarter_pitch_grin_rod_focuses_with_equal_optical_path() {
+        let sqrt_a = 0.3;
+        let length = std::f64::consts::PI / (2. * sqrt_a);
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., 1., Box::new(Air::default())));
+        system.add_surface(surface(0., length, Box::new(RadialGradient::parabolic("rod", 1.6, sqrt_a))));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system.gradient_step = 0.01;
+
+        let paths: Vec<Ray3> = [0., 0.01, 0.02].iter()
+            .map(|&y| system.trace_ray(Ray3::new(Point3{x: 0., y, z: -1.}, Vector3::unit_z())))
+            .collect();
+        for ray in paths.iter() {
+            assert_eq!(ray.validity, RayValidity::VALID);
+            assert_approx_eq!(ray.origin.y, 0., 1e-5);
+            assert_approx_eq!(ray.origin.z, length, 1e-9);
+            assert_approx_eq!(ray.optical_path, paths[0].optical_path, 1e-6);
+        }
+    }
+}
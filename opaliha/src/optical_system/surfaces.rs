@@ -0,0 +1,350 @@
+use std::fmt;
+use num::Float;
+use crate::geometry::intersection::Intersection;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::Ray3;
+use crate::geometry::vector::Vector3;
+use crate::materials::material::Material;
+use crate::optical_system::sequential_optical_system::{OpticalSurface, OpticalSurfaceType};
+
+const SAG_ITERATIONS: usize = 50;
+const SAG_TOLERANCE: f64 = 1e-12;
+
+/// Surface given by its sag z = f(x, y) in the local frame of the vertex.
+pub trait Sag {
+    fn sag(&self, x: f64, y: f64) -> Option<f64>;
+    /// Partial derivatives (∂z/∂x, ∂z/∂y).
+    fn sag_gradient(&self, x: f64, y: f64) -> Option<(f64, f64)>;
+}
+
+/// Biconic surface with separate radii and conics in the XZ and YZ sections. A zero radius is
+/// a flat section, so a cylindrical surface has one of the radii equal to zero.
+pub struct BiconicSurface {
+    pub name: String,
+    pub comment: String,
+    pub surface_type: OpticalSurfaceType,
+    pub radius_x: f64,
+    pub radius_y: f64,
+    pub conic_x: f64,
+    pub conic_y: f64,
+    pub thickness: f64,
+    pub material: Box<dyn Material>,
+    pub position: Point3,
+    pub semi_diameter: Option<f64>,
+}
+
+/// Rotationally symmetric conic with even polynomial terms,
+/// z = c r² / (1 + √(1 − (1 + k) c² r²)) + α₁ r⁴ + α₂ r⁶ + …
+pub struct EvenAsphereSurface {
+    pub name: String,
+    pub comment: String,
+    pub surface_type: OpticalSurfaceType,
+    pub radius: f64,
+    pub conic: f64,
+    /// Coefficients of r⁴, r⁶, r⁸ and so on.
+    pub coefficients: Vec<f64>,
+    pub thickness: f64,
+    pub material: Box<dyn Material>,
+    pub position: Point3,
+    pub semi_diameter: Option<f64>,
+}
+
+/// Plane tilted around the vertex, z = x tan θx + y tan θy.
+pub struct TiltedSurface {
+    pub name: String,
+    pub comment: String,
+    pub surface_type: OpticalSurfaceType,
+    pub x_tangent: f64,
+    pub y_tangent: f64,
+    pub thickness: f64,
+    pub material: Box<dyn Material>,
+    pub position: Point3,
+    pub semi_diameter: Option<f64>,
+}
+
+
+impl BiconicSurface {
+    pub fn new(radius_x: f64, radius_y: f64, thickness: f64, material: Box<dyn Material>) -> BiconicSurface {
+        BiconicSurface {
+            name: String::new(),
+            comment: String::new(),
+            surface_type: OpticalSurfaceType::Biconic,
+            radius_x,
+            radius_y,
+            conic_x: 0.,
+            conic_y: 0.,
+            thickness,
+            material,
+            position: Point3::origin(),
+            semi_diameter: None,
+        }
+    }
+
+    /// Cylinder with power in the YZ section only.
+    pub fn cylinder(radius_y: f64, thickness: f64, material: Box<dyn Material>) -> BiconicSurface {
+        BiconicSurface::new(0., radius_y, thickness, material)
+    }
+
+    pub fn curvatures(&self) -> (f64, f64) {
+        let curvature = |r: f64| if r == 0. { 0. } else { 1. / r };
+        (curvature(self.radius_x), curvature(self.radius_y))
+    }
+}
+
+
+impl EvenAsphereSurface {
+    pub fn new(radius: f64, conic: f64, coefficients: Vec<f64>, thickness: f64, material: Box<dyn Material>) -> EvenAsphereSurface {
+        EvenAsphereSurface {
+            name: String::new(),
+            comment: String::new(),
+            surface_type: OpticalSurfaceType::EvenAsphere,
+            radius,
+            conic,
+            coefficients,
+            thickness,
+            material,
+            position: Point3::origin(),
+            semi_diameter: None,
+        }
+    }
+
+    pub fn curvature(&self) -> f64 {
+        if self.radius == 0. { 0. } else { 1. / self.radius }
+    }
+}
+
+
+impl TiltedSurface {
+    /// Plane tilted by `angle` degrees around the x axis.
+    pub fn around_x(angle: f64, thickness: f64, material: Box<dyn Material>) -> TiltedSurface {
+        TiltedSurface {
+            name: String::new(),
+            comment: String::new(),
+            surface_type: OpticalSurfaceType::Tilted,
+            x_tangent: 0.,
+            y_tangent: angle.to_radians().tan(),
+            thickness,
+            material,
+            position: Point3::origin(),
+            semi_diameter: None,
+        }
+    }
+}
+
+
+impl Sag for BiconicSurface {
+    fn sag(&self, x: f64, y: f64) -> Option<f64> {
+        let (cx, cy) = self.curvatures();
+        let q = 1. - (1. + self.conic_x) * cx * cx * x * x - (1. + self.conic_y) * cy * cy * y * y;
+        if q < 0. { return None }
+        Some((cx * x * x + cy * y * y) / (1. + Float::sqrt(q)))
+    }
+
+    fn sag_gradient(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let (cx, cy) = self.curvatures();
+        let q = 1. - (1. + self.conic_x) * cx * cx * x * x - (1. + self.conic_y) * cy * cy * y * y;
+        if q <= 0. { return None }
+        let s = Float::sqrt(q);
+        let numerator = cx * x * x + cy * y * y;
+        let denominator = (1. + s) * (1. + s);
+        let ds_dx = -(1. + self.conic_x) * cx * cx * x / s;
+        let ds_dy = -(1. + self.conic_y) * cy * cy * y / s;
+        Some((
+            (2. * cx * x * (1. + s) - numerator * ds_dx) / denominator,
+            (2. * cy * y * (1. + s) - numerator * ds_dy) / denominator,
+        ))
+    }
+}
+
+
+impl Sag for EvenAsphereSurface {
+    fn sag(&self, x: f64, y: f64) -> Option<f64> {
+        let c = self.curvature();
+        let r2 = x * x + y * y;
+        let q = 1. - (1. + self.conic) * c * c * r2;
+        if q < 0. { return None }
+        let polynomial = self.coefficients.iter().rev().fold(0., |sum, a| sum * r2 + a);
+        Some(c * r2 / (1. + Float::sqrt(q)) + polynomial * r2 * r2)
+    }
+
+    fn sag_gradient(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let c = self.curvature();
+        let r2 = x * x + y * y;
+        let q = 1. - (1. + self.conic) * c * c * r2;
+        if q <= 0. { return None }
+        // dz/d(r²) of the conic and of the polynomial terms
+        let conic = c / (2. * Float::sqrt(q));
+        let polynomial = self.coefficients.iter().enumerate()
+            .map(|(i, a)| a * (i + 2) as f64 * r2.powi(i as i32 + 1))
+            .sum::<f64>();
+        let derivative = conic + polynomial;
+        Some((2. * x * derivative, 2. * y * derivative))
+    }
+}
+
+
+impl Sag for TiltedSurface {
+    fn sag(&self, x: f64, y: f64) -> Option<f64> {
+        Some(x * self.x_tangent + y * self.y_tangent)
+    }
+
+    fn sag_gradient(&self, _x: f64, _y: f64) -> Option<(f64, f64)> {
+        Some((self.x_tangent, self.y_tangent))
+    }
+}
+
+
+/// Intersection with a surface given by its sag: the ray is transferred to the vertex tangent
+/// plane and the distance along the ray is refined by Newton iterations.
+pub fn intersect_sag(ray: &Ray3, vertex: Point3, surface: &dyn Sag) -> Option<Intersection> {
+    let d = ray.direction.clone_normalized();
+    if d.z == 0. { return None }
+    let local = ray.origin - vertex;
+    let start = local + d * (-local.z / d.z);
+    let mut t = 0.;
+    for _ in 0..SAG_ITERATIONS {
+        let p = start + d * t;
+        let (gx, gy) = surface.sag_gradient(p.x, p.y)?;
+        let residual = p.z - surface.sag(p.x, p.y)?;
+        let slope = d.z - gx * d.x - gy * d.y;
+        if slope == 0. { return None }
+        let step = residual / slope;
+        t -= step;
+        if step.abs() < SAG_TOLERANCE {
+            let hit = start + d * t;
+            let (gx, gy) = surface.sag_gradient(hit.x, hit.y)?;
+            let normal = Vector3 { x: -gx, y: -gy, z: 1. };
+            return Some(Intersection { point: vertex + hit, normal: normal.clone_normalized() })
+        }
+    }
+    None
+}
+
+
+impl fmt::Debug for BiconicSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "biconic rx {} ry {}", self.radius_x, self.radius_y)
+    }
+}
+
+
+impl fmt::Debug for EvenAsphereSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "even asphere r {} k {} {:?}", self.radius, self.conic, self.coefficients)
+    }
+}
+
+
+impl fmt::Debug for TiltedSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tilted tx {} ty {}", self.x_tangent, self.y_tangent)
+    }
+}
+
+
+impl OpticalSurface for BiconicSurface {
+    fn name(&self) -> &str { &self.name }
+    fn comment(&self) -> &str { &self.comment }
+    fn surface_type(&self) -> &OpticalSurfaceType { &self.surface_type }
+    /// Radius of the YZ section, used by the axially symmetric paraxial trace.
+    fn radius(&self) -> Option<f64> { (self.radius_y != 0.).then_some(self.radius_y) }
+    fn section_curvatures(&self) -> (f64, f64) { self.curvatures() }
+    fn thickness(&self) -> Option<f64> { Some(self.thickness) }
+    fn position(&self) -> Point3 { self.position }
+    fn semi_diameter(&self) -> Option<f64> { self.semi_diameter }
+    fn set_position(&mut self, position: Point3) { self.position = position }
+    fn set_thickness(&mut self, thickness: f64) { self.thickness = thickness }
+    fn material(&self) -> &dyn Material { self.material.as_ref() }
+    fn intersect(&self, ray: &Ray3) -> Option<Intersection> {
+        intersect_sag(ray, self.position, self)
+    }
+}
+
+
+impl OpticalSurface for EvenAsphereSurface {
+    fn name(&self) -> &str { &self.name }
+    fn comment(&self) -> &str { &self.comment }
+    fn surface_type(&self) -> &OpticalSurfaceType { &self.surface_type }
+    fn radius(&self) -> Option<f64> { (self.radius != 0.).then_some(self.radius) }
+    fn fourth_order_deformation(&self) -> f64 {
+        self.conic * self.curvature().powi(3) / 8. + self.coefficients.first().copied().unwrap_or(0.)
+    }
+    fn sixth_order_deformation(&self) -> f64 {
+        // the conic sag has (1 + k)² c⁵ / 16 at r⁶, the sphere c⁵ / 16
+        ((1. + self.conic).powi(2) - 1.) * self.curvature().powi(5) / 16. + self.coefficients.get(1).copied().unwrap_or(0.)
+    }
+    fn thickness(&self) -> Option<f64> { Some(self.thickness) }
+    fn position(&self) -> Point3 { self.position }
+    fn semi_diameter(&self) -> Option<f64> { self.semi_diameter }
+    fn set_position(&mut self, position: Point3) { self.position = position }
+    fn set_thickness(&mut self, thickness: f64) { self.thickness = thickness }
+    fn material(&self) -> &dyn Material { self.material.as_ref() }
+    fn intersect(&self, ray: &Ray3) -> Option<Intersection> {
+        intersect_sag(ray, self.position, self)
+    }
+}
+
+
+impl OpticalSurface for TiltedSurface {
+    fn name(&self) -> &str { &self.name }
+    fn comment(&self) -> &str { &self.comment }
+    fn surface_type(&self) -> &OpticalSurfaceType { &self.surface_type }
+    fn radius(&self) -> Option<f64> { None }
+    fn thickness(&self) -> Option<f64> { Some(self.thickness) }
+    fn position(&self) -> Point3 { self.position }
+    fn semi_diameter(&self) -> Option<f64> { self.semi_diameter }
+    fn set_position(&mut self, position: Point3) { self.position = position }
+    fn set_thickness(&mut self, thickness: f64) { self.thickness = thickness }
+    fn material(&self) -> &dyn Material { self.material.as_ref() }
+    fn intersect(&self, ray: &Ray3) -> Option<Intersection> {
+        intersect_sag(ray, self.position, self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::material::Air;
+    use crate::optical_system::sequential_optical_system::intersect_standard;
+
+    #[test]
+    fn test_rotational_biconic_matches_sphere() {
+        let mut biconic = BiconicSurface::new(-30., -30., 0., Box::new(Air::default()));
+        biconic.position = Point3 { x: 0., y: 0., z: 5. };
+        let ray = Ray3::new(Point3 { x: 1., y: 7., z: -3. }, Vector3 { x: 0.05, y: -0.1, z: 1. });
+        let sphere = intersect_standard(&ray, biconic.position, -1. / 30.).unwrap();
+        let hit = biconic.intersect(&ray).unwrap();
+        assert_approx_eq!((hit.point - sphere.point).norm(), 0., 1e-10);
+        assert_approx_eq!((hit.normal - sphere.normal).norm(), 0., 1e-10);
+    }
+
+    #[test]
+    fn test_cylinder_and_tilted_plane() {
+        let cylinder = BiconicSurface::cylinder(20., 0., Box::new(Air::default()));
+        let hit = cylinder.intersect(&Ray3::new(Point3 { x: 3., y: 4., z: -1. }, Vector3::unit_z())).unwrap();
+        assert_approx_eq!(hit.point.z, 20. - Float::sqrt(20f64 * 20. - 16.));
+        assert_eq!(hit.normal.x, 0.);
+
+        let plane = TiltedSurface::around_x(45., 0., Box::new(Air::default()));
+        let hit = plane.intersect(&Ray3::new(Point3 { x: 0., y: 2., z: -1. }, Vector3::unit_z())).unwrap();
+        assert_approx_eq!(hit.point.z, 2.);
+        assert_approx_eq!(hit.normal.y, -Float::sqrt(0.5));
+    }
+
+    #[test]
+    fn test_even_asphere_sag() {
+        let mut asphere = EvenAsphereSurface::new(25., -1., vec![1e-5, -2e-8], 0., Box::new(Air::default()));
+        asphere.position = Point3 { x: 0., y: 0., z: 3. };
+        let r2 = 4f64 * 4. + 3. * 3.;
+        let expected = r2 / 50. + 1e-5 * r2 * r2 - 2e-8 * r2 * r2 * r2;
+        assert_approx_eq!(asphere.sag(4., 3.).unwrap(), expected);
+
+        let ray = Ray3::new(Point3 { x: 4., y: 3., z: -1. }, Vector3::unit_z());
+        let hit = asphere.intersect(&ray).unwrap();
+        assert_approx_eq!(hit.point.z, 3. + expected, 1e-10);
+        let slope = 1. / 25. + 2. * 2e-5 * r2 - 3. * 2. * 2e-8 * r2 * r2;
+        assert_approx_eq!(hit.normal.x / hit.normal.z, -4. * slope, 1e-10);
+    }
+}
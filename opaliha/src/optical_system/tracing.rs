@@ -0,0 +1,192 @@
+use num::Float;
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::intersection::Intersection;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::Ray3;
+use crate::geometry::vector::Vector3;
+use crate::materials::gradient_index::GradientIndex;
+use crate::materials::material::Material;
+use crate::optical_system::sequential_optical_system::OpticalSurface;
+
+/// Upper bound of integration steps inside a gradient medium before the ray is dropped.
+const MAX_GRADIENT_STEPS: usize = 1_000_000;
+
+/// Which of the two waves is followed inside a uniaxial crystal.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum BirefringenceMode {
+    #[default]
+    Ordinary,
+    Extraordinary,
+}
+
+/// Wave travelling through a medium. The wave vector is normalized to the phase index
+/// (|k| = n), the ray direction is the unit energy direction. Both coincide in isotropic media.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WaveState {
+    pub wave_vector: Vector3,
+    pub ray_direction: Vector3,
+}
+
+
+impl WaveState {
+    pub fn isotropic(direction: Vector3, refraction_index: f64) -> WaveState {
+        let ray_direction = direction.clone_normalized();
+        WaveState { wave_vector: ray_direction * refraction_index, ray_direction }
+    }
+
+    pub fn phase_index(&self) -> f64 {
+        self.wave_vector.norm()
+    }
+}
+
+
+/// Refracts the wave into `medium` on a boundary with unit `normal`.
+/// The tangential component of the wave vector is preserved, the normal one is found from
+/// the index (or, for the extraordinary wave, the index ellipsoid) of the new medium.
+/// Returns `None` on total internal reflection.
+pub fn refract(
+    wave: WaveState,
+    normal: Vector3,
+    medium: &dyn Material,
+    wavelength: Wavelength,
+    mode: BirefringenceMode,
+) -> Option<WaveState> {
+    match (medium.as_uniaxial(), mode) {
+        (Some(crystal), BirefringenceMode::Extraordinary) => {
+            let (tangential, normal) = split_wave_vector(wave, normal);
+            let wave_vector = crystal.extraordinary_wave_vector(wavelength, tangential, normal)?;
+            Some(WaveState {
+                wave_vector,
+                ray_direction: crystal.extraordinary_ray_direction(wavelength, wave_vector),
+            })
+        }
+        _ => refract_isotropic(wave, normal, medium.refraction_index_at(wavelength)),
+    }
+}
+
+
+/// Refracts the wave into an isotropic medium with the index `n` at the boundary point.
+pub fn refract_isotropic(wave: WaveState, normal: Vector3, n: f64) -> Option<WaveState> {
+    let (tangential, normal) = split_wave_vector(wave, normal);
+    let normal_component = n * n - tangential.dot(tangential);
+    if normal_component < 0. { return None }
+    let wave_vector = tangential + normal * Float::sqrt(normal_component);
+    Some(WaveState { wave_vector, ray_direction: wave_vector.clone_normalized() })
+}
+
+
+/// Tangential part of the wave vector and the unit normal oriented along propagation.
+fn split_wave_vector(wave: WaveState, normal: Vector3) -> (Vector3, Vector3) {
+    let mut normal = normal.clone_normalized();
+    if wave.wave_vector.dot(normal) < 0. {
+        normal = -normal;
+    }
+    (wave.wave_vector - normal * wave.wave_vector.dot(normal), normal)
+}
+
+
+/// Result of integrating a ray through a gradient medium up to the next surface.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GradientPropagation {
+    pub intersection: Intersection,
+    pub wave: WaveState,
+    pub optical_path: f64,
+}
+
+
+/// Integrates the eikonal ray equation d²R/dt² = n∇n (dt = ds / n, optical ray vector
+/// T = n dR/ds) with the Runge-Kutta scheme of Sharma, Kumar and Ghatak (1982) until the ray
+/// crosses `target` at the wavelength of the ray. `frame` is the vertex of the surface owning
+/// the medium, `step` is the geometrical step length. The optical path ∫n ds = ∫n² dt is
+/// integrated by Simpson's rule.
+pub fn propagate_in_gradient(
+    ray: &Ray3,
+    medium: &dyn GradientIndex,
+    frame: Point3,
+    target: &dyn OpticalSurface,
+    step: f64,
+) -> Option<GradientPropagation> {
+    let to_global = frame - Point3::origin();
+    let wavelength = ray.wavelength;
+    let index = |r: Point3| medium.index_at(r, wavelength);
+    let acceleration = |r: Point3| medium.gradient_at(r, wavelength) * medium.index_at(r, wavelength);
+
+    let mut r = ray.origin + (-to_global);
+    let mut t = ray.direction.clone_normalized() * index(r);
+    let mut optical_path = 0.;
+
+    for _ in 0..MAX_GRADIENT_STEPS {
+        let chord = Ray3::new(r + to_global, t).with_wavelength(wavelength);
+        let hit = target.intersect(&chord)?;
+        let remaining = (hit.point - (r + to_global)).dot(chord.direction);
+        if remaining < 0. { return None }
+
+        let length = remaining.min(step);
+        let dt = length / index(r);
+        let a = acceleration(r) * dt;
+        let middle = r + t * (dt / 2.) + a * (dt / 8.);
+        let b = acceleration(middle) * dt;
+        let end = r + (t + (a + b * 2.) / 6.) * dt;
+        let c = acceleration(r + t * dt + b * (dt / 2.)) * dt;
+
+        optical_path += dt / 6. * (index(r).powi(2) + 4. * index(middle).powi(2) + index(end).powi(2));
+        r = end;
+        t = t + (a + b * 4. + c) / 6.;
+
+        if remaining <= step {
+            // close the residual gap left by the curvature of the last step
+            let chord = Ray3::new(r + to_global, t).with_wavelength(wavelength);
+            let hit = target.intersect(&chord)?;
+            let residual = (hit.point - (r + to_global)).dot(chord.direction);
+            let n = index(r);
+            optical_path += n * residual;
+            return Some(GradientPropagation {
+                intersection: hit,
+                wave: WaveState::isotropic(chord.direction, n),
+                optical_path,
+            })
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::material::Air;
+    use crate::materials::uniaxial::UniaxialCrystal;
+
+    const GREEN: Wavelength = Wavelength::from_nm(550.);
+    const SODIUM: Wavelength = Wavelength::from_nm(589.3);
+
+    #[test]
+    fn test_snell_law_and_tir() {
+        let crystal = UniaxialCrystal::quartz(Vector3::unit_z());
+        let n = crystal.ordinary_index(GREEN);
+        let incident = WaveState::isotropic(Vector3{x: 0., y: 0.5, z: 1.}, 1.);
+        let refracted = refract(incident, Vector3::unit_z(), &crystal, GREEN, BirefringenceMode::Ordinary).unwrap();
+        assert_approx_eq!(refracted.ray_direction.y * n, incident.ray_direction.y);
+
+        let inside = WaveState::isotropic(Vector3{x: 0., y: 1., z: 1.}, n);
+        assert_eq!(refract(inside, Vector3::unit_z(), &Air::default(), GREEN, BirefringenceMode::Ordinary), None);
+    }
+
+    #[test]
+    fn test_extraordinary_walk_off_at_normal_incidence() {
+        let axis = Vector3{x: 0., y: 1., z: 1.};
+        let calcite = UniaxialCrystal::calcite(axis);
+        let incident = WaveState::isotropic(Vector3::unit_z(), 1.);
+
+        let o = refract(incident, Vector3::unit_z(), &calcite, SODIUM, BirefringenceMode::Ordinary).unwrap();
+        assert_eq!(o.ray_direction, Vector3::unit_z());
+
+        let e = refract(incident, Vector3::unit_z(), &calcite, SODIUM, BirefringenceMode::Extraordinary).unwrap();
+        assert_approx_eq!(e.wave_vector.clone_normalized().z, 1.);
+        assert_approx_eq!(e.phase_index(), calcite.extraordinary_index(SODIUM, Vector3::unit_z()));
+        let walk_off = Float::acos(e.ray_direction.z);
+        assert_approx_eq!(walk_off, calcite.walk_off_angle(SODIUM, Vector3::unit_z()));
+        assert!(e.ray_direction.y < 0.);
+    }
+}
@@ -0,0 +1,483 @@
+use std::ops::Mul;
+use crate::database::wavelengths::Wavelength;
+use crate::geometry::point::Point3;
+use crate::geometry::ray::{Ray3, RayValidity};
+use crate::geometry::vector::Vector3;
+use crate::optical_system::anamorphic::Section;
+use crate::optical_system::sequential_optical_system::SequentialOpticalSystem;
+
+/// Step of the differential rays around the axial ray, lens units and reduced angle.
+const DIFFERENTIAL_STEP: f64 = 1e-5;
+
+/// Ray transfer matrix acting on the height and the reduced angle: (y, nu) → (A y + B nu, C y + D nu).
+/// With reduced angles the determinant is one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RayTransferMatrix {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+}
+
+/// Transfer matrix of (x, nu_x, y, nu_y) for systems without rotational symmetry.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RayTransferMatrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+/// Cardinal points as distances from the input (front points) and the output (back points)
+/// reference planes of the matrix.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CardinalPoints {
+    pub effective_focal_length: f64,
+    pub front_focal_point: f64,
+    pub back_focal_point: f64,
+    pub front_principal_point: f64,
+    pub back_principal_point: f64,
+    pub front_nodal_point: f64,
+    pub back_nodal_point: f64,
+}
+
+/// Image of an object at `object_distance` in front of the input plane.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Conjugate {
+    pub object_distance: f64,
+    /// From the output plane, negative for a virtual image.
+    pub image_distance: f64,
+    pub magnification: f64,
+}
+
+/// Position of a two-group zoom for one focal length. Distances are between vertex planes:
+/// `spacing` from the output of the first group to the input of the second one,
+/// `back_focal_distance` from the output of the second group to the focus.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ZoomCamPoint {
+    pub focal_length: f64,
+    pub spacing: f64,
+    pub back_focal_distance: f64,
+}
+
+
+impl RayTransferMatrix {
+    pub fn identity() -> RayTransferMatrix {
+        RayTransferMatrix { a: 1., b: 0., c: 0., d: 1. }
+    }
+
+    /// Translation over `thickness` in a medium of index `n`.
+    pub fn translation(thickness: f64, n: f64) -> RayTransferMatrix {
+        RayTransferMatrix { b: thickness / n, ..RayTransferMatrix::identity() }
+    }
+
+    /// Refraction by a surface of optical power φ = c (n' − n).
+    pub fn refraction(power: f64) -> RayTransferMatrix {
+        RayTransferMatrix { c: -power, ..RayTransferMatrix::identity() }
+    }
+
+    /// Matrix of `self` followed by `next`.
+    pub fn then(&self, next: RayTransferMatrix) -> RayTransferMatrix {
+        next * *self
+    }
+
+    pub fn apply(&self, y: f64, nu: f64) -> (f64, f64) {
+        (self.a * y + self.b * nu, self.c * y + self.d * nu)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.a * self.d - self.b * self.c
+    }
+
+    pub fn inverse(&self) -> RayTransferMatrix {
+        let det = self.determinant();
+        RayTransferMatrix { a: self.d / det, b: -self.b / det, c: -self.c / det, d: self.a / det }
+    }
+
+    pub fn power(&self) -> f64 {
+        -self.c
+    }
+
+    /// Cardinal points for media of index `n` in front of and `n_image` behind the matrix.
+    /// Afocal matrices (C = 0) give infinite distances.
+    pub fn cardinal_points(&self, n: f64, n_image: f64) -> CardinalPoints {
+        let c = self.c;
+        CardinalPoints {
+            effective_focal_length: -1. / c,
+            front_focal_point: n * self.d / c,
+            back_focal_point: -n_image * self.a / c,
+            front_principal_point: n * (self.d - 1.) / c,
+            back_principal_point: n_image * (1. - self.a) / c,
+            front_nodal_point: (n * self.d - n_image) / c,
+            back_nodal_point: (n - n_image * self.a) / c,
+        }
+    }
+
+    /// Solves the conjugate of an object `object_distance` in front of the input plane
+    /// (positive for a real object).
+    pub fn conjugate(&self, object_distance: f64, n: f64, n_image: f64) -> Conjugate {
+        let total = RayTransferMatrix::translation(object_distance, n).then(*self);
+        let image_distance = -n_image * total.b / total.d;
+        let magnification = total.then(RayTransferMatrix::translation(image_distance, n_image)).a;
+        Conjugate { object_distance, image_distance, magnification }
+    }
+
+    /// Conjugate with the lateral magnification `magnification` (n u = m n' u').
+    pub fn conjugate_for_magnification(&self, magnification: f64, n: f64, n_image: f64) -> Conjugate {
+        // D of the object-to-image matrix is 1 / m and grows by C s / n with the object distance
+        let object_distance = n * (1. / magnification - self.d) / self.c;
+        self.conjugate(object_distance, n, n_image)
+    }
+}
+
+
+impl Mul for RayTransferMatrix {
+    type Output = RayTransferMatrix;
+
+    fn mul(self, rhs: RayTransferMatrix) -> RayTransferMatrix {
+        RayTransferMatrix {
+            a: self.a * rhs.a + self.b * rhs.c,
+            b: self.a * rhs.b + self.b * rhs.d,
+            c: self.c * rhs.a + self.d * rhs.c,
+            d: self.c * rhs.b + self.d * rhs.d,
+        }
+    }
+}
+
+
+impl RayTransferMatrix4 {
+    pub fn identity() -> RayTransferMatrix4 {
+        RayTransferMatrix4::from_sections(RayTransferMatrix::identity(), RayTransferMatrix::identity())
+    }
+
+    /// Block diagonal matrix of a system with two symmetry planes.
+    pub fn from_sections(x: RayTransferMatrix, y: RayTransferMatrix) -> RayTransferMatrix4 {
+        RayTransferMatrix4 {
+            m: [
+                [x.a, x.b, 0., 0.],
+                [x.c, x.d, 0., 0.],
+                [0., 0., y.a, y.b],
+                [0., 0., y.c, y.d],
+            ],
+        }
+    }
+
+    pub fn then(&self, next: RayTransferMatrix4) -> RayTransferMatrix4 {
+        next * *self
+    }
+
+    pub fn apply(&self, state: [f64; 4]) -> [f64; 4] {
+        let mut result = [0.; 4];
+        for (i, row) in self.m.iter().enumerate() {
+            result[i] = row.iter().zip(state.iter()).map(|(m, s)| m * s).sum();
+        }
+        result
+    }
+
+    /// Diagonal block of a section, meaningful when the sections are decoupled.
+    pub fn section(&self, section: Section) -> RayTransferMatrix {
+        let k = match section {
+            Section::X => 0,
+            Section::Y => 2,
+        };
+        RayTransferMatrix { a: self.m[k][k], b: self.m[k][k + 1], c: self.m[k + 1][k], d: self.m[k + 1][k + 1] }
+    }
+
+    /// Largest element of the off-diagonal blocks that couple the X and Y sections.
+    pub fn coupling(&self) -> f64 {
+        let mut largest: f64 = 0.;
+        for i in 0..4 {
+            for j in 0..4 {
+                if (i < 2) != (j < 2) {
+                    largest = largest.max(self.m[i][j].abs());
+                }
+            }
+        }
+        largest
+    }
+}
+
+
+impl Mul for RayTransferMatrix4 {
+    type Output = RayTransferMatrix4;
+
+    fn mul(self, rhs: RayTransferMatrix4) -> RayTransferMatrix4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        RayTransferMatrix4 { m }
+    }
+}
+
+
+impl SequentialOpticalSystem {
+    /// Refraction matrix of surface `k` from its paraxial curvature, `None` unless `k` lies
+    /// after the object and not past the image.
+    pub fn surface_matrix(&self, k: usize, wavelength: Wavelength) -> Option<RayTransferMatrix> {
+        let k = self.checked_surface(Some(k))?;
+        let indices = self.indices_at(wavelength);
+        let curvature = self.surfaces[k].radius().map_or(0., |r| 1. / r);
+        Some(RayTransferMatrix::refraction(curvature * (indices[k] - indices[k - 1])))
+    }
+
+    /// Refraction matrix of surface `k` with separate X and Y curvatures, `None` unless `k` lies
+    /// after the object and not past the image.
+    pub fn surface_matrix_4(&self, k: usize, wavelength: Wavelength) -> Option<RayTransferMatrix4> {
+        let k = self.checked_surface(Some(k))?;
+        let indices = self.indices_at(wavelength);
+        let (cx, cy) = self.surfaces[k].section_curvatures();
+        let delta = indices[k] - indices[k - 1];
+        Some(RayTransferMatrix4::from_sections(
+            RayTransferMatrix::refraction(cx * delta),
+            RayTransferMatrix::refraction(cy * delta),
+        ))
+    }
+
+    /// Matrix from the vertex plane of `first` in the medium in front of it to the vertex plane
+    /// of `last` in the medium behind it. `None` unless `first..=last` is a surface range between
+    /// the object and the image.
+    pub fn transfer_matrix(&self, first: usize, last: usize, wavelength: Wavelength) -> Option<RayTransferMatrix> {
+        let (first, last) = self.surface_range(first, last)?;
+        let indices = self.indices_at(wavelength);
+        (first..=last).try_fold(RayTransferMatrix::identity(), |matrix, k| {
+            let matrix = if k > first {
+                let thickness = self.surfaces[k - 1].thickness().unwrap_or(0.);
+                matrix.then(RayTransferMatrix::translation(thickness, indices[k - 1]))
+            } else {
+                matrix
+            };
+            Some(matrix.then(self.surface_matrix(k, wavelength)?))
+        })
+    }
+
+    pub fn transfer_matrix_4(&self, first: usize, last: usize, wavelength: Wavelength) -> Option<RayTransferMatrix4> {
+        let (first, last) = self.surface_range(first, last)?;
+        let indices = self.indices_at(wavelength);
+        (first..=last).try_fold(RayTransferMatrix4::identity(), |matrix, k| {
+            let matrix = if k > first {
+                let thickness = self.surfaces[k - 1].thickness().unwrap_or(0.);
+                let translation = RayTransferMatrix::translation(thickness, indices[k - 1]);
+                matrix.then(RayTransferMatrix4::from_sections(translation, translation))
+            } else {
+                matrix
+            };
+            Some(matrix.then(self.surface_matrix_4(k, wavelength)?))
+        })
+    }
+
+    /// Cardinal points of the surface range `first..=last`, as global z.
+    pub fn group_cardinal_points(&self, first: usize, last: usize, wavelength: Wavelength) -> Option<CardinalPoints> {
+        let matrix = self.transfer_matrix(first, last, wavelength)?;
+        let indices = self.indices_at(wavelength);
+        let points = matrix.cardinal_points(indices[first - 1], indices[last]);
+        let (z_first, z_last) = (self.surfaces[first].position().z, self.surfaces[last].position().z);
+        Some(CardinalPoints {
+            effective_focal_length: points.effective_focal_length,
+            front_focal_point: z_first + points.front_focal_point,
+            back_focal_point: z_last + points.back_focal_point,
+            front_principal_point: z_first + points.front_principal_point,
+            back_principal_point: z_last + points.back_principal_point,
+            front_nodal_point: z_first + points.front_nodal_point,
+            back_nodal_point: z_last + points.back_nodal_point,
+        })
+    }
+
+    /// `first` and `last` when both lie after the object and not past the image, in order.
+    fn surface_range(&self, first: usize, last: usize) -> Option<(usize, usize)> {
+        let first = self.checked_surface(Some(first))?;
+        let last = self.checked_surface(Some(last))?;
+        (first <= last).then_some((first, last))
+    }
+
+    /// 4×4 matrix from the vertex plane of surface 1 to a plane normal to the axial ray after
+    /// surface `last`, from central differences of real rays around the axial ray. Captures
+    /// tilts and coupling of the sections. Returns `None` when a differential ray fails or
+    /// `last` is not a surface between the object and the image.
+    pub fn differential_matrix(&self, last: usize) -> Option<RayTransferMatrix4> {
+        let last = self.checked_surface(Some(last))?;
+        let wavelength = self.primary_wavelength();
+        let indices = self.indices_at(wavelength);
+        let (n, n_last) = (indices[0], indices[last]);
+        let launch = |state: [f64; 4]| {
+            let origin = Point3 { x: state[0], y: state[2], z: 0. };
+            let direction = Vector3 { x: state[1] / n, y: state[3] / n, z: 1. };
+            self.trace_ray_to(Ray3::new(origin, direction).with_wavelength(wavelength), last)
+        };
+        let axial = launch([0.; 4]);
+        if axial.validity != RayValidity::VALID { return None }
+        let along = axial.direction;
+        let across_x = (Vector3::unit_x() - along * along.x).clone_normalized();
+        let across_y = along.cross_product(across_x);
+
+        let measure = |state: [f64; 4]| -> Option<[f64; 4]> {
+            let ray = launch(state);
+            if ray.validity != RayValidity::VALID { return None }
+            let t = (axial.origin - ray.origin).dot(along) / ray.direction.dot(along);
+            let offset = ray.at(t) - axial.origin;
+            let slope = n_last / ray.direction.dot(along);
+            Some([
+                offset.dot(across_x),
+                ray.direction.dot(across_x) * slope,
+                offset.dot(across_y),
+                ray.direction.dot(across_y) * slope,
+            ])
+        };
+        let mut m = [[0.; 4]; 4];
+        for j in 0..4 {
+            let mut state = [0.; 4];
+            state[j] = DIFFERENTIAL_STEP;
+            let plus = measure(state)?;
+            state[j] = -DIFFERENTIAL_STEP;
+            let minus = measure(state)?;
+            for i in 0..4 {
+                m[i][j] = (plus[i] - minus[i]) / (2. * DIFFERENTIAL_STEP);
+            }
+        }
+        Some(RayTransferMatrix4 { m })
+    }
+
+    /// Two-group zoom: for every focal length the air spacing between the groups
+    /// `first_group` and `second_group` (surface ranges) and the resulting back focal distance.
+    /// `None` is returned for focal lengths the groups can not reach, and for all of them when
+    /// a group is not a surface range between the object and the image.
+    pub fn zoom_cam(
+        &self,
+        first_group: (usize, usize),
+        second_group: (usize, usize),
+        focal_lengths: &[f64],
+        wavelength: Wavelength,
+    ) -> Vec<Option<ZoomCamPoint>> {
+        let indices = self.indices_at(wavelength);
+        let matrices = self.transfer_matrix(first_group.0, first_group.1, wavelength)
+            .zip(self.transfer_matrix(second_group.0, second_group.1, wavelength));
+        let Some((first, second)) = matrices else { return vec![None; focal_lengths.len()] };
+        let (n_gap, n_image) = (indices[first_group.1], indices[second_group.1]);
+        focal_lengths.iter().map(|&focal_length| {
+            // C of the combination is linear in the reduced spacing
+            let slope = second.c * first.c;
+            if slope == 0. { return None }
+            let reduced = (-1. / focal_length - second.c * first.a - second.d * first.c) / slope;
+            let spacing = reduced * n_gap;
+            if spacing < 0. { return None }
+            let total = first.then(RayTransferMatrix::translation(spacing, n_gap)).then(second);
+            Some(ZoomCamPoint {
+                focal_length,
+                spacing,
+                back_focal_distance: -n_image * total.a / total.c,
+            })
+        }).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+    use crate::materials::material::Air;
+    use crate::optical_system::surfaces::BiconicSurface;
+    use crate::optical_system::fixtures::{constant_glass as glass, surface};
+
+    /// n = 1.5, R = ±50, t = 5, followed by `gap` of air and a second identical lens.
+    fn two_lenses(gap: f64) -> SequentialOpticalSystem {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(surface(50., 5., glass()));
+        system.add_surface(surface(-50., gap, Box::new(Air::default())));
+        system.add_surface(surface(50., 5., glass()));
+        system.add_surface(surface(-50., 40., Box::new(Air::default())));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        system
+    }
+
+    #[test]
+    fn test_thick_lens_matrix() {
+        let system = two_lenses(10.);
+        let wavelength = system.primary_wavelength();
+        // R2 T R1 with φ1 = φ2 = 0.5 / 50 and t / n = 5 / 1.5, multiplied out by hand
+        let lens = system.transfer_matrix(1, 2, wavelength).unwrap();
+        assert_approx_eq!(lens.a, 1. - 0.01 * 5. / 1.5);
+        assert_approx_eq!(lens.b, 5. / 1.5);
+        assert_approx_eq!(lens.c, -0.01 - 0.01 * (1. - 0.01 * 5. / 1.5));
+        assert_approx_eq!(lens.d, 1. - 0.01 * 5. / 1.5);
+        assert_approx_eq!(lens.determinant(), 1.);
+        assert_eq!(system.surface_matrix(1, wavelength), Some(RayTransferMatrix::refraction(0.01)));
+        assert_eq!(system.surface_matrix(0, wavelength), None);
+        assert_eq!(system.surface_matrix(6, wavelength), None);
+        assert_eq!(system.group_cardinal_points(0, 2, wavelength), None);
+        assert_eq!(system.transfer_matrix(3, 2, wavelength), None);
+
+        let points = system.group_cardinal_points(1, 2, wavelength).unwrap();
+        let f = 1. / (0.02 - 0.01 * 0.01 * 5. / 1.5);
+        let shift = f * 0.01 * 5. / 1.5;
+        assert_approx_eq!(points.effective_focal_length, f);
+        assert_approx_eq!(points.front_principal_point, shift);
+        assert_approx_eq!(points.back_principal_point, 5. - shift);
+        assert_approx_eq!(points.back_focal_point, 5. - shift + f);
+        assert_approx_eq!(points.front_focal_point, shift - f);
+        assert_approx_eq!(points.front_nodal_point, points.front_principal_point);
+        assert_approx_eq!(points.back_nodal_point, points.back_principal_point);
+
+        let whole = system.transfer_matrix(1, 4, wavelength).unwrap();
+        assert_approx_eq!(-1. / whole.c, system.first_order_properties().unwrap().effective_focal_length);
+        assert_approx_eq!(lens.then(lens.inverse()).a, 1.);
+    }
+
+    #[test]
+    fn test_conjugates() {
+        let system = two_lenses(10.);
+        let lens = system.transfer_matrix(1, 2, system.primary_wavelength()).unwrap();
+        let points = lens.cardinal_points(1., 1.);
+        let conjugate = lens.conjugate(150., 1., 1.);
+        // Gauss equation between the principal planes
+        let s = -150. - points.front_principal_point;
+        let s_image = conjugate.image_distance - points.back_principal_point;
+        assert_approx_eq!(1. / s_image - 1. / s, 1. / points.effective_focal_length);
+        assert_approx_eq!(conjugate.magnification, s_image / s);
+
+        let unit = lens.conjugate_for_magnification(-1., 1., 1.);
+        assert_approx_eq!(unit.magnification, -1.);
+        assert_approx_eq!(unit.object_distance + points.front_principal_point, 2. * points.effective_focal_length);
+    }
+
+    #[test]
+    fn test_zoom_cam() {
+        let system = two_lenses(10.);
+        let wavelength = system.primary_wavelength();
+        let cam = system.zoom_cam((1, 2), (3, 4), &[30., 35., 20.], wavelength);
+        assert_eq!(cam[2], None);
+        assert_eq!(system.zoom_cam((0, 2), (3, 4), &[30.], wavelength), vec![None]);
+        for point in cam.iter().take(2) {
+            let point = point.unwrap();
+            let zoomed = two_lenses(point.spacing);
+            let properties = zoomed.first_order_properties().unwrap();
+            assert_approx_eq!(properties.effective_focal_length, point.focal_length, 1e-9);
+            assert_approx_eq!(properties.back_focal_length, point.back_focal_distance, 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_biconic_matrix_4() {
+        let mut system = SequentialOpticalSystem::default();
+        system.add_surface(surface(0., f64::INFINITY, Box::new(Air::default())));
+        system.add_surface(Box::new(BiconicSurface::new(40., 60., 5., glass())));
+        system.add_surface(Box::new(BiconicSurface::new(-80., -60., 40., Box::new(Air::default()))));
+        system.add_surface(surface(0., 0., Box::new(Air::default())));
+        let wavelength = system.primary_wavelength();
+
+        let matrix = system.transfer_matrix_4(1, 2, wavelength).unwrap();
+        assert_eq!(matrix.coupling(), 0.);
+        assert_approx_eq!(matrix.section(Section::Y).c, system.transfer_matrix(1, 2, wavelength).unwrap().c);
+        let x = matrix.section(Section::X);
+        assert_approx_eq!(x.c, -(0.5 / 40. + 0.5 / 80. - 0.5 * 0.5 * 5. / (1.5 * 40. * 80.)));
+
+        let real = system.differential_matrix(2).unwrap();
+        assert!(real.coupling() < 1e-9);
+        for section in [Section::X, Section::Y] {
+            assert_approx_eq!(real.section(section).c, matrix.section(section).c, 1e-8);
+            assert_approx_eq!(real.section(section).a, matrix.section(section).a, 1e-8);
+        }
+        let state = matrix.apply([1., 0., 1., 0.]);
+        assert_approx_eq!(state[1], x.c);
+    }
+}